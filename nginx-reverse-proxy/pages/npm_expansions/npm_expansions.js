@@ -13,10 +13,10 @@ async function loadExpansionIntoTextArea() {
 }
 
 async function searchExpansions(query) {
-    const searchExpansionResponse = await fetch(`/api/search?query=${query.target.value}`);
+    const searchExpansionResponse = await fetch(`/api/search?query=${encodeURIComponent(query.target.value)}`);
     const searchExpansionJSONResponse = await searchExpansionResponse.json();
     
-    const textareaString = searchExpansionJSONResponse.reduce((acc, expansion) => acc + (expansion + "\n\n"), "");
+    const textareaString = searchExpansionJSONResponse.reduce((acc, expansion) => acc + (expansion["npm-expansion"] + "\n\n"), "");
     const textarea = document.querySelector(".results-expansions-list");
     textarea.innerHTML = textareaString;
     textarea.setAttribute("rows", searchExpansionJSONResponse.length);
@@ -26,7 +26,7 @@ async function loadAllExpansions() {
     const allExpansionResponse = await fetch("/api/all");
     const allExpansionJSONResponse = await allExpansionResponse.json();
 
-    const textareaString = allExpansionJSONResponse.reduce((acc, expansion) => acc + (expansion + "\n\n"), "");
+    const textareaString = allExpansionJSONResponse.reduce((acc, expansion) => acc + (expansion["npm-expansion"] + "\n\n"), "");
     const textarea = document.querySelector(".results-expansions-list");
     textarea.innerHTML = textareaString;
     textarea.setAttribute("rows", allExpansionJSONResponse.length);
@@ -2,30 +2,149 @@
 
 extern crate test;
 use npm_expansions::expansions_model::ExpansionsModel;
+use once_cell::sync::Lazy;
+use std::fs;
+
+const SYNTHETIC_CORPUS_SIZE: usize = 100_000;
+
+/// A query for the synthetic corpus, i.e. its first expansion with a typo
+const SYNTHETIC_QUERY: &str = "Nopexa Pirudo Mavik";
+
+/// A synthetic corpus of 100,000 npm expansions made of pseudo-random words so that
+/// every run of the benches searches the same expansions
+static SYNTHETIC_EXPANSIONS: Lazy<ExpansionsModel> = Lazy::new(|| {
+    let mut seed: u64 = 246;
+    let mut contents = String::from("Nopexa Pirudo Mavike\n");
+
+    for _ in 1..SYNTHETIC_CORPUS_SIZE {
+        let n_word = synthetic_word('N', &mut seed);
+        let p_word = synthetic_word('P', &mut seed);
+        let m_word = synthetic_word('M', &mut seed);
+
+        contents.push_str(&format!("{n_word} {p_word} {m_word}\n"));
+    }
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    fs::write(file.path(), contents).unwrap();
+
+    ExpansionsModel::build(file.path().to_str().unwrap()).unwrap()
+});
+
+fn synthetic_word(first: char, seed: &mut u64) -> String {
+    let mut next = |bound: usize| {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (*seed >> 33) as usize % bound
+    };
+
+    let consonants = b"bcdfghjklmnprstvwxz";
+    let vowels = b"aeiou";
+    let length = 4 + next(5);
+    let mut word = first.to_string();
+
+    for position in 1..length {
+        let letters: &[u8] = if position % 2 == 1 {
+            vowels
+        } else {
+            consonants
+        };
+        word.push(letters[next(letters.len())] as char);
+    }
+
+    word
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use npm_expansions::expansions_model::ExpansionsAccess;
+    use npm_expansions::{
+        expansions_model::ExpansionsAccess,
+        search::{SearchAlgorithm, SearchOptions},
+    };
     use test::Bencher;
 
     #[bench]
     fn random_expansions(b: &mut Bencher) {
-        let expansions_generator = ExpansionsModel::build("rsc/expansions.txt");
+        let expansions_generator = ExpansionsModel::build("rsc/expansions.txt").unwrap();
         b.iter(|| expansions_generator.random_expansion());
     }
 
     #[bench]
     fn all_expansions(b: &mut Bencher) {
-        let expansions_generator = ExpansionsModel::build("rsc/expansions.txt");
+        let expansions_generator = ExpansionsModel::build("rsc/expansions.txt").unwrap();
 
         b.iter(|| expansions_generator.all());
     }
 
     #[bench]
     fn search_expansions(b: &mut Bencher) {
-        let expansions_generator = ExpansionsModel::build("rsc/expansions.txt");
+        let expansions_generator = ExpansionsModel::build("rsc/expansions.txt").unwrap();
+
+        b.iter(|| expansions_generator.search("Nacho Person Manager", &SearchOptions::default()));
+    }
+
+    fn search_with_algorithm(b: &mut Bencher, algorithm: SearchAlgorithm) {
+        let expansions_generator = ExpansionsModel::build("rsc/expansions.txt").unwrap();
+        let options = SearchOptions {
+            limit: Some(10),
+            algorithm: Some(algorithm),
+            ..SearchOptions::default()
+        };
+
+        b.iter(|| expansions_generator.search("Nacho Person Manager", &options));
+    }
+
+    #[bench]
+    fn search_jaro_winkler(b: &mut Bencher) {
+        search_with_algorithm(b, SearchAlgorithm::JaroWinkler);
+    }
+
+    #[bench]
+    fn search_levenshtein(b: &mut Bencher) {
+        search_with_algorithm(b, SearchAlgorithm::Levenshtein);
+    }
+
+    #[bench]
+    fn search_word_prefix(b: &mut Bencher) {
+        search_with_algorithm(b, SearchAlgorithm::WordPrefix);
+    }
+
+    #[bench]
+    fn search_substring(b: &mut Bencher) {
+        search_with_algorithm(b, SearchAlgorithm::Substring);
+    }
+
+    #[bench]
+    fn search_100k_indexed(b: &mut Bencher) {
+        let expansions_generator = &*SYNTHETIC_EXPANSIONS;
+        let options = SearchOptions {
+            limit: Some(10),
+            ..SearchOptions::default()
+        };
+
+        b.iter(|| expansions_generator.search(SYNTHETIC_QUERY, &options));
+    }
+
+    #[bench]
+    fn search_100k_full_scan(b: &mut Bencher) {
+        let expansions_generator = &*SYNTHETIC_EXPANSIONS;
+
+        b.iter(|| {
+            let mut scored_matches: Vec<(f64, &str)> = expansions_generator
+                .all()
+                .iter()
+                .map(|expansion| {
+                    (
+                        SearchAlgorithm::JaroWinkler.score(expansion.text(), SYNTHETIC_QUERY),
+                        expansion.text(),
+                    )
+                })
+                .collect();
 
-        b.iter(|| expansions_generator.search("Nacho Person Manager"));
+            scored_matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+            scored_matches.truncate(10);
+            scored_matches
+        });
     }
 }
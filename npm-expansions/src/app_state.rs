@@ -0,0 +1,130 @@
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+
+/// A container of shared values e.g. the expansions model or config, holding at most one value
+/// of each type, which the router gives to every handler so that a new subsystem can be made
+/// available to handlers without changing their signatures
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::app_state::AppState;
+/// use std::time::Duration;
+///
+/// struct Greeting(&'static str);
+///
+/// let state = AppState::new()
+///     .with(Greeting("Hello"))
+///     .with(Duration::from_secs(5));
+///
+/// assert_eq!(state.get::<Greeting>().unwrap().0, "Hello");
+/// assert_eq!(state.get::<Duration>(), Some(&Duration::from_secs(5)));
+/// assert!(state.get::<String>().is_none());
+/// ```
+#[derive(Default)]
+pub struct AppState {
+    values: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl AppState {
+    /// Creates an empty app state
+    pub fn new() -> AppState {
+        AppState::default()
+    }
+
+    /// Returns the app state holding the given value, replacing any value of the same type
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to share with handlers
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::app_state::AppState;
+    ///
+    /// let state = AppState::new().with(1_u32).with(2_u32);
+    ///
+    /// assert_eq!(state.get::<u32>(), Some(&2));
+    /// ```
+    pub fn with<T: Any + Send + Sync>(mut self, value: T) -> AppState {
+        self.values.insert(TypeId::of::<T>(), Box::new(value));
+        self
+    }
+
+    /// Returns the value of the given type if the app state holds one
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::app_state::AppState;
+    ///
+    /// let state = AppState::new().with("npm");
+    ///
+    /// assert_eq!(state.get::<&str>(), Some(&"npm"));
+    /// assert_eq!(state.get::<String>(), None);
+    /// ```
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+    }
+
+    /// Returns the value of the given type for handlers which cannot answer a request without it
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::app_state::AppState;
+    ///
+    /// let state = AppState::new().with(1_u32);
+    ///
+    /// assert_eq!(state.require::<u32>().unwrap(), &1);
+    /// assert!(state.require::<String>().is_err());
+    /// ```
+    ///
+    /// # Failures
+    ///
+    /// The function fails with an internal server error if the app state does not hold a value
+    /// of the type, as the server has been set up incorrectly
+    pub fn require<T: Any>(&self) -> Result<&T, NpmExpansionsError> {
+        self.get::<T>().ok_or(NpmExpansionsError::new(
+            NpmErrorKind::InternalServerError,
+            &format!("The app state does not hold a {}", type_name::<T>()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expansions_model::ExpansionsAccess;
+    use crate::mock_expansions_model::MockExpansionsModel;
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn holds_one_value_per_type() {
+        let state = AppState::new().with(1_u8).with(2_u16).with(3_u8);
+
+        assert_eq!(state.get::<u8>(), Some(&3));
+        assert_eq!(state.get::<u16>(), Some(&2));
+        assert_eq!(state.get::<u32>(), None);
+    }
+
+    #[test]
+    fn holds_trait_objects() {
+        let expansions_model: Arc<RwLock<dyn ExpansionsAccess>> =
+            Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let state = AppState::new().with(expansions_model);
+
+        assert!(state.require::<Arc<RwLock<dyn ExpansionsAccess>>>().is_ok());
+    }
+
+    #[test]
+    fn require_missing_value() {
+        let error = AppState::new().require::<u8>().unwrap_err();
+
+        assert!(matches!(error.kind(), NpmErrorKind::InternalServerError));
+    }
+}
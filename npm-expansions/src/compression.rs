@@ -0,0 +1,228 @@
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::Write;
+
+/// The quality brotli compresses with, a trade off between size and speed suited to responses
+/// compressed as they are sent
+const BROTLI_QUALITY: u32 = 5;
+
+/// The base two logarithm of the brotli window size
+const BROTLI_WINDOW: u32 = 22;
+
+/// The weight of identity when the header does not name it, the lowest q-value a coding may have
+const IDENTITY_FALLBACK_WEIGHT: f32 = 0.001;
+
+/// A content coding a response body may be sent with, listed in the order the server prefers them
+/// when a client accepts several equally
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContentEncoding {
+    /// Brotli compression, `br`
+    Brotli,
+    /// Gzip compression, `gzip`
+    Gzip,
+    /// Zlib compression, `deflate`
+    Deflate,
+    /// No compression, `identity`
+    Identity,
+}
+
+impl ContentEncoding {
+    pub(crate) const PREFERENCE: [ContentEncoding; 4] = [
+        ContentEncoding::Brotli,
+        ContentEncoding::Gzip,
+        ContentEncoding::Deflate,
+        ContentEncoding::Identity,
+    ];
+
+    /// Returns the name of the coding used by the `Accept-Encoding` and `Content-Encoding`
+    /// headers e.g. `gzip`
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+            ContentEncoding::Identity => "identity",
+        }
+    }
+
+    /// Returns the content coding most preferred by an `Accept-Encoding` header. Codings are
+    /// chosen by their q-value and then by the server's preference of brotli, gzip, deflate and
+    /// identity. A `*` gives its q-value to every coding the header does not name and identity is
+    /// the fallback unless it is given, or `*` gives it, a q-value of 0. A request without the header
+    /// accepts any coding, but as older clients often omit it the body is sent as is.
+    ///
+    /// # Arguments
+    ///
+    /// * `accept_encoding` - The `Accept-Encoding` header of a request, if it has one
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::compression::ContentEncoding;
+    ///
+    /// assert_eq!(
+    ///     ContentEncoding::negotiate(Some("gzip;q=0.8, br;q=0.5, deflate")),
+    ///     Some(ContentEncoding::Deflate)
+    /// );
+    /// assert_eq!(ContentEncoding::negotiate(Some("gzip, br")), Some(ContentEncoding::Brotli));
+    /// assert_eq!(ContentEncoding::negotiate(None), Some(ContentEncoding::Identity));
+    /// assert_eq!(ContentEncoding::negotiate(Some("identity;q=0")), None);
+    /// ```
+    pub fn negotiate(accept_encoding: Option<&str>) -> Option<ContentEncoding> {
+        let Some(accept_encoding) = accept_encoding else {
+            return Some(ContentEncoding::Identity);
+        };
+
+        let weights: Vec<(&str, f32)> = accept_encoding
+            .split(',')
+            .filter_map(parse_weighted_coding)
+            .collect();
+        let weight_of = |name: &str| {
+            weights
+                .iter()
+                .find(|(coding, _)| coding.eq_ignore_ascii_case(name))
+                .map(|(_, weight)| *weight)
+        };
+        let wildcard = weight_of("*");
+
+        let mut best: Option<(ContentEncoding, f32)> = None;
+
+        for encoding in ContentEncoding::PREFERENCE {
+            let weight = match (weight_of(encoding.name()), wildcard, encoding) {
+                (Some(weight), _, _) => weight,
+                (None, Some(weight), _) => weight,
+                // Identity stays acceptable without being chosen over a coding the header asks for
+                (None, None, ContentEncoding::Identity) => IDENTITY_FALLBACK_WEIGHT,
+                (None, None, _) => 0.0,
+            };
+
+            if weight > 0.0 && best.is_none_or(|(_, best_weight)| weight > best_weight) {
+                best = Some((encoding, weight));
+            }
+        }
+
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Returns the bytes encoded with the coding
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes to encode e.g. a response body
+    ///
+    /// # Failures
+    ///
+    /// The function fails with an internal server error if the encoder fails
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::compression::ContentEncoding;
+    ///
+    /// let body = "Nice People Meet ".repeat(100);
+    /// let encoded = ContentEncoding::Gzip.encode(body.as_bytes()).unwrap();
+    ///
+    /// assert!(encoded.len() < body.len());
+    /// assert_eq!(ContentEncoding::Identity.encode(b"npm").unwrap(), b"npm");
+    /// ```
+    pub fn encode(self, bytes: &[u8]) -> Result<Vec<u8>, NpmExpansionsError> {
+        let encoded = match self {
+            ContentEncoding::Brotli => {
+                let mut encoder =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+
+                encoder.write_all(bytes).map(|_| encoder.into_inner())
+            }
+            ContentEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+                encoder.write_all(bytes).and_then(|_| encoder.finish())
+            }
+            ContentEncoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+                encoder.write_all(bytes).and_then(|_| encoder.finish())
+            }
+            ContentEncoding::Identity => Ok(bytes.to_vec()),
+        };
+
+        encoded.map_err(|error| {
+            NpmExpansionsError::new(NpmErrorKind::InternalServerError, &error.to_string())
+        })
+    }
+}
+
+/// Parses one coding of an `Accept-Encoding` header e.g. `gzip;q=0.5` into its name and q-value,
+/// leaving out codings with an invalid q-value
+fn parse_weighted_coding(coding: &str) -> Option<(&str, f32)> {
+    let mut parts = coding.split(';').map(str::trim);
+    let name = parts.next().filter(|name| !name.is_empty())?;
+
+    let mut weight = 1.0;
+
+    for param in parts {
+        if let Some((key, value)) = param.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("q") {
+                weight = value
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|weight| (0.0..=1.0).contains(weight))?;
+            }
+        }
+    }
+
+    Some((name, weight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+    use test_case::test_case;
+
+    #[test_case(Some("gzip"), Some(ContentEncoding::Gzip); "single coding")]
+    #[test_case(Some("GZIP"), Some(ContentEncoding::Gzip); "upper case")]
+    #[test_case(Some("deflate, gzip, br"), Some(ContentEncoding::Brotli); "server preference")]
+    #[test_case(Some("br;q=0.5, gzip;q=0.9"), Some(ContentEncoding::Gzip); "q-values")]
+    #[test_case(Some("br;q=0, gzip;q=0"), Some(ContentEncoding::Identity); "rejected codings")]
+    #[test_case(Some("*"), Some(ContentEncoding::Brotli); "wildcard")]
+    #[test_case(Some("*;q=0.5, gzip"), Some(ContentEncoding::Gzip); "named over wildcard")]
+    #[test_case(Some("identity;q=0, *;q=0"), None; "nothing acceptable")]
+    #[test_case(Some("*;q=0"), None; "wildcard rejects identity")]
+    #[test_case(Some("compress"), Some(ContentEncoding::Identity); "unknown coding")]
+    #[test_case(Some("gzip;q=2, deflate;q=abc"), Some(ContentEncoding::Identity); "invalid q-values")]
+    #[test_case(Some(""), Some(ContentEncoding::Identity); "empty header")]
+    #[test_case(None, Some(ContentEncoding::Identity); "no header")]
+    fn negotiate(accept_encoding: Option<&str>, expected: Option<ContentEncoding>) {
+        assert_eq!(ContentEncoding::negotiate(accept_encoding), expected)
+    }
+
+    #[test_case(ContentEncoding::Brotli)]
+    #[test_case(ContentEncoding::Gzip)]
+    #[test_case(ContentEncoding::Deflate)]
+    #[test_case(ContentEncoding::Identity)]
+    fn encode_round_trips(encoding: ContentEncoding) {
+        let body = r#"["Nice People Meet","Nacho Pizza Marinade"]"#.repeat(50);
+        let encoded = encoding.encode(body.as_bytes()).unwrap();
+
+        let mut decoded = String::new();
+        match encoding {
+            ContentEncoding::Brotli => {
+                brotli::Decompressor::new(encoded.as_slice(), 4096).read_to_string(&mut decoded)
+            }
+            ContentEncoding::Gzip => {
+                GzDecoder::new(encoded.as_slice()).read_to_string(&mut decoded)
+            }
+            ContentEncoding::Deflate => {
+                ZlibDecoder::new(encoded.as_slice()).read_to_string(&mut decoded)
+            }
+            ContentEncoding::Identity => encoded.as_slice().read_to_string(&mut decoded),
+        }
+        .unwrap();
+
+        assert_eq!(decoded, body);
+    }
+}
@@ -1,278 +1,179 @@
-use crate::http_request::HttpRequest;
 use crate::http_response::HttpResponse;
-use crate::mime_type::matcher;
-use crate::npm_expansion_error::NpmExpansionsError;
+use crate::http_status::HttpStatus;
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
 
-/// A struct representing a series of functions to respond to HTTP errors e.g. 400, 500, 404 etc
+/// A struct representing a series of functions to respond to HTTP errors e.g. 400, 500, 404 etc.
+/// The responses are plain text whatever the request accepts, as the status code is what matters
+/// to a client and the `AcceptNegotiation` middleware rejects unacceptable requests before routing.
 pub struct DefaultController {}
 
 impl DefaultController {
     /// Returns a vector byte representation of a 404 response. The response body is a plain string of "NOT FOUND".
     ///
-    /// # Arguments
-    ///
-    /// * `request` - An incoming HTTP request
-    ///
     /// # Examples
     ///
     /// ```
-    /// use npm_expansions::{
-    ///     default_controller::DefaultController,
-    ///     http_request::HttpRequest
-    /// };
-    /// use std::collections::HashMap;
+    /// use npm_expansions::default_controller::DefaultController;
     ///
-    /// let request = HttpRequest::new("127.0.0.1", "GET /non-existant/route HTTP/1.1", HashMap::from([("Accept".to_string(), "application/json".to_string())]), HashMap::new());
-    /// let response = DefaultController::not_found(&request);
+    /// let response = DefaultController::not_found();
     ///
-    /// assert!(response.is_ok());
+    /// assert_eq!(response.status_code(), 404);
     /// ```
+    pub fn not_found() -> HttpResponse {
+        HttpResponse::new(HttpStatus::NotFound).text("NOT FOUND")
+    }
+
+    /// Returns a vector byte representation of a 500 response. The response body is a plain string of "INTERNAL SERVER ERROR".
     ///
-    /// # Failures
-    ///
-    /// The function fails if the given request has invalid headers
+    /// # Examples
     ///
-    /// ```rust,should_error
-    /// // fails if the given request has invalid headers
-    /// use npm_expansions::{
-    ///     default_controller::DefaultController,
-    ///     http_request::HttpRequest,
-    /// };
-    /// use std::collections::HashMap;
+    /// ```
+    /// use npm_expansions::default_controller::DefaultController;
     ///
-    /// let request = HttpRequest::new("127.0.0.1", "GET /non-existant/route HTTP/1.1", HashMap::from([("Accept".to_string(), "text/".to_string())]), HashMap::new());
+    /// let response = DefaultController::internal_server_error();
     ///
-    /// DefaultController::not_found(&request);
+    /// assert_eq!(response.status_code(), 500);
     /// ```
-    pub fn not_found(request: &HttpRequest) -> Result<HttpResponse, NpmExpansionsError> {
-        let headers = request.headers();
-        let accept_header = headers.get("Accept").or_else(|| headers.get("accept"));
-        let best = matcher::best_match(
-            Vec::from(["text/plain", "*/*"]),
-            accept_header.unwrap_or(&"".to_string()),
-        )?;
-
-        let response = if best.is_empty() {
-            not_acceptable_response()
-        } else {
-            HttpResponse::new("404", "NOT FOUND", "", "NOT FOUND")
-        };
-
-        Ok(response)
+    pub fn internal_server_error() -> HttpResponse {
+        HttpResponse::new(HttpStatus::InternalServerError).text("INTERNAL SERVER ERROR")
     }
 
-    /// Returns a vector byte representation of a 500 response. The response body is a plain string of "INTERNAL SERVER ERROR".
-    ///
-    /// # Arguments
-    ///
-    /// * `request` - An incoming HTTP request
+    /// Returns a vector byte representation of a 400 response. The response body is a plain string of "BAD REQUEST".
     ///
     /// # Examples
     ///
     /// ```
-    /// use npm_expansions::{
-    ///     default_controller::DefaultController,
-    ///     http_request::HttpRequest,
-    /// };
-    /// use std::collections::HashMap;
+    /// use npm_expansions::default_controller::DefaultController;
     ///
-    /// let request = HttpRequest::new("127.0.0.1", "GET /non-existant/route HTTP/1.1", HashMap::from([("Accept".to_string(), "application/json".to_string())]), HashMap::new());
-    /// let response = DefaultController::internal_server_error(&request);
+    /// let response = DefaultController::client_error();
     ///
-    /// assert!(response.is_ok());
+    /// assert_eq!(response.status_code(), 400);
     /// ```
+    pub fn client_error() -> HttpResponse {
+        HttpResponse::new(HttpStatus::BadRequest).text("BAD REQUEST")
+    }
+
+    /// Returns a vector byte representation of a 413 response. The response body is a plain string of "PAYLOAD TOO LARGE".
     ///
-    /// # Failures
-    ///
-    /// The function fails if the given request has invalid headers
+    /// # Examples
     ///
-    /// ```rust,should_error
-    /// // fails if the given request has invalid headers
-    /// use npm_expansions::{
-    ///     default_controller::DefaultController,
-    ///     http_request::HttpRequest,
-    /// };
-    /// use std::collections::HashMap;
+    /// ```
+    /// use npm_expansions::default_controller::DefaultController;
     ///
-    /// let request = HttpRequest::new("127.0.0.1", "GET / HTTP/1.1", HashMap::from([("Accept".to_string(), "text/".to_string())]), HashMap::new());
+    /// let response = DefaultController::payload_too_large();
     ///
-    /// DefaultController::internal_server_error(&request);
+    /// assert_eq!(response.status_code(), 413);
     /// ```
-    pub fn internal_server_error(
-        request: &HttpRequest,
-    ) -> Result<HttpResponse, NpmExpansionsError> {
-        let headers = request.headers();
-        let accept_header = headers.get("Accept").or_else(|| headers.get("accept"));
-        let best = matcher::best_match(
-            Vec::from(["text/plain", "*/*"]),
-            accept_header.unwrap_or(&"".to_string()),
-        )?;
-
-        let response = if best.is_empty() {
-            not_acceptable_response()
-        } else {
-            HttpResponse::new("500", "INTERNAL SERVER ERROR", "", "INTERNAL SERVER ERROR")
-        };
-
-        Ok(response)
+    pub fn payload_too_large() -> HttpResponse {
+        HttpResponse::new(HttpStatus::PayloadTooLarge).text("PAYLOAD TOO LARGE")
     }
 
-    /// Returns a vector byte representation of a 400 response. The response body is a plain string of "BAD REQUEST".
+    /// Returns a vector byte representation of a 408 response. The response body is a plain string of "REQUEST TIMEOUT".
     ///
-    /// # Arguments
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::default_controller::DefaultController;
     ///
-    /// * `request` - An incoming HTTP request
+    /// let response = DefaultController::request_timeout();
+    ///
+    /// assert_eq!(response.status_code(), 408);
+    /// ```
+    pub fn request_timeout() -> HttpResponse {
+        HttpResponse::new(HttpStatus::RequestTimeout).text("REQUEST TIMEOUT")
+    }
+
+    /// Returns a vector byte representation of a 405 response. The response body is a plain string of "METHOD NOT ALLOWED".
     ///
     /// # Examples
     ///
     /// ```
-    /// use npm_expansions::{
-    ///     default_controller::DefaultController,
-    ///     http_request::HttpRequest,
-    /// };
-    /// use std::collections::HashMap;
+    /// use npm_expansions::default_controller::DefaultController;
     ///
-    /// let request = HttpRequest::new("127.0.0.1", "GET /non-existant/route HTTP/1.1", HashMap::from([("Accept".to_string(), "application/json".to_string())]), HashMap::new());
-    /// let response = DefaultController::client_error(&request);
+    /// let response = DefaultController::method_not_allowed();
     ///
-    /// assert!(response.is_ok());
+    /// assert_eq!(response.status_code(), 405);
     /// ```
+    pub fn method_not_allowed() -> HttpResponse {
+        HttpResponse::new(HttpStatus::MethodNotAllowed).text("METHOD NOT ALLOWED")
+    }
+
+    /// Returns the response for an error produced while reading or routing a request e.g. a 413
+    /// response for a `PayloadTooLarge` error or a 404 response for a `RouteNotFound` error
     ///
-    /// # Failures
+    /// # Arguments
     ///
-    /// The function fails if the given request has invalid headers
+    /// * `error` - The error produced while handling the request
     ///
-    /// ```rust,should_error
-    /// // fails if the given request has invalid headers
+    /// # Examples
+    ///
+    /// ```
     /// use npm_expansions::{
     ///     default_controller::DefaultController,
-    ///     http_request::HttpRequest,
+    ///     npm_expansion_error::{NpmErrorKind, NpmExpansionsError},
     /// };
-    /// use std::collections::HashMap;
     ///
-    /// let request = HttpRequest::new("127.0.0.1", "GET / HTTP/1.1", HashMap::from([("Accept".to_string(), "text/".to_string())]), HashMap::new());
+    /// let error = NpmExpansionsError::from(NpmErrorKind::PayloadTooLarge);
+    /// let response = DefaultController::from_error(&error);
     ///
-    /// DefaultController::client_error(&request);
+    /// assert_eq!(response.status_code(), 413);
     /// ```
-    pub fn client_error(request: &HttpRequest) -> Result<HttpResponse, NpmExpansionsError> {
-        let headers = request.headers();
-        let accept_header = headers.get("Accept").or_else(|| headers.get("accept"));
-        let best = matcher::best_match(
-            Vec::from(["text/plain", "*/*"]),
-            accept_header.unwrap_or(&"".to_string()),
-        )?;
-
-        let response = if best.is_empty() {
-            not_acceptable_response()
-        } else {
-            HttpResponse::new("400", "BAD REQUEST", "", "BAD REQUEST")
-        };
-
-        Ok(response)
+    pub fn from_error(error: &NpmExpansionsError) -> HttpResponse {
+        match error.kind() {
+            NpmErrorKind::InvalidRequestHeaders => DefaultController::client_error(),
+            NpmErrorKind::TooManyRequestHeaders => DefaultController::client_error(),
+            NpmErrorKind::InternalServerError => DefaultController::internal_server_error(),
+            NpmErrorKind::InvalidHttpRequest => DefaultController::client_error(),
+            NpmErrorKind::SupportedMimeTypeError => DefaultController::internal_server_error(),
+            NpmErrorKind::InvalidRequestMimeType => DefaultController::client_error(),
+            NpmErrorKind::RouteNotFound => DefaultController::not_found(),
+            NpmErrorKind::InvalidExpansion => DefaultController::client_error(),
+            NpmErrorKind::DuplicateExpansion => DefaultController::client_error(),
+            NpmErrorKind::ExpansionsLoadError => DefaultController::internal_server_error(),
+            NpmErrorKind::InvalidQueryParam => DefaultController::client_error(),
+            NpmErrorKind::PayloadTooLarge => DefaultController::payload_too_large(),
+            NpmErrorKind::RequestTimeout => DefaultController::request_timeout(),
+        }
     }
 }
 
-fn not_acceptable_response() -> HttpResponse {
-    HttpResponse::new(
-        "406",
-        "NOT ACCEPTABLE",
-        "",
-        "Please accept application/json",
-    )
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
     use test_case::test_case;
 
     #[test_case(DefaultController::not_found; "not_found")]
     #[test_case(DefaultController::internal_server_error; "internal_server_error")]
     #[test_case(DefaultController::client_error; "client_error")]
-    fn valid_request(
-        controller_function: fn(&HttpRequest) -> Result<HttpResponse, NpmExpansionsError>,
-    ) {
-        let request = HttpRequest::new(
-            "127.0.0.1",
-            "GET / HTTP/1.1",
-            HashMap::from([("Accept".to_string(), "text/html".to_string())]),
-            HashMap::new(),
-        );
-
-        assert!(controller_function(&request).is_ok())
+    #[test_case(DefaultController::payload_too_large; "payload_too_large")]
+    #[test_case(DefaultController::request_timeout; "request_timeout")]
+    #[test_case(DefaultController::method_not_allowed; "method_not_allowed")]
+    #[allow(clippy::len_zero)]
+    fn valid_request_returns_content(controller_function: fn() -> HttpResponse) {
+        assert!(controller_function().into_bytes_vec().len() > 0)
     }
 
-    #[test_case(DefaultController::not_found; "not_found")]
-    #[test_case(DefaultController::internal_server_error; "internal_server_error")]
-    #[test_case(DefaultController::client_error; "client_error")]
-
-    fn valid_request_returns_content(
-        controller_function: fn(&HttpRequest) -> Result<HttpResponse, NpmExpansionsError>,
-    ) {
-        let request = HttpRequest::new(
-            "127.0.0.1",
-            "GET / HTTP/1.1",
-            HashMap::from([("Accept".to_string(), "text/html".to_string())]),
-            HashMap::new(),
-        );
-
-        assert!(
-            controller_function(&request)
-                .unwrap()
-                .into_bytes_vec()
-                .len()
-                > 0
-        )
-    }
-
-    #[test_case(DefaultController::not_found; "not_found")]
-    #[test_case(DefaultController::internal_server_error; "internal_server_error")]
-    #[test_case(DefaultController::client_error; "client_error")]
-    fn invalid_request_headers(
-        controller_function: fn(&HttpRequest) -> Result<HttpResponse, NpmExpansionsError>,
-    ) {
-        let request = HttpRequest::new(
-            "127.0.0.1",
-            "GET / HTTP/1.1",
-            HashMap::from([("Accept".to_string(), "text/".to_string())]),
-            HashMap::new(),
-        );
-
-        assert!(controller_function(&request).is_err())
-    }
-
-    #[test_case(DefaultController::not_found; "not_found")]
-    #[test_case(DefaultController::internal_server_error; "internal_server_error")]
-    #[test_case(DefaultController::client_error; "client_error")]
-
-    fn lower_case_accept_header(
-        controller_function: fn(&HttpRequest) -> Result<HttpResponse, NpmExpansionsError>,
-    ) {
-        let request = HttpRequest::new(
-            "127.0.0.1",
-            "GET / HTTP/1.1",
-            HashMap::from([("accept".to_string(), "text/html".to_string())]),
-            HashMap::new(),
-        );
-
-        assert!(controller_function(&request).is_ok())
+    #[test_case(DefaultController::not_found, 404; "not_found")]
+    #[test_case(DefaultController::internal_server_error, 500; "internal_server_error")]
+    #[test_case(DefaultController::client_error, 400; "client_error")]
+    #[test_case(DefaultController::payload_too_large, 413; "payload_too_large")]
+    #[test_case(DefaultController::request_timeout, 408; "request_timeout")]
+    #[test_case(DefaultController::method_not_allowed, 405; "method_not_allowed")]
+    fn returns_status(controller_function: fn() -> HttpResponse, status_code: u16) {
+        assert_eq!(controller_function().status_code(), status_code)
     }
 
-    #[test_case(DefaultController::not_found; "not_found")]
-    #[test_case(DefaultController::internal_server_error; "internal_server_error")]
-    #[test_case(DefaultController::client_error; "client_error")]
-
-    fn no_accept_header(
-        controller_function: fn(&HttpRequest) -> Result<HttpResponse, NpmExpansionsError>,
-    ) {
-        let request = HttpRequest::new(
-            "127.0.0.1",
-            "GET / HTTP/1.1",
-            HashMap::new(),
-            HashMap::new(),
-        );
-
-        assert!(controller_function(&request).is_ok())
+    #[test_case(NpmErrorKind::InvalidRequestHeaders, 400; "invalid request headers")]
+    #[test_case(NpmErrorKind::InvalidHttpRequest, 400; "invalid http request")]
+    #[test_case(NpmErrorKind::RouteNotFound, 404; "route not found")]
+    #[test_case(NpmErrorKind::PayloadTooLarge, 413; "payload too large")]
+    #[test_case(NpmErrorKind::RequestTimeout, 408; "request timeout")]
+    fn from_error(kind: NpmErrorKind, status_code: u16) {
+        let error = NpmExpansionsError::from(kind);
+
+        assert_eq!(
+            DefaultController::from_error(&error).status_code(),
+            status_code
+        )
     }
 }
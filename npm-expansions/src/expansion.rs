@@ -0,0 +1,187 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// A npm expansion record consisting of its text and the metadata describing it
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::expansion::Expansion;
+/// use chrono::NaiveDate;
+///
+/// let expansion = Expansion::new("Nacho Pizza Marinade")
+///     .with_author("hiccup246")
+///     .with_tags(vec!["food".to_string()])
+///     .with_date_added(NaiveDate::from_ymd_opt(2023, 3, 25).unwrap());
+///
+/// assert_eq!(expansion.text(), "Nacho Pizza Marinade");
+/// assert_eq!(expansion.author(), Some("hiccup246"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Expansion {
+    #[serde(default)]
+    id: String,
+    expansion: String,
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    date_added: Option<NaiveDate>,
+}
+
+impl Expansion {
+    /// Creates a new expansion record with the given text and no metadata
+    pub fn new(text: &str) -> Expansion {
+        Expansion {
+            id: String::new(),
+            expansion: text.to_string(),
+            author: None,
+            tags: Vec::new(),
+            date_added: None,
+        }
+    }
+
+    /// Returns the expansion with its author or contributor set
+    pub fn with_author(mut self, author: &str) -> Expansion {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Returns the expansion with its tags set e.g. "food", "programming" or "non-English"
+    pub fn with_tags(mut self, tags: Vec<String>) -> Expansion {
+        self.tags = tags;
+        self
+    }
+
+    /// Returns the expansion with the date it was added set
+    pub fn with_date_added(mut self, date_added: NaiveDate) -> Expansion {
+        self.date_added = Some(date_added);
+        self
+    }
+
+    /// Returns the id of the expansion
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the text of the expansion e.g. "Nacho Pizza Marinade"
+    pub fn text(&self) -> &str {
+        &self.expansion
+    }
+
+    /// Returns the author or contributor of the expansion if known
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    /// Returns the tags of the expansion
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns the date the expansion was added if known
+    pub fn date_added(&self) -> Option<NaiveDate> {
+        self.date_added
+    }
+
+    /// Sets the id of the expansion to its content id if it does not already have one
+    pub(crate) fn ensure_id(&mut self) {
+        if self.id.is_empty() {
+            self.id = content_id(&self.expansion);
+        }
+    }
+
+    pub(crate) fn set_id(&mut self, id: &str) {
+        self.id = id.to_string();
+    }
+
+    pub(crate) fn set_text(&mut self, text: &str) {
+        self.expansion = text.to_string();
+    }
+}
+
+/// Returns a stable id for the given expansion text. The id is the 64 bit FNV-1a hash of the
+/// trimmed, ASCII lowercased text written as 16 hex digits, so it does not change between
+/// restarts, reloads or the order of an expansions file.
+///
+/// # Arguments
+///
+/// * `text` - The text of a npm expansion
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::expansion::content_id;
+///
+/// assert_eq!(content_id("Nacho Pizza Marinade"), content_id(" nacho pizza marinade "));
+/// assert_eq!(content_id("Nacho Pizza Marinade").len(), 16);
+/// ```
+pub fn content_id(text: &str) -> String {
+    format!(
+        "{:016x}",
+        fnv1a(text.trim().to_ascii_lowercase().as_bytes())
+    )
+}
+
+/// Returns the 64 bit FNV-1a hash of the given bytes, which unlike the standard library hasher
+/// is the same on every build and platform
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_with_metadata() {
+        let expansion: Expansion = serde_json::from_str(
+            r#"{"id": "1", "expansion": "Nadie Programa más", "author": "hiccup246", "tags": ["non-English"], "date_added": "2023-03-25"}"#,
+        )
+        .unwrap();
+        let mut expected = Expansion::new("Nadie Programa más")
+            .with_author("hiccup246")
+            .with_tags(vec!["non-English".to_string()])
+            .with_date_added(NaiveDate::from_ymd_opt(2023, 3, 25).unwrap());
+        expected.set_id("1");
+
+        assert_eq!(expansion, expected)
+    }
+
+    #[test]
+    fn deserialize_without_metadata() {
+        let expansion: Expansion =
+            serde_json::from_str(r#"{"expansion": "Nacho Pizza Marinade"}"#).unwrap();
+
+        assert_eq!(expansion, Expansion::new("Nacho Pizza Marinade"))
+    }
+
+    #[test]
+    fn serialize() {
+        let expansion = Expansion::new("Nacho Pizza Marinade").with_tags(vec!["food".to_string()]);
+
+        assert_eq!(
+            serde_json::to_string(&expansion).unwrap(),
+            r#"{"id":"","expansion":"Nacho Pizza Marinade","author":null,"tags":["food"],"date_added":null}"#
+        )
+    }
+
+    #[test]
+    fn content_id_is_stable() {
+        assert_eq!(content_id("Nadie Programa más"), "5742046512790417")
+    }
+
+    #[test]
+    fn content_id_differs_between_expansions() {
+        assert_ne!(
+            content_id("Nacho Pizza Marinade"),
+            content_id("Nacho Portion Monitor")
+        )
+    }
+}
@@ -0,0 +1,534 @@
+use crate::expansion::Expansion;
+use std::{collections::HashSet, fmt};
+
+/// Options controlling how forgiving expansion validation is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValidationOptions {
+    /// Accept words which begin with a symbol such as "*nix" by ignoring the symbol
+    pub allow_symbol_prefixes: bool,
+    /// Accept words which contain digits such as "N00b"
+    pub allow_digits: bool,
+    /// Refuse to load an expansions file containing any rejected lines
+    pub strict: bool,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions {
+            allow_symbol_prefixes: true,
+            allow_digits: true,
+            strict: false,
+        }
+    }
+}
+
+/// Enum for the different reasons an expansion can be rejected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RejectionReason {
+    /// The initials of the expansion do not spell out N, P, M
+    NotNpmAcronym,
+    /// A word begins with a symbol and symbol prefixes are not allowed
+    SymbolPrefix,
+    /// A word contains digits and digits are not allowed
+    ContainsDigits,
+    /// The expansion appears earlier in the same file
+    Duplicate,
+    /// The id of the expansion record is used by an earlier record in the same file
+    DuplicateId,
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            RejectionReason::NotNpmAcronym => "does not spell out N-P-M",
+            RejectionReason::SymbolPrefix => "contains a word starting with a symbol",
+            RejectionReason::ContainsDigits => "contains a word with digits",
+            RejectionReason::Duplicate => "is a duplicate",
+            RejectionReason::DuplicateId => "has the same id as an earlier expansion",
+        };
+
+        write!(f, "{reason}")
+    }
+}
+
+/// A line or record of an expansions file which failed validation
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedLine {
+    /// The line number or record position starting from 1
+    pub line_number: usize,
+    /// The contents of the line or the text of the record
+    pub line: String,
+    /// Why the line was rejected
+    pub reason: RejectionReason,
+}
+
+/// A report of every line of an expansions file which failed validation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    rejected_lines: Vec<RejectedLine>,
+}
+
+impl ValidationReport {
+    /// Returns the lines which failed validation in file order
+    pub fn rejected_lines(&self) -> &[RejectedLine] {
+        &self.rejected_lines
+    }
+
+    /// Returns whether no lines failed validation
+    pub fn is_valid(&self) -> bool {
+        self.rejected_lines.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} rejected line(s)", self.rejected_lines.len())?;
+
+        for rejected in &self.rejected_lines {
+            write!(
+                f,
+                "\nline {}: \"{}\" {}",
+                rejected.line_number, rejected.line, rejected.reason
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns whether the given expansion spells out the NPM acronym using the default validation options
+///
+/// # Arguments
+///
+/// * `expansion` - A string slice representing a candidate npm expansion
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::expansion_validator::is_npm_expansion;
+///
+/// assert!(is_npm_expansion("Nectar of the Programming Masses"));
+/// assert!(!is_npm_expansion("Nucleophosmin"));
+/// ```
+pub fn is_npm_expansion(expansion: &str) -> bool {
+    check_expansion(expansion, &ValidationOptions::default()).is_ok()
+}
+
+/// Checks whether the given expansion spells out the NPM acronym. The first word and any
+/// following capitalised words must begin with N, P and M in that order. Lower case connecting
+/// words such as "and" or "of" are skipped unless the whole expansion is lower case, and
+/// hyphenated words may count as either one word or several.
+///
+/// # Arguments
+///
+/// * `expansion` - A string slice representing a candidate npm expansion
+/// * `options` - How forgiving to be of unusual words
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::expansion_validator::{check_expansion, RejectionReason, ValidationOptions};
+///
+/// let options = ValidationOptions {
+///     allow_digits: false,
+///     ..ValidationOptions::default()
+/// };
+///
+/// assert!(check_expansion("*nix Programming Mammals", &options).is_ok());
+/// assert_eq!(check_expansion("N00b Pwn M3", &options), Err(RejectionReason::ContainsDigits));
+/// ```
+///
+/// # Failures
+///
+/// The function fails with the reason for rejection if the expansion is invalid
+///
+/// ```rust,should_error
+/// // fails if the expansion does not spell out N-P-M
+/// use npm_expansions::expansion_validator::{check_expansion, ValidationOptions};
+///
+/// check_expansion("Nucleophosmin", &ValidationOptions::default());
+/// ```
+pub fn check_expansion(
+    expansion: &str,
+    options: &ValidationOptions,
+) -> Result<(), RejectionReason> {
+    let hyphenated_words = words(expansion, options, |c| c.is_whitespace() || c == '/')?;
+    let split_words = words(expansion, options, |c| {
+        c.is_whitespace() || c == '/' || c == '-'
+    })?;
+
+    if spells_npm(&hyphenated_words) || spells_npm(&split_words) {
+        Ok(())
+    } else {
+        Err(RejectionReason::NotNpmAcronym)
+    }
+}
+
+/// Validates the contents of an expansions txt file returning the accepted expansions and a report
+/// of every rejected line. Blank lines and comments i.e. lines starting with a # or a * followed
+/// by a non alphanumeric character are skipped and surrounding whitespace is trimmed.
+///
+/// # Arguments
+///
+/// * `contents` - The contents of an expansions file
+/// * `options` - How forgiving to be of unusual words
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::expansion_validator::{validate_expansions, ValidationOptions};
+///
+/// let (expansions, report) = validate_expansions(
+///     "# comment\n Nacho Pizza Marinade \nNucleophosmin\n",
+///     &ValidationOptions::default(),
+/// );
+///
+/// assert_eq!(expansions[0].text(), "Nacho Pizza Marinade");
+/// assert_eq!(report.rejected_lines()[0].line_number, 3);
+/// ```
+pub fn validate_expansions(
+    contents: &str,
+    options: &ValidationOptions,
+) -> (Vec<Expansion>, ValidationReport) {
+    let numbered_expansions = contents
+        .lines()
+        .enumerate()
+        .filter(|(_index, line)| !line.trim().is_empty() && !is_comment(line.trim()))
+        .map(|(index, line)| (index + 1, Expansion::new(line)));
+
+    validate_numbered(numbered_expansions, options)
+}
+
+/// Validates a list of expansion records returning the accepted records and a report of every
+/// rejected record. The line number of a rejected record is its position in the list starting from 1.
+///
+/// # Arguments
+///
+/// * `records` - A list of expansion records e.g. parsed from a JSON expansions file
+/// * `options` - How forgiving to be of unusual words
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{
+///     expansion::Expansion,
+///     expansion_validator::{validate_records, ValidationOptions},
+/// };
+///
+/// let (expansions, report) = validate_records(
+///     vec![Expansion::new("Nacho Pizza Marinade"), Expansion::new("Nucleophosmin")],
+///     &ValidationOptions::default(),
+/// );
+///
+/// assert_eq!(expansions.len(), 1);
+/// assert_eq!(report.rejected_lines()[0].line_number, 2);
+/// ```
+pub fn validate_records(
+    records: Vec<Expansion>,
+    options: &ValidationOptions,
+) -> (Vec<Expansion>, ValidationReport) {
+    validate_numbered(
+        records
+            .into_iter()
+            .enumerate()
+            .map(|(index, record)| (index + 1, record)),
+        options,
+    )
+}
+
+fn validate_numbered(
+    numbered_expansions: impl Iterator<Item = (usize, Expansion)>,
+    options: &ValidationOptions,
+) -> (Vec<Expansion>, ValidationReport) {
+    let mut expansions: Vec<Expansion> = Vec::new();
+    let mut report = ValidationReport::default();
+    let mut seen_texts: HashSet<String> = HashSet::new();
+    let mut seen_ids: HashSet<String> = HashSet::new();
+
+    for (line_number, mut expansion) in numbered_expansions {
+        let line = expansion.text().to_string();
+        let text = line.trim();
+
+        expansion.set_text(text);
+        expansion.ensure_id();
+
+        let lowercase_text = text.to_ascii_lowercase();
+
+        let result = check_expansion(text, options).and_then(|_| {
+            if seen_texts.contains(&lowercase_text) {
+                Err(RejectionReason::Duplicate)
+            } else if seen_ids.contains(expansion.id()) {
+                Err(RejectionReason::DuplicateId)
+            } else {
+                Ok(())
+            }
+        });
+
+        match result {
+            Ok(()) => {
+                seen_texts.insert(lowercase_text);
+                seen_ids.insert(expansion.id().to_string());
+                expansions.push(expansion)
+            }
+            Err(reason) => report.rejected_lines.push(RejectedLine {
+                line_number,
+                line,
+                reason,
+            }),
+        }
+    }
+
+    (expansions, report)
+}
+
+fn is_comment(line: &str) -> bool {
+    let mut chars = line.chars();
+
+    match chars.next() {
+        Some('#') => true,
+        Some('*') => !chars.next().is_some_and(char::is_alphanumeric),
+        _ => false,
+    }
+}
+
+fn is_not_opening_bracket_or_quote(c: char) -> bool {
+    !matches!(c, '(' | '[' | '"' | '\'' | '“' | '‘' | '¿' | '¡')
+}
+
+fn words<'a>(
+    expansion: &'a str,
+    options: &ValidationOptions,
+    is_separator: fn(char) -> bool,
+) -> Result<Vec<&'a str>, RejectionReason> {
+    let mut words = Vec::new();
+
+    for raw_word in expansion.split(is_separator) {
+        let word = raw_word.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let unprefixed_word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let prefix = &word[..word.len() - unprefixed_word.len()];
+
+        if unprefixed_word.is_empty() {
+            continue;
+        }
+
+        if !options.allow_symbol_prefixes && prefix.chars().any(is_not_opening_bracket_or_quote) {
+            return Err(RejectionReason::SymbolPrefix);
+        }
+
+        if !options.allow_digits && unprefixed_word.chars().any(|c| c.is_ascii_digit()) {
+            return Err(RejectionReason::ContainsDigits);
+        }
+
+        words.push(unprefixed_word);
+    }
+
+    Ok(words)
+}
+
+fn spells_npm(words: &[&str]) -> bool {
+    let all_initials = initials(words.iter().copied());
+    let capitalised_initials = initials(
+        words
+            .iter()
+            .enumerate()
+            .filter(|(index, word)| *index == 0 || starts_with_uppercase(word))
+            .map(|(_index, word)| *word),
+    );
+
+    all_initials == "npm" || capitalised_initials == "npm"
+}
+
+fn initials<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    words
+        .filter_map(|word| word.chars().next())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn starts_with_uppercase(word: &str) -> bool {
+    word.chars().next().is_some_and(char::is_uppercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expansion::content_id;
+    use test_case::test_case;
+
+    #[test_case("Nacho Pizza Marinade"; "capitalised")]
+    #[test_case("nacho pizza marinade"; "lower case")]
+    #[test_case("Naan and Paneer Makhani"; "connecting word")]
+    #[test_case("Nit-Picking Manager"; "hyphenated")]
+    #[test_case("Noun/Pronoun Mix-up"; "slash separated")]
+    #[test_case("Nobody Pays (for) Magazines"; "parenthesised")]
+    #[test_case("Neptune: Planet or Myth?"; "punctuation")]
+    #[test_case("Ninety-nine Pitchers of Malt"; "lower case hyphenated word")]
+    #[test_case("Nutella Peanut-Butter Marshmallow"; "hyphenated compound word")]
+    #[test_case("*nix Programming Mammals"; "symbol prefix")]
+    #[test_case("N00b Pwn M3"; "digits")]
+    fn valid_expansion(expansion: &str) {
+        assert!(is_npm_expansion(expansion))
+    }
+
+    #[test_case(""; "empty")]
+    #[test_case("   "; "whitespace")]
+    #[test_case("Nucleophosmin"; "single word")]
+    #[test_case("Nice People"; "two words")]
+    #[test_case("Nice Mango People"; "wrong order")]
+    #[test_case("Nice People Meet Often"; "too many capitalised words")]
+    #[test_case("naM ,sevitcepsreP weN"; "reversed")]
+    fn invalid_expansion(expansion: &str) {
+        assert!(!is_npm_expansion(expansion))
+    }
+
+    #[test]
+    fn symbol_prefix_not_allowed() {
+        let options = ValidationOptions {
+            allow_symbol_prefixes: false,
+            ..ValidationOptions::default()
+        };
+
+        assert_eq!(
+            check_expansion("*nix Programming Mammals", &options),
+            Err(RejectionReason::SymbolPrefix)
+        )
+    }
+
+    #[test]
+    fn parenthesis_is_not_a_symbol_prefix() {
+        let options = ValidationOptions {
+            allow_symbol_prefixes: false,
+            ..ValidationOptions::default()
+        };
+
+        assert!(check_expansion("Nobody Pays (for) Magazines", &options).is_ok())
+    }
+
+    #[test]
+    fn digits_not_allowed() {
+        let options = ValidationOptions {
+            allow_digits: false,
+            ..ValidationOptions::default()
+        };
+
+        assert_eq!(
+            check_expansion("N00b Pwn M3", &options),
+            Err(RejectionReason::ContainsDigits)
+        )
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let (expansions, report) = validate_expansions(
+            "# comment\n* another comment\n\n   \nNacho Pizza Marinade",
+            &ValidationOptions::default(),
+        );
+
+        assert_eq!(texts(&expansions), ["Nacho Pizza Marinade"]);
+        assert!(report.is_valid())
+    }
+
+    #[test]
+    fn trims_whitespace() {
+        let (expansions, _report) = validate_expansions(
+            "Nacho Pizza Marinade \n Nacho Portion Monitor",
+            &ValidationOptions::default(),
+        );
+
+        assert_eq!(
+            texts(&expansions),
+            ["Nacho Pizza Marinade", "Nacho Portion Monitor"]
+        )
+    }
+
+    #[test]
+    fn reports_rejected_lines() {
+        let (expansions, report) = validate_expansions(
+            "Nacho Pizza Marinade\nNucleophosmin\nnacho pizza marinade",
+            &ValidationOptions::default(),
+        );
+
+        assert_eq!(expansions.len(), 1);
+        assert_eq!(
+            report.rejected_lines(),
+            &[
+                RejectedLine {
+                    line_number: 2,
+                    line: "Nucleophosmin".to_string(),
+                    reason: RejectionReason::NotNpmAcronym,
+                },
+                RejectedLine {
+                    line_number: 3,
+                    line: "nacho pizza marinade".to_string(),
+                    reason: RejectionReason::Duplicate,
+                },
+            ]
+        )
+    }
+
+    #[test]
+    fn symbol_prefixed_line_is_not_a_comment() {
+        let (expansions, _report) =
+            validate_expansions("*nix Programming Mammals", &ValidationOptions::default());
+
+        assert_eq!(texts(&expansions), ["*nix Programming Mammals"])
+    }
+
+    #[test]
+    fn reports_rejected_records() {
+        let (expansions, report) = validate_records(
+            vec![
+                Expansion::new("Nucleophosmin"),
+                Expansion::new(" Nacho Pizza Marinade ").with_author("hiccup246"),
+            ],
+            &ValidationOptions::default(),
+        );
+
+        assert_eq!(texts(&expansions), ["Nacho Pizza Marinade"]);
+        assert_eq!(expansions[0].author(), Some("hiccup246"));
+        assert_eq!(report.rejected_lines()[0].line_number, 1)
+    }
+
+    #[test]
+    fn assigns_content_ids() {
+        let (expansions, _report) =
+            validate_expansions(" Nacho Pizza Marinade", &ValidationOptions::default());
+
+        assert_eq!(expansions[0].id(), content_id("Nacho Pizza Marinade"))
+    }
+
+    #[test]
+    fn keeps_given_ids() {
+        let mut expansion = Expansion::new("Nacho Pizza Marinade");
+        expansion.set_id("nacho");
+
+        let (expansions, _report) =
+            validate_records(vec![expansion], &ValidationOptions::default());
+
+        assert_eq!(expansions[0].id(), "nacho")
+    }
+
+    #[test]
+    fn reports_duplicate_ids() {
+        let mut first = Expansion::new("Nacho Pizza Marinade");
+        first.set_id("nacho");
+        let mut second = Expansion::new("Nacho Portion Monitor");
+        second.set_id("nacho");
+
+        let (expansions, report) =
+            validate_records(vec![first, second], &ValidationOptions::default());
+
+        assert_eq!(expansions.len(), 1);
+        assert_eq!(
+            report.rejected_lines()[0].reason,
+            RejectionReason::DuplicateId
+        )
+    }
+
+    fn texts(expansions: &[Expansion]) -> Vec<&str> {
+        expansions
+            .iter()
+            .map(|expansion| expansion.text())
+            .collect()
+    }
+}
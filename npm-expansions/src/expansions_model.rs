@@ -1,79 +1,373 @@
+use crate::expansion::{content_id, fnv1a, Expansion};
+use crate::expansion_validator::{
+    check_expansion, validate_expansions, validate_records, ValidationOptions, ValidationReport,
+};
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use crate::search::{ScoredExpansion, SearchAlgorithm, SearchOptions, SearchResults};
+use crate::search_index::SearchIndex;
 use rand::RngExt;
-use std::fs;
-use strsim::jaro_winkler;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-/// A struct representing a vector of npm expansion strings and methods to search them
+/// A struct representing a vector of npm expansion records and methods to search them
 pub struct ExpansionsModel {
-    expansions: Vec<String>,
+    expansions: Vec<Expansion>,
+    path: String,
+    write_back: bool,
+    validation_options: ValidationOptions,
+    validation_report: ValidationReport,
+    search_algorithm: SearchAlgorithm,
+    search_index: SearchIndex,
+    corpus_hash: String,
+    last_modified: SystemTime,
 }
 
-/// This trait represents the basic search functions that a expansions model should provide
-pub trait ExpansionsAccess {
+/// This trait represents the basic search functions that a expansions model should provide. It
+/// is shared between the threads answering requests so must be Send and Sync.
+pub trait ExpansionsAccess: Send + Sync {
     /// Returns a random npm expansion
-    fn random_expansion(&self) -> String;
+    fn random_expansion(&self) -> &Expansion;
     /// Returns all available npm expansions
-    fn all(&self) -> &Vec<String>;
-    /// Returns a curated list of npm expansions based on a given search query
-    fn search(&self, query: &str) -> Vec<String>;
+    fn all(&self) -> &Vec<Expansion>;
+    /// Returns the npm expansions ranked by their similarity to a given search query, best match first,
+    /// limited by the given search options
+    fn search(&self, query: &str, options: &SearchOptions) -> SearchResults<'_>;
+    /// Returns the npm expansion with the given id if it exists
+    fn find(&self, id: &str) -> Option<&Expansion> {
+        self.all().iter().find(|expansion| expansion.id() == id)
+    }
+    /// Adds a new npm expansion, failing if it is not a valid npm expansion or already exists.
+    /// The content id and date added of the given expansion are assigned by the model.
+    fn add_expansion(&mut self, expansion: Expansion) -> Result<&Expansion, NpmExpansionsError>;
+    /// Returns a hash of every expansion record which changes whenever the expansions do, e.g. to
+    /// tag responses built from them
+    fn corpus_hash(&self) -> &str;
+    /// Returns when the expansions last changed
+    fn last_modified(&self) -> SystemTime;
 }
 
 impl ExpansionsAccess for ExpansionsModel {
-    fn all(&self) -> &Vec<String> {
+    fn all(&self) -> &Vec<Expansion> {
         &self.expansions
     }
 
-    fn random_expansion(&self) -> String {
+    fn random_expansion(&self) -> &Expansion {
         let random_index: usize = rand::rng().random_range(0..self.expansions.len());
-        let expansion = self.expansions.get(random_index).unwrap();
 
-        expansion.to_string()
+        self.expansions.get(random_index).unwrap()
+    }
+
+    fn search(&self, query: &str, options: &SearchOptions) -> SearchResults<'_> {
+        let algorithm = options.algorithm.unwrap_or(self.search_algorithm);
+        let score = |expansion| ScoredExpansion {
+            expansion,
+            score: algorithm.score(expansion.text(), query),
+        };
+
+        match self.search_index.candidates(query) {
+            Some(candidates) => SearchResults::rank(
+                candidates
+                    .into_iter()
+                    .map(|position| score(&self.expansions[position])),
+                options,
+            ),
+            None => SearchResults::rank(self.expansions.iter().map(score), options),
+        }
     }
 
-    fn search(&self, query: &str) -> Vec<String> {
-        let mut scored_matches: Vec<(f64, &String)> = self
+    fn add_expansion(
+        &mut self,
+        mut expansion: Expansion,
+    ) -> Result<&Expansion, NpmExpansionsError> {
+        let text = expansion.text().trim().to_string();
+
+        if let Err(reason) = check_expansion(&text, &self.validation_options) {
+            return Err(NpmExpansionsError::new(
+                NpmErrorKind::InvalidExpansion,
+                &format!("\"{text}\" {reason}"),
+            ));
+        }
+
+        let id = content_id(&text);
+
+        if self
             .expansions
             .iter()
-            .map(|expansion| (jaro_winkler(expansion, query), expansion))
-            .collect();
+            .any(|existing| existing.text().eq_ignore_ascii_case(&text) || existing.id() == id)
+        {
+            return Err(NpmExpansionsError::new(
+                NpmErrorKind::DuplicateExpansion,
+                &format!("\"{text}\" already exists"),
+            ));
+        }
 
-        scored_matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+        expansion.set_text(&text);
+        expansion.set_id(&id);
+        let expansion = expansion.with_date_added(chrono::Utc::now().date_naive());
 
-        let end_index = if scored_matches.len() < 10 {
-            scored_matches.len()
-        } else {
-            10
-        };
+        // The file is written first so that a failed write leaves the model unchanged
+        if self.write_back {
+            self.persist(&expansion).or(Err(NpmExpansionsError::from(
+                NpmErrorKind::InternalServerError,
+            )))?;
+        }
 
-        scored_matches[0..end_index]
-            .iter()
-            .map(|expansions| expansions.1.clone())
-            .collect::<Vec<String>>()
+        self.search_index.insert(self.expansions.len(), &text);
+        self.expansions.push(expansion);
+        self.corpus_hash = corpus_hash(&self.expansions);
+        self.last_modified = whole_second_after(SystemTime::now().max(self.last_modified));
+
+        Ok(self.expansions.last().unwrap())
+    }
+
+    fn corpus_hash(&self) -> &str {
+        &self.corpus_hash
+    }
+
+    fn last_modified(&self) -> SystemTime {
+        self.last_modified
     }
 }
 
 impl ExpansionsModel {
-    /// Takes a path to a txt file and constructs a ExpansionsModel with its
-    /// expansions field populated by the expansions found in the txt file.
+    /// Takes a path to a txt or JSON file and constructs a ExpansionsModel with its
+    /// expansions field populated by the expansions found in the file.
     ///
-    /// The given text file should be in a format where each line
-    /// that is not a comment i.e. start with a # or * () is a npm expansion
-    pub fn build(path: &str) -> ExpansionsModel {
-        let expansions_string: Vec<String> = fs::read_to_string(path)
-            .unwrap()
-            .lines()
-            .filter(|a| !a.starts_with('*') && !a.starts_with('#'))
-            .map(|expansion| expansion.to_string())
-            .collect();
+    /// A given text file should be in a format where each line
+    /// that is not blank or a comment i.e. start with a # or * () is a npm expansion.
+    /// A given JSON file (identified by its .json extension) should contain an array of
+    /// expansion records e.g. `[{ "expansion": "Nice People Meet", "author": "npm", "tags": ["food"], "date_added": "2023-03-25" }]`.
+    /// Expansions which do not spell out N-P-M are left out of the model and recorded in
+    /// its validation report. The corpus hash of the model is computed from the expansions it
+    /// keeps and its last modified time is that of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path to a txt file of expansions
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::expansions_model::{ExpansionsAccess, ExpansionsModel};
+    /// use std::fs;
+    ///
+    /// let file = tempfile::NamedTempFile::new().unwrap();
+    /// let file_path = file.path().to_str().unwrap();
+    /// fs::write(file_path, "Nacho Pizza Marinade\nNucleophosmin").unwrap();
+    ///
+    /// let expansions_model = ExpansionsModel::build(file_path).unwrap();
+    ///
+    /// assert_eq!(expansions_model.all().len(), 1);
+    /// assert_eq!(expansions_model.validation_report().rejected_lines()[0].line_number, 2);
+    /// ```
+    ///
+    /// # Failures
+    ///
+    /// The function fails if the given file cannot be read, is not valid UTF-8, is not valid JSON
+    /// when it has a .json extension or contains no valid expansions
+    ///
+    /// ```rust,should_error
+    /// // fails if the file does not exist
+    /// use npm_expansions::expansions_model::ExpansionsModel;
+    ///
+    /// ExpansionsModel::build("non-existant.txt");
+    /// ```
+    pub fn build(path: &str) -> Result<ExpansionsModel, NpmExpansionsError> {
+        Self::build_with_options(path, &ValidationOptions::default())
+    }
+
+    /// Constructs a ExpansionsModel in the same way as `build` but validates expansions
+    /// using the given options
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path to a txt file of expansions
+    /// * `validation_options` - The options used to validate each expansion
+    ///
+    /// # Failures
+    ///
+    /// The function fails under the same conditions as `build` and also when the file
+    /// contains rejected lines while in strict mode
+    ///
+    /// ```rust,should_error
+    /// // fails if the file contains rejected lines while in strict mode
+    /// use npm_expansions::{
+    ///     expansion_validator::ValidationOptions,
+    ///     expansions_model::ExpansionsModel,
+    /// };
+    /// use std::fs;
+    ///
+    /// let file = tempfile::NamedTempFile::new().unwrap();
+    /// let file_path = file.path().to_str().unwrap();
+    /// fs::write(file_path, "Nacho Pizza Marinade\nNucleophosmin").unwrap();
+    ///
+    /// let strict = ValidationOptions {
+    ///     strict: true,
+    ///     ..ValidationOptions::default()
+    /// };
+    ///
+    /// ExpansionsModel::build_with_options(file_path, &strict);
+    /// ```
+    pub fn build_with_options(
+        path: &str,
+        validation_options: &ValidationOptions,
+    ) -> Result<ExpansionsModel, NpmExpansionsError> {
+        let contents = fs::read_to_string(path).map_err(|error| {
+            NpmExpansionsError::new(
+                NpmErrorKind::ExpansionsLoadError,
+                &format!("Failed to read {path}: {error}"),
+            )
+        })?;
+        let (expansions, validation_report) = if is_json_file(path) {
+            let records: Vec<Expansion> = serde_json::from_str(&contents).map_err(|error| {
+                NpmExpansionsError::new(
+                    NpmErrorKind::ExpansionsLoadError,
+                    &format!("Failed to parse {path}: {error}"),
+                )
+            })?;
+
+            validate_records(records, validation_options)
+        } else {
+            validate_expansions(&contents, validation_options)
+        };
+
+        if validation_options.strict && !validation_report.is_valid() {
+            return Err(NpmExpansionsError::new(
+                NpmErrorKind::ExpansionsLoadError,
+                &format!("{path} failed validation with {validation_report}"),
+            ));
+        }
 
-        ExpansionsModel {
-            expansions: expansions_string,
+        if expansions.is_empty() {
+            return Err(NpmExpansionsError::new(
+                NpmErrorKind::ExpansionsLoadError,
+                &format!("{path} contains no expansions"),
+            ));
         }
+
+        let last_modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+
+        Ok(ExpansionsModel {
+            search_index: SearchIndex::build(&expansions),
+            corpus_hash: corpus_hash(&expansions),
+            last_modified,
+            expansions,
+            path: path.to_string(),
+            write_back: false,
+            validation_options: *validation_options,
+            validation_report,
+            search_algorithm: SearchAlgorithm::default(),
+        })
+    }
+
+    /// Returns the options used to validate the expansions of the model
+    pub fn validation_options(&self) -> &ValidationOptions {
+        &self.validation_options
+    }
+
+    /// Returns a report of the lines which were rejected when the model was built
+    pub fn validation_report(&self) -> &ValidationReport {
+        &self.validation_report
+    }
+
+    /// Returns whether added expansions are written to the file the model was built from
+    pub fn write_back(&self) -> bool {
+        self.write_back
+    }
+
+    /// Sets whether added expansions are written to the file the model was built from.
+    /// A txt file has the text of the newest expansion appended while a JSON file is rewritten
+    /// with every record so that metadata is kept.
+    pub fn set_write_back(&mut self, write_back: bool) {
+        self.write_back = write_back;
     }
+
+    /// Returns the algorithm used by searches which do not choose their own
+    pub fn search_algorithm(&self) -> SearchAlgorithm {
+        self.search_algorithm
+    }
+
+    /// Sets the algorithm used by searches which do not choose their own
+    pub fn set_search_algorithm(&mut self, search_algorithm: SearchAlgorithm) {
+        self.search_algorithm = search_algorithm;
+    }
+
+    /// Dates a model replacing the given one no earlier than it, so that `Last-Modified` never
+    /// moves backwards. A model with different expansions is dated by a later whole second, as
+    /// the file it was built from may have an earlier time than an added expansion.
+    pub(crate) fn date_after(&mut self, previous: &ExpansionsModel) {
+        self.last_modified = if self.corpus_hash == previous.corpus_hash {
+            self.last_modified.max(previous.last_modified)
+        } else {
+            self.last_modified
+                .max(whole_second_after(previous.last_modified))
+        };
+    }
+
+    /// Writes the given expansion, which is not yet in the model, to the file the model was
+    /// built from
+    fn persist(&self, added: &Expansion) -> io::Result<()> {
+        if is_json_file(&self.path) {
+            let records: Vec<&Expansion> = self.expansions.iter().chain([added]).collect();
+
+            fs::write(&self.path, serde_json::to_string_pretty(&records)?)
+        } else {
+            append_line(&self.path, added.text())
+        }
+    }
+}
+
+/// Returns the hash of every field of the given expansion records as 16 hex digits, which is the
+/// same between restarts for the same expansions
+pub(crate) fn corpus_hash(expansions: &[Expansion]) -> String {
+    let records = serde_json::to_vec(expansions).unwrap_or_default();
+
+    format!("{:016x}", fnv1a(&records))
+}
+
+/// Returns the first whole second after the given time. `Last-Modified` headers drop fractions of
+/// a second, so a change is dated by the next second to never share the date of the version
+/// before it.
+fn whole_second_after(time: SystemTime) -> SystemTime {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+
+    UNIX_EPOCH + Duration::from_secs(seconds + 1)
+}
+
+fn is_json_file(path: &str) -> bool {
+    path.ends_with(".json")
+}
+
+fn append_line(path: &str, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).append(true).open(path)?;
+    let mut last_byte = [0u8; 1];
+
+    let needs_newline = if file.metadata()?.len() > 0 {
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last_byte)?;
+        last_byte[0] != b'\n'
+    } else {
+        false
+    };
+
+    if needs_newline {
+        writeln!(file)?;
+    }
+
+    writeln!(file, "{line}")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http_date::{format_http_date, parse_http_date};
     use tempfile::Builder;
 
     #[test]
@@ -87,9 +381,9 @@ mod tests {
         )
         .unwrap();
 
-        let expansion = ExpansionsModel::build(file_path).random_expansion();
+        let expansions = ExpansionsModel::build(file_path).unwrap();
 
-        assert!(!expansion.is_empty())
+        assert!(!expansions.random_expansion().text().is_empty())
     }
 
     #[test]
@@ -103,7 +397,7 @@ mod tests {
         )
         .unwrap();
 
-        let all_expansions = ExpansionsModel::build(file_path);
+        let all_expansions = ExpansionsModel::build(file_path).unwrap();
 
         assert_eq!(all_expansions.all().len(), 3)
     }
@@ -118,13 +412,19 @@ mod tests {
             "Nacho Pizza Marinade\nNacho Portion Monitor\nNacho Portmanteau Meltdown\nNacho Printing Machine\nNachos Pillage Milwaukee\nNachos Preventing Motivation\nNadie Programa más\nNagging Penguin Matriarchs\nNahi Pata Mujhe!\nNail Polish Makeover\nNail Polishing Minions\nNaive Pac Man\nNaive Props Mutation\nNaive Puppets Marching".as_bytes()
         ).unwrap();
 
-        let expansions = ExpansionsModel::build(file_path).search("Nachos Pillage Milwaukee");
+        let expansions = ExpansionsModel::build(file_path).unwrap();
 
-        assert_eq!(expansions.first().unwrap(), "Nachos Pillage Milwaukee")
+        let results = expansions.search("Nachos Pillage Milwaukee", &SearchOptions::default());
+
+        assert_eq!(
+            results.matches[0].expansion.text(),
+            "Nachos Pillage Milwaukee"
+        );
+        assert_eq!(results.matches[0].score, 1.0)
     }
 
     #[test]
-    fn search_expansions_returns_top_ten() {
+    fn search_expansions_leaves_out_dissimilar_expansions() {
         let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
         let file_path = file.path().to_str().unwrap();
 
@@ -133,8 +433,435 @@ mod tests {
             "Nacho Pizza Marinade\nNacho Portion Monitor\nNacho Portmanteau Meltdown\nNacho Printing Machine\nNachos Pillage Milwaukee\nNachos Preventing Motivation\nNadie Programa más\nNagging Penguin Matriarchs\nNahi Pata Mujhe!\nNail Polish Makeover\nNail Polishing Minions\nNaive Pac Man\nNaive Props Mutation\nNaive Puppets Marching".as_bytes()
         ).unwrap();
 
-        let expansions = ExpansionsModel::build(file_path).search("Nachos Pillage Milwaukee");
+        let expansions = ExpansionsModel::build(file_path).unwrap();
+
+        let results = expansions.search("Nachos Pillage Milwaukee", &SearchOptions::default());
+        let texts: Vec<&str> = results
+            .matches
+            .iter()
+            .map(|scored| scored.expansion.text())
+            .collect();
+
+        assert!(texts.contains(&"Nachos Preventing Motivation"));
+        assert!(!texts.contains(&"Naive Puppets Marching"))
+    }
+
+    #[test]
+    fn search_expansions_blank_query_ranks_every_expansion() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade\nNail Polish Makeover").unwrap();
+
+        let expansions = ExpansionsModel::build(file_path).unwrap();
+
+        assert_eq!(expansions.search(" ", &SearchOptions::default()).total, 2)
+    }
+
+    #[test]
+    fn search_expansions_with_limit() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(
+            &file,
+            "Nacho Pizza Marinade\nNacho Portion Monitor\nNacho Portmanteau Meltdown",
+        )
+        .unwrap();
+
+        let expansions = ExpansionsModel::build(file_path).unwrap();
+        let options = SearchOptions {
+            limit: Some(2),
+            ..SearchOptions::default()
+        };
+        let results = expansions.search("Nacho", &options);
+
+        assert_eq!(results.matches.len(), 2);
+        assert_eq!(results.total, 3)
+    }
+
+    #[test]
+    fn search_expansions_with_min_score() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade\nNail Polish Makeover").unwrap();
+
+        let expansions = ExpansionsModel::build(file_path).unwrap();
+        let options = SearchOptions {
+            min_score: 0.9,
+            ..SearchOptions::default()
+        };
+
+        assert!(expansions.search("zzzz", &options).matches.is_empty())
+    }
+
+    #[test]
+    fn add_expansion() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+
+        let mut expansions = ExpansionsModel::build(file_path).unwrap();
+        let added = expansions
+            .add_expansion(Expansion::new("Nice People Meet").with_author("hiccup246"))
+            .unwrap();
+
+        assert_eq!(added.id(), content_id("Nice People Meet"));
+        assert_eq!(added.author(), Some("hiccup246"));
+        assert!(added.date_added().is_some());
+        assert_eq!(expansions.all().len(), 2)
+    }
+
+    #[test]
+    fn add_invalid_expansion() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+
+        let error = ExpansionsModel::build(file_path)
+            .unwrap()
+            .add_expansion(Expansion::new("Not An Acronym"))
+            .unwrap_err();
+
+        assert!(matches!(error.kind(), NpmErrorKind::InvalidExpansion))
+    }
+
+    #[test]
+    fn add_duplicate_expansion() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+
+        let error = ExpansionsModel::build(file_path)
+            .unwrap()
+            .add_expansion(Expansion::new(" nacho pizza marinade "))
+            .unwrap_err();
+
+        assert!(matches!(error.kind(), NpmErrorKind::DuplicateExpansion))
+    }
+
+    #[test]
+    fn add_expansion_without_write_back() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+
+        ExpansionsModel::build(file_path)
+            .unwrap()
+            .add_expansion(Expansion::new("Nice People Meet"))
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(file_path).unwrap(),
+            "Nacho Pizza Marinade"
+        )
+    }
+
+    #[test]
+    fn add_expansion_with_write_back() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+
+        let mut expansions = ExpansionsModel::build(file_path).unwrap();
+        expansions.set_write_back(true);
+        expansions
+            .add_expansion(Expansion::new("Nice People Meet"))
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(file_path).unwrap(),
+            "Nacho Pizza Marinade\nNice People Meet\n"
+        )
+    }
+
+    #[test]
+    fn add_expansion_with_failed_write_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("expansions.txt");
+        let file_path = file_path.to_str().unwrap();
+
+        fs::write(file_path, "Nacho Pizza Marinade").unwrap();
+
+        let mut expansions = ExpansionsModel::build(file_path).unwrap();
+        let corpus_hash = expansions.corpus_hash().to_string();
+        let last_modified = expansions.last_modified();
+        expansions.set_write_back(true);
+
+        // A directory in place of the file makes the write fail
+        fs::remove_file(file_path).unwrap();
+        fs::create_dir(file_path).unwrap();
+
+        assert!(expansions
+            .add_expansion(Expansion::new("Nice People Meet"))
+            .is_err());
+        assert_eq!(expansions.all().len(), 1);
+        assert_eq!(expansions.corpus_hash(), corpus_hash);
+        assert_eq!(expansions.last_modified(), last_modified);
+        assert!(expansions.find(&content_id("Nice People Meet")).is_none());
+    }
+
+    #[test]
+    fn build_leaves_out_invalid_expansions() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(
+            &file,
+            "Nacho Pizza Marinade\n\nNucleophosmin\n*nix Programming Mammals",
+        )
+        .unwrap();
+
+        let expansions = ExpansionsModel::build(file_path).unwrap();
+
+        assert_eq!(expansions.all().len(), 2);
+        assert_eq!(expansions.validation_report().rejected_lines().len(), 1)
+    }
+
+    #[test]
+    fn strict_build_with_invalid_expansions() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade\nNucleophosmin").unwrap();
+
+        let strict = ValidationOptions {
+            strict: true,
+            ..ValidationOptions::default()
+        };
+
+        assert!(ExpansionsModel::build_with_options(file_path, &strict).is_err())
+    }
+
+    #[test]
+    fn strict_build_with_valid_expansions() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade\nNacho Portion Monitor").unwrap();
+
+        let strict = ValidationOptions {
+            strict: true,
+            ..ValidationOptions::default()
+        };
+
+        assert!(ExpansionsModel::build_with_options(file_path, &strict).is_ok())
+    }
+
+    #[test]
+    fn build_missing_file() {
+        let error = ExpansionsModel::build("non-existant.txt").err().unwrap();
+
+        assert!(matches!(error.kind(), NpmErrorKind::ExpansionsLoadError))
+    }
+
+    #[test]
+    fn build_invalid_utf8_file() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, [0xff, 0xfe, 0xfd]).unwrap();
+
+        let error = ExpansionsModel::build(file_path).err().unwrap();
+
+        assert!(matches!(error.kind(), NpmErrorKind::ExpansionsLoadError))
+    }
+
+    #[test]
+    fn build_empty_file() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "# only a comment\n\n").unwrap();
+
+        let error = ExpansionsModel::build(file_path).err().unwrap();
+
+        assert!(matches!(error.kind(), NpmErrorKind::ExpansionsLoadError))
+    }
+
+    #[test]
+    fn build_json_file() {
+        let file = Builder::new().suffix(".json").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(
+            &file,
+            r#"[
+                {"expansion": "Nacho Pizza Marinade", "author": "hiccup246", "tags": ["food"], "date_added": "2023-03-25"},
+                {"expansion": "Nucleophosmin"},
+                {"id": "custom", "expansion": "Nacho Portion Monitor"}
+            ]"#,
+        )
+        .unwrap();
+
+        let expansions = ExpansionsModel::build(file_path).unwrap();
+        let first = &expansions.all()[0];
+
+        assert_eq!(expansions.all().len(), 2);
+        assert_eq!(expansions.validation_report().rejected_lines().len(), 1);
+        assert_eq!(first.id(), content_id("Nacho Pizza Marinade"));
+        assert_eq!(first.tags(), ["food".to_string()]);
+        assert_eq!(expansions.all()[1].id(), "custom")
+    }
+
+    #[test]
+    fn build_invalid_json_file() {
+        let file = Builder::new().suffix(".json").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+
+        let error = ExpansionsModel::build(file_path).err().unwrap();
+
+        assert!(matches!(error.kind(), NpmErrorKind::ExpansionsLoadError))
+    }
+
+    #[test]
+    fn add_expansion_with_json_write_back() {
+        let file = Builder::new().suffix(".json").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, r#"[{"expansion": "Nacho Pizza Marinade"}]"#).unwrap();
+
+        let mut expansions = ExpansionsModel::build(file_path).unwrap();
+        expansions.set_write_back(true);
+        expansions
+            .add_expansion(Expansion::new("Nice People Meet").with_tags(vec!["people".to_string()]))
+            .unwrap();
+
+        let reloaded = ExpansionsModel::build(file_path).unwrap();
+
+        assert_eq!(reloaded.all(), expansions.all())
+    }
+
+    #[test]
+    fn ids_are_stable_between_builds() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade\nNacho Portion Monitor").unwrap();
+        let id = ExpansionsModel::build(file_path).unwrap().all()[1]
+            .id()
+            .to_string();
+
+        fs::write(&file, "Nacho Portion Monitor\nNacho Pizza Marinade").unwrap();
+        let expansions = ExpansionsModel::build(file_path).unwrap();
+
+        assert_eq!(
+            expansions.find(&id).unwrap().text(),
+            "Nacho Portion Monitor"
+        )
+    }
+
+    #[test]
+    fn corpus_hash_is_stable_between_builds() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade\nNacho Portion Monitor").unwrap();
+        let first = ExpansionsModel::build(file_path).unwrap();
+        let second = ExpansionsModel::build(file_path).unwrap();
+
+        assert_eq!(first.corpus_hash(), second.corpus_hash());
+        assert_eq!(
+            first.last_modified(),
+            fs::metadata(&file).unwrap().modified().unwrap()
+        );
+
+        fs::write(&file, "Nacho Portion Monitor\nNacho Pizza Marinade").unwrap();
+
+        assert_ne!(
+            ExpansionsModel::build(file_path).unwrap().corpus_hash(),
+            first.corpus_hash()
+        );
+    }
+
+    #[test]
+    fn add_expansion_changes_corpus_hash() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+        let mut expansions = ExpansionsModel::build(file_path).unwrap();
+        let corpus_hash = expansions.corpus_hash().to_string();
+        let last_modified = expansions.last_modified();
+
+        expansions
+            .add_expansion(Expansion::new("Nice People Meet"))
+            .unwrap();
+
+        assert_ne!(expansions.corpus_hash(), corpus_hash);
+        assert!(expansions.last_modified() >= last_modified);
+    }
+
+    #[test]
+    fn add_expansion_dates_change_by_next_second() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+        let mut expansions = ExpansionsModel::build(file_path).unwrap();
+        let mut last_modified = format_http_date(expansions.last_modified());
+
+        for text in ["Nice People Meet", "Never Poke Monkeys"] {
+            expansions.add_expansion(Expansion::new(text)).unwrap();
+
+            let modified = format_http_date(expansions.last_modified());
+
+            assert!(parse_http_date(&modified) > parse_http_date(&last_modified));
+            assert_eq!(parse_http_date(&modified), Some(expansions.last_modified()));
+            last_modified = modified;
+        }
+    }
+
+    #[test]
+    fn find_missing_expansion() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade").unwrap();
+
+        assert!(ExpansionsModel::build(file_path)
+            .unwrap()
+            .find("missing")
+            .is_none())
+    }
+
+    #[test]
+    fn search_with_default_algorithm() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade\nNail Polish Makeover").unwrap();
+
+        let mut expansions = ExpansionsModel::build(file_path).unwrap();
+        expansions.set_search_algorithm(SearchAlgorithm::Substring);
+        let results = expansions.search("polish", &SearchOptions::default());
+
+        assert_eq!(results.total, 1);
+        assert_eq!(results.matches[0].expansion.text(), "Nail Polish Makeover")
+    }
+
+    #[test]
+    fn search_with_requested_algorithm() {
+        let file = Builder::new().prefix("expansions.txt").tempfile().unwrap();
+        let file_path = file.path().to_str().unwrap();
+
+        fs::write(&file, "Nacho Pizza Marinade\nNail Polish Makeover").unwrap();
+
+        let mut expansions = ExpansionsModel::build(file_path).unwrap();
+        expansions.set_search_algorithm(SearchAlgorithm::Substring);
+        let options = SearchOptions {
+            algorithm: Some(SearchAlgorithm::WordPrefix),
+            ..SearchOptions::default()
+        };
 
-        assert_eq!(expansions.len(), 10)
+        assert_eq!(expansions.search("nac", &options).total, 1)
     }
 }
@@ -0,0 +1,312 @@
+use crate::expansions_model::ExpansionsModel;
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use std::{
+    fs, io,
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// A struct which watches an expansions file and swaps a freshly built ExpansionsModel
+/// into a shared RwLock whenever the file changes
+pub struct ExpansionsReloader {
+    path: String,
+    last_modified: Option<SystemTime>,
+}
+
+impl ExpansionsReloader {
+    /// Creates a new reloader for the expansions file found at the given path
+    pub fn new(path: &str) -> ExpansionsReloader {
+        ExpansionsReloader {
+            path: path.to_string(),
+            last_modified: fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+        }
+    }
+
+    /// Rebuilds the expansions model from the watched file and swaps it into the given RwLock.
+    /// The new model is built before the write lock is taken so in-flight requests holding a
+    /// read lock are only blocked for the duration of the swap.
+    ///
+    /// # Arguments
+    ///
+    /// * `expansions_model` - The shared expansions model to replace
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{
+    ///     expansions_model::{ExpansionsAccess, ExpansionsModel},
+    ///     expansions_reloader::ExpansionsReloader,
+    /// };
+    /// use std::{fs, sync::RwLock};
+    ///
+    /// let file = tempfile::NamedTempFile::new().unwrap();
+    /// let file_path = file.path().to_str().unwrap();
+    /// fs::write(file_path, "Nacho Pizza Marinade").unwrap();
+    ///
+    /// let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+    /// let mut reloader = ExpansionsReloader::new(file_path);
+    ///
+    /// fs::write(file_path, "Nacho Pizza Marinade\nNacho Portion Monitor").unwrap();
+    /// reloader.reload(&expansions_model).unwrap();
+    ///
+    /// assert_eq!(expansions_model.read().unwrap().all().len(), 2);
+    /// ```
+    ///
+    /// # Failures
+    ///
+    /// The function fails if the watched file cannot be read, is not valid UTF-8, contains
+    /// no expansions or fails strict validation. In this case the given expansions model is left untouched.
+    ///
+    /// ```rust,should_error
+    /// // fails if the watched file does not exist
+    /// use npm_expansions::{
+    ///     expansions_model::ExpansionsModel,
+    ///     expansions_reloader::ExpansionsReloader,
+    /// };
+    /// use std::{fs, sync::RwLock};
+    ///
+    /// let file = tempfile::NamedTempFile::new().unwrap();
+    /// let file_path = file.path().to_str().unwrap();
+    /// fs::write(file_path, "Nacho Pizza Marinade").unwrap();
+    ///
+    /// let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+    ///
+    /// ExpansionsReloader::new("non-existant.txt").reload(&expansions_model);
+    /// ```
+    pub fn reload(
+        &mut self,
+        expansions_model: &RwLock<ExpansionsModel>,
+    ) -> Result<(), NpmExpansionsError> {
+        let last_modified = self.modified()?;
+        let validation_options = *expansions_model
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .validation_options();
+        let mut new_model = ExpansionsModel::build_with_options(&self.path, &validation_options)?;
+        let mut current_model = expansions_model
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        new_model.set_write_back(current_model.write_back());
+        new_model.set_search_algorithm(current_model.search_algorithm());
+        new_model.date_after(&current_model);
+        *current_model = new_model;
+        self.last_modified = last_modified;
+
+        Ok(())
+    }
+
+    /// Reloads the expansions model if the watched file has been modified since the last
+    /// successful load. Returns whether a reload took place.
+    ///
+    /// # Failures
+    ///
+    /// The function fails under the same conditions as `reload`
+    pub fn reload_if_changed(
+        &mut self,
+        expansions_model: &RwLock<ExpansionsModel>,
+    ) -> Result<bool, NpmExpansionsError> {
+        let modified = self.modified()?;
+
+        if modified == self.last_modified {
+            return Ok(false);
+        }
+
+        self.reload(expansions_model).map(|_| true)
+    }
+
+    fn modified(&self) -> Result<Option<SystemTime>, NpmExpansionsError> {
+        fs::metadata(&self.path)
+            .map(|metadata| metadata.modified().ok())
+            .map_err(|error| {
+                NpmExpansionsError::new(
+                    NpmErrorKind::ExpansionsLoadError,
+                    &format!("Failed to read {}: {}", self.path, error),
+                )
+            })
+    }
+
+    /// Spawns a thread which polls the watched file at the given interval and reloads the
+    /// expansions model whenever it changes. Failed reloads are logged and the previous
+    /// model is kept.
+    pub fn watch(
+        mut self,
+        expansions_model: Arc<RwLock<ExpansionsModel>>,
+        interval: Duration,
+    ) -> io::Result<thread::JoinHandle<()>> {
+        thread::Builder::new().spawn(move || loop {
+            thread::sleep(interval);
+
+            match self.reload_if_changed(&expansions_model) {
+                Ok(true) => println!(
+                    "Reloaded expansions from: {} with {}",
+                    self.path,
+                    expansions_model
+                        .read()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .validation_report()
+                ),
+                Ok(false) => {}
+                Err(error) => println!(
+                    "Failed to reload expansions from: {}. Keeping previous expansions. Error Message: {}",
+                    self.path, error
+                ),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expansion::Expansion;
+    use crate::expansion_validator::ValidationOptions;
+    use crate::expansions_model::ExpansionsAccess;
+    use crate::http_date::{format_http_date, parse_http_date};
+    use tempfile::NamedTempFile;
+
+    fn expansions_file(contents: &str) -> NamedTempFile {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), contents).unwrap();
+
+        file
+    }
+
+    fn bump_modified(file: &NamedTempFile) {
+        let modified = fs::metadata(file.path()).unwrap().modified().unwrap();
+
+        file.as_file()
+            .set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+    }
+
+    #[test]
+    fn reload_swaps_model() {
+        let file = expansions_file("Nacho Pizza Marinade");
+        let file_path = file.path().to_str().unwrap();
+        let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+        let mut reloader = ExpansionsReloader::new(file_path);
+
+        fs::write(file_path, "Nacho Pizza Marinade\nNacho Portion Monitor").unwrap();
+        reloader.reload(&expansions_model).unwrap();
+
+        assert_eq!(expansions_model.read().unwrap().all().len(), 2);
+    }
+
+    #[test]
+    fn reload_of_same_expansions_keeps_date() {
+        let file = expansions_file("Nacho Pizza Marinade");
+        let file_path = file.path().to_str().unwrap();
+        let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+        let mut reloader = ExpansionsReloader::new(file_path);
+        let last_modified = expansions_model.read().unwrap().last_modified();
+
+        reloader.reload(&expansions_model).unwrap();
+
+        assert_eq!(
+            expansions_model.read().unwrap().last_modified(),
+            last_modified
+        );
+    }
+
+    #[test]
+    fn reload_never_dates_model_earlier() {
+        let file = expansions_file("Nacho Pizza Marinade");
+        let file_path = file.path().to_str().unwrap();
+        let modified = fs::metadata(file_path).unwrap().modified().unwrap();
+        let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+        let mut reloader = ExpansionsReloader::new(file_path);
+
+        expansions_model
+            .write()
+            .unwrap()
+            .add_expansion(Expansion::new("Nice People Meet"))
+            .unwrap();
+        let added = expansions_model.read().unwrap().last_modified();
+
+        fs::write(file_path, "Nacho Pizza Marinade\nNacho Portion Monitor").unwrap();
+        file.as_file().set_modified(modified).unwrap();
+        reloader.reload(&expansions_model).unwrap();
+
+        assert!(
+            parse_http_date(&format_http_date(
+                expansions_model.read().unwrap().last_modified()
+            )) > Some(added)
+        );
+    }
+
+    #[test]
+    fn unchanged_file_is_not_reloaded() {
+        let file = expansions_file("Nacho Pizza Marinade");
+        let file_path = file.path().to_str().unwrap();
+        let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+        let mut reloader = ExpansionsReloader::new(file_path);
+
+        assert!(!reloader.reload_if_changed(&expansions_model).unwrap())
+    }
+
+    #[test]
+    fn changed_file_is_reloaded() {
+        let file = expansions_file("Nacho Pizza Marinade");
+        let file_path = file.path().to_str().unwrap();
+        let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+        let mut reloader = ExpansionsReloader::new(file_path);
+
+        fs::write(file_path, "Nacho Pizza Marinade\nNacho Portion Monitor").unwrap();
+        bump_modified(&file);
+
+        assert!(reloader.reload_if_changed(&expansions_model).unwrap());
+        assert_eq!(expansions_model.read().unwrap().all().len(), 2);
+    }
+
+    #[test]
+    fn empty_file_keeps_previous_model() {
+        let file = expansions_file("Nacho Pizza Marinade");
+        let file_path = file.path().to_str().unwrap();
+        let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+        let mut reloader = ExpansionsReloader::new(file_path);
+
+        fs::write(file_path, "# only a comment").unwrap();
+        bump_modified(&file);
+
+        assert!(reloader.reload_if_changed(&expansions_model).is_err());
+        assert_eq!(
+            expansions_model.read().unwrap().all()[0].text(),
+            "Nacho Pizza Marinade"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_keeps_previous_model() {
+        let file = expansions_file("Nacho Pizza Marinade");
+        let file_path = file.path().to_str().unwrap();
+        let expansions_model = RwLock::new(ExpansionsModel::build(file_path).unwrap());
+        let mut reloader = ExpansionsReloader::new(file_path);
+
+        fs::write(file_path, [0xff, 0xfe, 0xfd]).unwrap();
+
+        assert!(reloader.reload(&expansions_model).is_err());
+        assert_eq!(expansions_model.read().unwrap().all().len(), 1);
+    }
+
+    #[test]
+    fn strict_validation_failure_keeps_previous_model() {
+        let file = expansions_file("Nacho Pizza Marinade");
+        let file_path = file.path().to_str().unwrap();
+        let strict = ValidationOptions {
+            strict: true,
+            ..ValidationOptions::default()
+        };
+        let expansions_model =
+            RwLock::new(ExpansionsModel::build_with_options(file_path, &strict).unwrap());
+        let mut reloader = ExpansionsReloader::new(file_path);
+
+        fs::write(file_path, "Nacho Pizza Marinade\nNucleophosmin").unwrap();
+
+        assert!(reloader.reload(&expansions_model).is_err());
+        assert_eq!(expansions_model.read().unwrap().all().len(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::time::SystemTime;
+
+/// The preferred format of HTTP dates e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+const IMF_FIXDATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// The obsolete formats of HTTP dates which must still be accepted from clients, RFC 850 e.g.
+/// `Sunday, 06-Nov-94 08:49:37 GMT` and asctime e.g. `Sun Nov  6 08:49:37 1994`
+const OBSOLETE_FORMATS: [&str; 2] = ["%A, %d-%b-%y %H:%M:%S GMT", "%a %b %e %H:%M:%S %Y"];
+
+/// Returns the given time as a HTTP date e.g. for a `Last-Modified` header. Fractions of a
+/// second are dropped.
+///
+/// # Arguments
+///
+/// * `time` - The time to format
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::http_date::format_http_date;
+/// use std::time::{Duration, SystemTime};
+///
+/// let time = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+///
+/// assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+/// ```
+pub fn format_http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).format(IMF_FIXDATE).to_string()
+}
+
+/// Parses a HTTP date e.g. from an `If-Modified-Since` header in any of the formats clients may
+/// send, returning None when the date is invalid
+///
+/// # Arguments
+///
+/// * `date` - The date to parse e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::http_date::parse_http_date;
+/// use std::time::{Duration, SystemTime};
+///
+/// let time = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+///
+/// assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+/// assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(time));
+/// assert_eq!(parse_http_date("yesterday"), None);
+/// ```
+pub fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let date = date.trim();
+
+    std::iter::once(IMF_FIXDATE)
+        .chain(OBSOLETE_FORMATS)
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .map(|date_time| date_time.and_utc().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use test_case::test_case;
+
+    #[test_case("Sun, 06 Nov 1994 08:49:37 GMT"; "imf fixdate")]
+    #[test_case("Sunday, 06-Nov-94 08:49:37 GMT"; "rfc 850")]
+    #[test_case("Sun Nov  6 08:49:37 1994"; "asctime")]
+    fn parses_every_format(date: &str) {
+        assert_eq!(
+            parse_http_date(date),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777))
+        );
+    }
+
+    #[test_case(""; "empty")]
+    #[test_case("Sun, 06 Nov 1994 08:49:37 PST"; "other time zone")]
+    #[test_case("Sun, 32 Nov 1994 08:49:37 GMT"; "invalid day")]
+    fn rejects_invalid_dates(date: &str) {
+        assert_eq!(parse_http_date(date), None);
+    }
+
+    #[test]
+    fn round_trips_whole_seconds() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_900);
+
+        assert_eq!(
+            parse_http_date(&format_http_date(time)),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// The headers of a HTTP request or response. Names are matched case-insensitively while keeping
+/// the casing they were sent with, and a header sent on several lines keeps every value.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::http_headers::HttpHeaders;
+///
+/// let mut headers = HttpHeaders::new();
+/// headers.append("Accept", "text/html");
+/// headers.append("ACCEPT", "application/json");
+///
+/// assert_eq!(headers.get("accept"), Some("text/html, application/json"));
+/// assert_eq!(headers.get_all("Accept"), ["text/html", "application/json"]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpHeaders {
+    fields: Vec<HeaderField>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct HeaderField {
+    name: String,
+    values: Vec<String>,
+    combined: String,
+}
+
+impl HttpHeaders {
+    /// Creates an empty set of headers
+    pub fn new() -> HttpHeaders {
+        HttpHeaders::default()
+    }
+
+    /// Adds a value to a header. A value for a name already present, ignoring case, is added to
+    /// the existing header which keeps the casing of its first line.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the header e.g. `Accept`
+    /// * `value` - The value of the header e.g. `application/json`
+    pub fn append(&mut self, name: &str, value: &str) {
+        match self.field_mut(name) {
+            Some(field) => {
+                field.values.push(value.to_string());
+                field.combined = field.values.join(", ");
+            }
+            None => self.fields.push(HeaderField {
+                name: name.to_string(),
+                values: vec![value.to_string()],
+                combined: value.to_string(),
+            }),
+        }
+    }
+
+    /// Sets a header to a single value, replacing any values it had. A header already present,
+    /// ignoring case, keeps its position and the casing of its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the header e.g. `X-Request-Id`
+    /// * `value` - The value of the header e.g. `4f5ac2a2-00000001`
+    pub fn insert(&mut self, name: &str, value: &str) {
+        match self.field_mut(name) {
+            Some(field) => {
+                field.values = vec![value.to_string()];
+                field.combined = value.to_string();
+            }
+            None => self.append(name, value),
+        }
+    }
+
+    /// Returns the value of a header, ignoring the case of its name. The values of a header sent
+    /// on several lines are joined with `, ` as they would be in a single comma separated line.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.field(name).map(|field| field.combined.as_str())
+    }
+
+    /// Returns every value of a header in the order they were sent, ignoring the case of its name
+    pub fn get_all(&self, name: &str) -> &[String] {
+        self.field(name)
+            .map_or(&[], |field| field.values.as_slice())
+    }
+
+    /// Returns true if the header is present, ignoring the case of its name
+    pub fn contains(&self, name: &str) -> bool {
+        self.field(name).is_some()
+    }
+
+    /// Returns the name, in the casing it was sent with, and value of each header in the order
+    /// they were first sent
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.combined.as_str()))
+    }
+
+    /// Returns the name and value of each header line in order, giving a header with several
+    /// values one line per value as a response must for headers such as `Set-Cookie`
+    pub fn lines(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().flat_map(|field| {
+            field
+                .values
+                .iter()
+                .map(|value| (field.name.as_str(), value.as_str()))
+        })
+    }
+
+    /// Returns the number of distinct headers
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns true if there are no headers
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    fn field(&self, name: &str) -> Option<&HeaderField> {
+        self.fields
+            .iter()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut HeaderField> {
+        self.fields
+            .iter_mut()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for HttpHeaders {
+    /// Formats the headers as they are written in a HTTP message, one `name: value` line per
+    /// value separated by CRLF without a trailing CRLF
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (position, (name, value)) in self.lines().enumerate() {
+            if position > 0 {
+                f.write_str("\r\n")?;
+            }
+
+            write!(f, "{name}: {value}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<HashMap<String, String>> for HttpHeaders {
+    fn from(headers: HashMap<String, String>) -> Self {
+        let mut http_headers = HttpHeaders::new();
+
+        for (name, value) in headers {
+            http_headers.append(&name, &value);
+        }
+
+        http_headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("Accept"; "canonical case")]
+    #[test_case("accept"; "lower case")]
+    #[test_case("ACCEPT"; "upper case")]
+    #[test_case("AcCePt"; "mixed case")]
+    fn get_ignores_case(name: &str) {
+        let mut headers = HttpHeaders::new();
+        headers.append("aCCEPT", "application/json");
+
+        assert_eq!(headers.get(name), Some("application/json"))
+    }
+
+    #[test]
+    fn merges_repeated_headers() {
+        let mut headers = HttpHeaders::new();
+        headers.append("Accept", "text/html");
+        headers.append("User-Agent", "curl");
+        headers.append("accept", "application/json;q=0.9");
+
+        assert_eq!(
+            headers.get("Accept"),
+            Some("text/html, application/json;q=0.9")
+        );
+        assert_eq!(
+            headers.get_all("ACCEPT"),
+            ["text/html", "application/json;q=0.9"]
+        );
+        assert_eq!(headers.len(), 2)
+    }
+
+    #[test]
+    fn keeps_original_casing() {
+        let mut headers = HttpHeaders::new();
+        headers.append("x-REQUEST-id", "1");
+        headers.append("X-Request-Id", "2");
+
+        assert_eq!(
+            headers.iter().collect::<Vec<(&str, &str)>>(),
+            [("x-REQUEST-id", "1, 2")]
+        )
+    }
+
+    #[test]
+    fn insert_replaces_values() {
+        let mut headers = HttpHeaders::new();
+        headers.append("X-Request-Id", "1");
+        headers.append("Accept", "text/html");
+        headers.append("x-request-id", "2");
+        headers.insert("X-REQUEST-ID", "3");
+        headers.insert("User-Agent", "curl");
+
+        assert_eq!(
+            headers.iter().collect::<Vec<(&str, &str)>>(),
+            [
+                ("X-Request-Id", "3"),
+                ("Accept", "text/html"),
+                ("User-Agent", "curl")
+            ]
+        );
+        assert_eq!(headers.get_all("X-Request-Id"), ["3"])
+    }
+
+    #[test]
+    fn formats_one_line_per_value() {
+        let mut headers = HttpHeaders::new();
+        headers.append("Set-Cookie", "a=1");
+        headers.append("Vary", "Origin");
+        headers.append("set-cookie", "b=2");
+
+        assert_eq!(
+            headers.to_string(),
+            "Set-Cookie: a=1\r\nSet-Cookie: b=2\r\nVary: Origin"
+        )
+    }
+
+    #[test]
+    fn missing_header() {
+        let headers = HttpHeaders::new();
+
+        assert_eq!(headers.get("Accept"), None);
+        assert!(headers.get_all("Accept").is_empty());
+        assert!(!headers.contains("Accept"))
+    }
+}
@@ -1,20 +1,55 @@
+use crate::http_headers::HttpHeaders;
 use crate::mock_tcp_stream::TcpAddr;
 use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
 use std::{
     collections::HashMap,
-    io::{prelude::*, BufReader, Lines, Read, Take, Write},
+    io::{self, prelude::*, BufReader, Lines, Read, Write},
 };
 
-/// A struct representing the basic parts of a HTTP request i.e. status line, headers and query params
+/// A struct representing the basic parts of a HTTP request i.e. status line, headers, query params and body
+#[derive(Clone)]
 pub struct HttpRequest {
     host: String,
     status_line: String,
-    headers: HashMap<String, String>,
+    headers: HttpHeaders,
     query_params: HashMap<String, String>,
+    query_param_values: HashMap<String, Vec<String>>,
+    path_params: HashMap<String, String>,
+    body: Vec<u8>,
 }
 
 const HEADER_SIZE_LIMIT: u64 = 8000;
 
+const BODY_SIZE_LIMIT: usize = 8000;
+
+const CHUNK_LINE_SIZE_LIMIT: u64 = 1024;
+
+/// Options controlling how a HTTP request is read from a stream
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::http_request::RequestOptions;
+///
+/// let options = RequestOptions {
+///     body_size_limit: 64 * 1024,
+/// };
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct RequestOptions {
+    /// The largest body, in bytes, a request may have. A request with a larger body fails with
+    /// a `PayloadTooLarge` error. Defaults to 8000 bytes.
+    pub body_size_limit: usize,
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        RequestOptions {
+            body_size_limit: BODY_SIZE_LIMIT,
+        }
+    }
+}
+
 impl HttpRequest {
     /// Builds a request object from a given http request stream
     ///
@@ -45,8 +80,8 @@ impl HttpRequest {
     /// # Failures
     ///
     /// The function fails if the the given request stream is invalid. This can be due to
-    /// the request having too many headers, having no status line, having invalid headers or
-    /// the server being unable to process the stream.
+    /// the request having too many headers, having no status line, having invalid headers, having
+    /// a body which does not match its Content-Length header or the server being unable to process the stream.
     ///
     /// ```rust,should_error
     /// // fails if no http status line is given
@@ -67,53 +102,244 @@ impl HttpRequest {
     /// ```
     pub fn build(
         stream: &mut (impl Read + Write + TcpAddr),
+    ) -> Result<HttpRequest, NpmExpansionsError> {
+        Self::build_with_options(stream, &RequestOptions::default())
+    }
+
+    /// Builds a request object from a given http request stream using the given options. The body
+    /// is read using the `Content-Length` header or, when the request has a
+    /// `Transfer-Encoding: chunked` header, by joining its chunks.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - An incoming http request stream
+    /// * `options` - The options controlling how the request is read e.g. the body size limit
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{
+    ///     http_request::{HttpRequest, RequestOptions},
+    ///     mock_tcp_stream::MockTcpStream,
+    /// };
+    ///
+    /// let input_bytes = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+    /// let mut contents = vec![0u8; 1024];
+    /// contents[..input_bytes.len()].clone_from_slice(input_bytes);
+    /// let mut stream = MockTcpStream {
+    ///     read_data: contents,
+    ///     write_data: Vec::new(),
+    /// };
+    /// let request = HttpRequest::build_with_options(&mut stream, &RequestOptions::default()).unwrap();
+    ///
+    /// assert_eq!(request.body_text().unwrap(), "hello world");
+    /// ```
+    ///
+    /// # Failures
+    ///
+    /// The function fails for the same reasons as [`HttpRequest::build`], if the request has a
+    /// malformed chunked body or if its body is larger than the body size limit
+    ///
+    /// ```rust,should_error
+    /// // fails if the body is larger than the body size limit
+    /// use npm_expansions::{
+    ///     http_request::{HttpRequest, RequestOptions},
+    ///     mock_tcp_stream::MockTcpStream,
+    /// };
+    ///
+    /// let input_bytes = b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world";
+    /// let mut contents = vec![0u8; 1024];
+    /// contents[..input_bytes.len()].clone_from_slice(input_bytes);
+    /// let mut stream = MockTcpStream {
+    ///     read_data: contents,
+    ///     write_data: Vec::new(),
+    /// };
+    ///
+    /// HttpRequest::build_with_options(&mut stream, &RequestOptions { body_size_limit: 5 });
+    /// ```
+    pub fn build_with_options(
+        stream: &mut (impl Read + Write + TcpAddr),
+        options: &RequestOptions,
     ) -> Result<HttpRequest, NpmExpansionsError> {
         let host = stream
             .peer_addr()
             .map(|socket_addr| socket_addr.ip().to_string())
             .unwrap_or("-".to_string());
-        let buf_reader = BufReader::new(stream);
-        let mut buffer = buf_reader.take(HEADER_SIZE_LIMIT).lines();
 
-        let status_line;
+        Self::read_from(&mut BufReader::new(stream), &host, options)
+    }
 
-        if let Some(Ok(line)) = buffer.next() {
-            status_line = line;
-        } else {
-            return Err(NpmExpansionsError::from(NpmErrorKind::InvalidHttpRequest));
-        }
+    /// Reads the next request from a buffered stream. Unlike [`HttpRequest::build`] the reader
+    /// can be kept between calls so that requests sent one after another on a persistent
+    /// connection are read in turn.
+    pub(crate) fn read_from(
+        reader: &mut impl BufRead,
+        host: &str,
+        options: &RequestOptions,
+    ) -> Result<HttpRequest, NpmExpansionsError> {
+        Self::read_head(reader, host)?.read_body(reader, options)
+    }
 
-        let query_params = Self::build_query_params(&status_line)?;
+    /// Reads the status line and headers of the next request from a buffered stream, leaving its
+    /// body to be read by [`HttpRequest::read_body`]. Reading the two apart lets a caller wait a
+    /// different length of time for each.
+    pub(crate) fn read_head(
+        reader: &mut impl BufRead,
+        host: &str,
+    ) -> Result<HttpRequest, NpmExpansionsError> {
+        let host = host.to_string();
+        let header_reader = reader.take(HEADER_SIZE_LIMIT);
+        let mut buffer = header_reader.lines();
+
+        let status_line = match buffer.next() {
+            Some(Ok(line)) => line,
+            Some(Err(read_err)) => return Err(read_error(read_err)),
+            None => return Err(NpmExpansionsError::from(NpmErrorKind::InvalidHttpRequest)),
+        };
+
+        let query_param_values = Self::build_query_params(&status_line)?;
         let headers = Self::build_headers(&mut buffer)?;
 
         Ok(HttpRequest {
             host,
             status_line,
             headers,
-            query_params,
+            query_params: first_values(&query_param_values),
+            query_param_values,
+            path_params: HashMap::new(),
+            body: Vec::new(),
         })
     }
 
+    /// Reads the body of a request whose status line and headers were read by
+    /// [`HttpRequest::read_head`] from the same reader
+    pub(crate) fn read_body(
+        mut self,
+        reader: &mut impl BufRead,
+        options: &RequestOptions,
+    ) -> Result<HttpRequest, NpmExpansionsError> {
+        self.body = Self::build_body(reader, &self.headers, options)?;
+
+        Ok(self)
+    }
+
     fn build_headers(
-        header_buffer: &mut Lines<Take<BufReader<&mut (impl Read + Write)>>>,
-    ) -> Result<HashMap<String, String>, NpmExpansionsError> {
-        let mut headers: HashMap<String, String> = HashMap::new();
+        header_buffer: &mut Lines<impl BufRead>,
+    ) -> Result<HttpHeaders, NpmExpansionsError> {
+        let mut headers = HttpHeaders::new();
 
         for line in header_buffer {
-            let current_line =
-                line.map_err(|_| NpmExpansionsError::from(NpmErrorKind::InvalidHttpRequest))?;
+            let current_line = line.map_err(read_error)?;
 
             if current_line.is_empty() {
                 return Ok(headers);
             } else {
                 let (key, value) = Self::header_key_value(current_line)?;
-                headers.insert(key, value);
+                headers.append(&key, &value);
             }
         }
 
         Err(NpmExpansionsError::from(NpmErrorKind::InvalidHttpRequest))
     }
 
+    fn build_body(
+        body_reader: impl BufRead,
+        headers: &HttpHeaders,
+        options: &RequestOptions,
+    ) -> Result<Vec<u8>, NpmExpansionsError> {
+        match (
+            headers.get("Transfer-Encoding"),
+            headers.get("Content-Length"),
+        ) {
+            (Some(_), Some(_)) => Err(NpmExpansionsError::new(
+                NpmErrorKind::InvalidHttpRequest,
+                "A HTTP request cannot have both a Transfer-Encoding and a Content-Length header",
+            )),
+            (Some(transfer_encoding), None)
+                if transfer_encoding.eq_ignore_ascii_case("chunked") =>
+            {
+                Self::build_chunked_body(body_reader, options.body_size_limit)
+            }
+            (Some(_), None) => Err(NpmExpansionsError::new(
+                NpmErrorKind::InvalidHttpRequest,
+                "The only supported Transfer-Encoding is chunked",
+            )),
+            (None, Some(content_length)) => {
+                Self::build_sized_body(body_reader, content_length, options.body_size_limit)
+            }
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    fn build_sized_body(
+        body_reader: impl BufRead,
+        content_length: &str,
+        body_size_limit: usize,
+    ) -> Result<Vec<u8>, NpmExpansionsError> {
+        let content_length = content_length
+            .parse::<usize>()
+            .or(Err(NpmExpansionsError::from(
+                NpmErrorKind::InvalidHttpRequest,
+            )))?;
+
+        if content_length > body_size_limit {
+            return Err(payload_too_large(body_size_limit));
+        }
+
+        let mut body = Vec::with_capacity(content_length);
+
+        body_reader
+            .take(content_length as u64)
+            .read_to_end(&mut body)
+            .map_err(read_error)?;
+
+        if body.len() != content_length {
+            return Err(NpmExpansionsError::from(NpmErrorKind::InvalidHttpRequest));
+        }
+
+        Ok(body)
+    }
+
+    fn build_chunked_body(
+        mut body_reader: impl BufRead,
+        body_size_limit: usize,
+    ) -> Result<Vec<u8>, NpmExpansionsError> {
+        let mut body = Vec::new();
+
+        loop {
+            let size_line = read_chunk_line(&mut body_reader)?;
+            let size = size_line.split(';').next().unwrap_or("").trim();
+
+            if size.is_empty() || !size.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                return Err(invalid_chunk());
+            }
+
+            let size = usize::from_str_radix(size, 16).map_err(|_| invalid_chunk())?;
+
+            if size == 0 {
+                break;
+            }
+
+            if body.len().saturating_add(size) > body_size_limit {
+                return Err(payload_too_large(body_size_limit));
+            }
+
+            let read = body_reader
+                .by_ref()
+                .take(size as u64)
+                .read_to_end(&mut body)
+                .map_err(read_chunk_error)?;
+
+            if read != size || !read_chunk_line(&mut body_reader)?.is_empty() {
+                return Err(invalid_chunk());
+            }
+        }
+
+        while !read_chunk_line(&mut body_reader)?.is_empty() {}
+
+        Ok(body)
+    }
+
     fn header_key_value(header_line: String) -> Result<(String, String), NpmExpansionsError> {
         let colon_position = header_line.find(':').ok_or(NpmExpansionsError::from(
             NpmErrorKind::InvalidRequestHeaders,
@@ -127,7 +353,7 @@ impl HttpRequest {
 
     fn build_query_params(
         status_line: &str,
-    ) -> Result<HashMap<String, String>, NpmExpansionsError> {
+    ) -> Result<HashMap<String, Vec<String>>, NpmExpansionsError> {
         let split_line: Vec<&str> = status_line.split(' ').collect();
 
         let uri = split_line
@@ -145,54 +371,118 @@ impl HttpRequest {
 
     fn process_query_string(
         query_string: &str,
-    ) -> Result<HashMap<String, String>, NpmExpansionsError> {
-        let query_parameters: Result<Vec<(&str, &str)>, NpmExpansionsError> = query_string
-            .split('&')
-            .filter(|a| !a.is_empty())
-            .map(|param| {
-                param
-                    .split_once('=')
-                    .ok_or(NpmExpansionsError::from(NpmErrorKind::InvalidHttpRequest))
-            })
-            .collect();
+    ) -> Result<HashMap<String, Vec<String>>, NpmExpansionsError> {
+        let mut query_hash_map: HashMap<String, Vec<String>> = HashMap::new();
+
+        for param in query_string.split('&').filter(|a| !a.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
 
-        let query_hash_map: HashMap<String, String> =
-            query_parameters?
-                .into_iter()
-                .fold(HashMap::new(), |mut acc, param| {
-                    let (key, value) = param;
-                    acc.insert(key.to_string(), value.to_string());
-                    acc
-                });
+            query_hash_map
+                .entry(percent_decode(key)?)
+                .or_default()
+                .push(percent_decode(value)?);
+        }
 
         Ok(query_hash_map)
     }
 
-    /// Creates a new request from status line string, headers HashMap and query params hashmap
+    /// Creates a new request from status line string, headers and query params hashmap. The headers
+    /// can be given as a HashMap or as [`HttpHeaders`]
     pub fn new(
         host: &str,
         status_line: &str,
-        headers: HashMap<String, String>,
+        headers: impl Into<HttpHeaders>,
         query_params: HashMap<String, String>,
     ) -> HttpRequest {
         HttpRequest {
             host: host.to_string(),
             status_line: status_line.to_string(),
-            headers,
+            headers: headers.into(),
+            query_param_values: query_params
+                .iter()
+                .map(|(key, value)| (key.to_string(), vec![value.to_string()]))
+                .collect(),
             query_params,
+            path_params: HashMap::new(),
+            body: Vec::new(),
         }
     }
 
+    /// Returns the request with its body replaced by the given bytes
+    pub fn with_body(mut self, body: &[u8]) -> HttpRequest {
+        self.body = body.to_vec();
+        self
+    }
+
+    /// Returns the request with its path params replaced by the given params. Path params are
+    /// the named segments of a matched route e.g. `id` in `/api/expansions/{id}`
+    pub fn with_path_params(mut self, path_params: HashMap<String, String>) -> HttpRequest {
+        self.path_params = path_params;
+        self
+    }
+
     /// Returns the status line of a request object
     pub fn status_line(&self) -> &str {
         self.status_line.as_str()
     }
 
+    /// Returns the method from the status line of a request object e.g. `GET`
+    pub fn method(&self) -> &str {
+        self.status_line.split(' ').next().unwrap_or("")
+    }
+
+    /// Returns the HTTP version from the status line of a request object e.g. `HTTP/1.1`
+    pub fn version(&self) -> &str {
+        self.status_line.split(' ').nth(2).unwrap_or("")
+    }
+
+    /// Returns true if the client wants the connection kept open after the response. HTTP/1.1
+    /// connections are kept open unless the request has a `Connection: close` header while
+    /// HTTP/1.0 connections are only kept open given a `Connection: keep-alive` header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::http_request::HttpRequest;
+    /// use std::collections::HashMap;
+    ///
+    /// let request = HttpRequest::new("127.0.0.1", "GET / HTTP/1.0", HashMap::from([("Connection".to_string(), "Keep-Alive".to_string())]), HashMap::new());
+    ///
+    /// assert!(request.keep_alive());
+    /// ```
+    pub fn keep_alive(&self) -> bool {
+        let has_token = |token: &str| {
+            self.headers
+                .get_all("Connection")
+                .iter()
+                .flat_map(|value| value.split(','))
+                .any(|option| option.trim().eq_ignore_ascii_case(token))
+        };
+
+        match self.version() {
+            "HTTP/1.1" => !has_token("close"),
+            _ => has_token("keep-alive") && !has_token("close"),
+        }
+    }
+
     /// Returns the SocketAddr of the http request object
     pub fn host(&self) -> &str {
         self.host.as_str()
     }
 
+    /// Returns the target from the status line of a request object including its query string
+    /// e.g. `/api/all?limit=10`
+    pub fn target(&self) -> &str {
+        self.status_line.split(' ').nth(1).unwrap_or("")
+    }
+
+    /// Returns the path of the request without its query string e.g. `/api/all`
+    pub fn path(&self) -> &str {
+        let uri = self.target();
+
+        uri.split_once('?').map_or(uri, |(path, _query)| path)
+    }
+
     /// Returns the path from the status line of a request object
     pub fn status_line_path(&self) -> String {
         let split_line: Vec<&str> = self.status_line.split(' ').collect();
@@ -211,23 +501,263 @@ impl HttpRequest {
     }
 
     /// Returns the requests headers
-    pub fn headers(&self) -> &HashMap<String, String> {
+    pub fn headers(&self) -> &HttpHeaders {
         &self.headers
     }
 
-    /// Returns the requests query params
+    /// Returns the requests headers for changing e.g. by a middleware giving the request an id
+    pub fn headers_mut(&mut self) -> &mut HttpHeaders {
+        &mut self.headers
+    }
+
+    /// Returns the requests query params. When a key is repeated only its first value is given,
+    /// see [`HttpRequest::query_param_values`] for every value.
     pub fn query_params(&self) -> &HashMap<String, String> {
         &self.query_params
     }
+
+    /// Returns every value of a query param in the order they appear in the query string e.g.
+    /// `["food", "people"]` for `?tag=food&tag=people`. A key without a value, such as a flag
+    /// like `?pretty`, has a single empty value.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The decoded name of the query param
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{
+    ///     http_request::HttpRequest,
+    ///     mock_tcp_stream::MockTcpStream,
+    /// };
+    ///
+    /// let input_bytes = b"GET /api/search?tag=food&tag=people%20watching HTTP/1.1\r\n\r\n";
+    /// let mut contents = vec![0u8; 1024];
+    /// contents[..input_bytes.len()].clone_from_slice(input_bytes);
+    /// let mut stream = MockTcpStream {
+    ///     read_data: contents,
+    ///     write_data: Vec::new(),
+    /// };
+    /// let request = HttpRequest::build(&mut stream).unwrap();
+    ///
+    /// assert_eq!(request.query_param_values("tag"), ["food", "people watching"]);
+    /// assert!(request.query_param_values("missing").is_empty());
+    /// ```
+    pub fn query_param_values(&self, key: &str) -> &[String] {
+        self.query_param_values
+            .get(key)
+            .map_or(&[], |values| values.as_slice())
+    }
+
+    /// Returns the requests path params
+    pub fn path_params(&self) -> &HashMap<String, String> {
+        &self.path_params
+    }
+
+    /// Returns the value of a path param of the matched route e.g. `id` for a route of
+    /// `/api/expansions/{id}`, or the rest of the path for a wildcard e.g. `path` for a route of
+    /// `/static/{*path}`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::http_request::HttpRequest;
+    /// use std::collections::HashMap;
+    ///
+    /// let request = HttpRequest::new("127.0.0.1", "GET /api/expansions/abc HTTP/1.1", HashMap::new(), HashMap::new())
+    ///     .with_path_params(HashMap::from([("id".to_string(), "abc".to_string())]));
+    ///
+    /// assert_eq!(request.path_param("id"), Some("abc"));
+    /// assert_eq!(request.path_param("name"), None);
+    /// ```
+    pub fn path_param(&self, name: &str) -> Option<&str> {
+        self.path_params.get(name).map(String::as_str)
+    }
+
+    /// Returns the requests body
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Returns the requests body as UTF-8 text
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::http_request::HttpRequest;
+    /// use std::collections::HashMap;
+    ///
+    /// let request = HttpRequest::new("127.0.0.1", "POST / HTTP/1.1", HashMap::new(), HashMap::new())
+    ///     .with_body("Nadie Programa más".as_bytes());
+    ///
+    /// assert_eq!(request.body_text().unwrap(), "Nadie Programa más");
+    /// ```
+    ///
+    /// # Failures
+    ///
+    /// The function fails if the body is not valid UTF-8
+    ///
+    /// ```rust,should_error
+    /// // fails if the body is not valid UTF-8
+    /// use npm_expansions::http_request::HttpRequest;
+    /// use std::collections::HashMap;
+    ///
+    /// let request = HttpRequest::new("127.0.0.1", "POST / HTTP/1.1", HashMap::new(), HashMap::new())
+    ///     .with_body(&[0xC3, 0x28]);
+    ///
+    /// request.body_text();
+    /// ```
+    pub fn body_text(&self) -> Result<&str, NpmExpansionsError> {
+        std::str::from_utf8(&self.body).map_err(|_| {
+            NpmExpansionsError::new(
+                NpmErrorKind::InvalidHttpRequest,
+                "A HTTP request body is not valid UTF-8",
+            )
+        })
+    }
+}
+
+/// Reads a line of a chunked body without its line ending. The line must end in a newline and
+/// be shorter than the chunk line size limit.
+fn read_chunk_line(body_reader: &mut impl BufRead) -> Result<String, NpmExpansionsError> {
+    let mut line = Vec::new();
+
+    body_reader
+        .take(CHUNK_LINE_SIZE_LIMIT)
+        .read_until(b'\n', &mut line)
+        .map_err(read_chunk_error)?;
+
+    if line.pop() != Some(b'\n') {
+        return Err(invalid_chunk());
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line).map_err(|_| invalid_chunk())
+}
+
+/// Returns a `RequestTimeout` error if a read timed out, otherwise an `InvalidHttpRequest` error
+fn read_error(read_err: io::Error) -> NpmExpansionsError {
+    if is_timeout(&read_err) {
+        request_timeout()
+    } else {
+        NpmExpansionsError::from(NpmErrorKind::InvalidHttpRequest)
+    }
+}
+
+fn read_chunk_error(read_err: io::Error) -> NpmExpansionsError {
+    if is_timeout(&read_err) {
+        request_timeout()
+    } else {
+        invalid_chunk()
+    }
+}
+
+/// Sockets report a read timeout as `WouldBlock` on some platforms and `TimedOut` on others
+fn is_timeout(read_err: &io::Error) -> bool {
+    matches!(
+        read_err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+fn request_timeout() -> NpmExpansionsError {
+    NpmExpansionsError::new(
+        NpmErrorKind::RequestTimeout,
+        "A HTTP request was not received in time",
+    )
+}
+
+fn invalid_chunk() -> NpmExpansionsError {
+    NpmExpansionsError::new(
+        NpmErrorKind::InvalidHttpRequest,
+        "A HTTP request has a malformed chunked body",
+    )
+}
+
+fn payload_too_large(body_size_limit: usize) -> NpmExpansionsError {
+    NpmExpansionsError::new(
+        NpmErrorKind::PayloadTooLarge,
+        &format!("A HTTP request body is larger than the {body_size_limit} byte limit"),
+    )
+}
+
+fn first_values(query_param_values: &HashMap<String, Vec<String>>) -> HashMap<String, String> {
+    query_param_values
+        .iter()
+        .filter_map(|(key, values)| Some((key.to_string(), values.first()?.to_string())))
+        .collect()
+}
+
+/// Decodes an `application/x-www-form-urlencoded` query string component i.e. `+` becomes a
+/// space and `%XX` becomes the byte XX. A `%` not followed by two hex digits is kept as it is.
+fn percent_decode(component: &str) -> Result<String, NpmExpansionsError> {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut position = 0;
+
+    while position < bytes.len() {
+        match bytes[position] {
+            b'+' => decoded.push(b' '),
+            b'%' => match bytes
+                .get(position + 1..position + 3)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    position += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+
+        position += 1;
+    }
+
+    String::from_utf8(decoded).map_err(|_| {
+        NpmExpansionsError::new(
+            NpmErrorKind::InvalidQueryParam,
+            "A query param is not valid UTF-8 once percent decoded",
+        )
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::mock_tcp_stream::MockTcpStream;
+    use test_case::test_case;
+
+    #[test_case("HTTP/1.1", None, true; "http 1.1 default")]
+    #[test_case("HTTP/1.1", Some("close"), false; "http 1.1 close")]
+    #[test_case("HTTP/1.1", Some("Upgrade, CLOSE"), false; "http 1.1 close among options")]
+    #[test_case("HTTP/1.0", None, false; "http 1.0 default")]
+    #[test_case("HTTP/1.0", Some("keep-alive"), true; "http 1.0 keep alive")]
+    #[test_case("HTTP/1.0", Some("keep-alive, close"), false; "http 1.0 keep alive and close")]
+    fn keep_alive(version: &str, connection: Option<&str>, expected: bool) {
+        let headers: HashMap<String, String> = connection
+            .map(|connection| ("Connection".to_string(), connection.to_string()))
+            .into_iter()
+            .collect();
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            &format!("GET / {version}"),
+            headers,
+            HashMap::new(),
+        );
+
+        assert_eq!(request.keep_alive(), expected)
+    }
 
     mod build_tests {
         use super::*;
+        use test_case::test_case;
 
         #[test]
         fn parses_status_line() {
@@ -319,7 +849,7 @@ mod tests {
         }
 
         #[test]
-        fn malformed_single_query_param() {
+        fn flag_query_param() {
             let input_bytes = b"GET /random?search HTTP/1.1\r\n\r\n";
             let mut contents = vec![0u8; 1024];
 
@@ -329,14 +859,17 @@ mod tests {
                 read_data: contents,
                 write_data: Vec::new(),
             };
-            let request = HttpRequest::build(&mut stream);
+            let request = HttpRequest::build(&mut stream).unwrap();
 
-            assert!(request.is_err())
+            assert_eq!(
+                request.query_params(),
+                &HashMap::from([("search".to_string(), "".to_string())])
+            )
         }
 
         #[test]
-        fn malformed_two_single_query_param() {
-            let input_bytes = b"GET /random?search& HTTP/1.1\r\n\r\n";
+        fn flag_query_param_with_trailing_ampersand() {
+            let input_bytes = b"GET /random?search&limit=5& HTTP/1.1\r\n\r\n";
             let mut contents = vec![0u8; 1024];
 
             contents[..input_bytes.len()].clone_from_slice(input_bytes);
@@ -345,9 +878,88 @@ mod tests {
                 read_data: contents,
                 write_data: Vec::new(),
             };
-            let request = HttpRequest::build(&mut stream);
+            let request = HttpRequest::build(&mut stream).unwrap();
 
-            assert!(request.is_err())
+            assert_eq!(
+                request.query_params(),
+                &HashMap::from([
+                    ("search".to_string(), "".to_string()),
+                    ("limit".to_string(), "5".to_string())
+                ])
+            )
+        }
+
+        #[test]
+        fn percent_decodes_query_params() {
+            let input_bytes =
+                b"GET /api/search?query=Nacho%20Pizza+Marinade&na%6De=m%C3%A1s%2B%26%3D HTTP/1.1\r\n\r\n";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert_eq!(
+                request.query_params(),
+                &HashMap::from([
+                    ("query".to_string(), "Nacho Pizza Marinade".to_string()),
+                    ("name".to_string(), "más+&=".to_string())
+                ])
+            )
+        }
+
+        #[test]
+        fn keeps_malformed_percent_escapes() {
+            let input_bytes = b"GET /api/search?query=100%25%2%zz%+f HTTP/1.1\r\n\r\n";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert_eq!(request.query_params()["query"], "100%%2%zz% f")
+        }
+
+        #[test]
+        fn invalid_utf8_query_param() {
+            let input_bytes = b"GET /api/search?query=%C3%28 HTTP/1.1\r\n\r\n";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let error = HttpRequest::build(&mut stream).err().unwrap();
+
+            assert!(matches!(error.kind(), NpmErrorKind::InvalidQueryParam))
+        }
+
+        #[test]
+        fn repeated_query_params() {
+            let input_bytes = b"GET /api/search?tag=food&query=abc&tag=people HTTP/1.1\r\n\r\n";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert_eq!(request.query_param_values("tag"), ["food", "people"]);
+            assert_eq!(request.query_param_values("query"), ["abc"]);
+            assert_eq!(request.query_params()["tag"], "food")
         }
 
         #[test]
@@ -437,17 +1049,58 @@ mod tests {
             let request = HttpRequest::build(&mut stream).unwrap();
 
             assert_eq!(
-                request.headers(),
-                &HashMap::from([
-                    ("Content-Length".to_string(), "0".to_string()),
-                    (
-                        "Accept".to_string(),
-                        "application/text,text/plain;q=0.1".to_string()
-                    ),
-                ])
+                request.headers().iter().collect::<Vec<(&str, &str)>>(),
+                [
+                    ("Accept", "application/text,text/plain;q=0.1"),
+                    ("Content-Length", "0"),
+                ]
             )
         }
 
+        #[test]
+        fn parses_headers_case_insensitively() {
+            let input_bytes =
+                b"GET / HTTP/1.1\r\nACCEPT: text/html\r\nuser-agent: curl\r\naccept: application/json\r\n\r\n";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert_eq!(
+                request.headers().get("Accept"),
+                Some("text/html, application/json")
+            );
+            assert_eq!(request.headers().get("User-Agent"), Some("curl"));
+            assert_eq!(
+                request.headers().iter().collect::<Vec<(&str, &str)>>(),
+                [
+                    ("ACCEPT", "text/html, application/json"),
+                    ("user-agent", "curl")
+                ]
+            )
+        }
+
+        #[test]
+        fn lower_case_content_length() {
+            let input_bytes = b"POST / HTTP/1.1\r\ncontent-LENGTH: 5\r\n\r\nhello world";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert_eq!(request.body(), b"hello")
+        }
+
         #[test]
         fn invalid_status_line() {
             let input_bytes = b"";
@@ -481,6 +1134,7 @@ mod tests {
         }
 
         #[test]
+        #[allow(clippy::match_like_matches_macro, clippy::bool_assert_comparison)]
         fn request_has_no_empty_line() {
             let input_bytes = b"GET / HTTP/1.1\r\n Content-Type: application/json";
             let mut contents = vec![0u8; 1024];
@@ -503,5 +1157,165 @@ mod tests {
 
             assert_eq!(is_correct_error, true)
         }
+
+        #[test]
+        fn parses_body() {
+            let input_bytes = b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert_eq!(request.body(), b"hello world")
+        }
+
+        #[test]
+        fn no_content_length_has_empty_body() {
+            let input_bytes = b"POST / HTTP/1.1\r\n\r\nhello world";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert!(request.body().is_empty())
+        }
+
+        #[test]
+        fn invalid_content_length() {
+            let input_bytes = b"POST / HTTP/1.1\r\nContent-Length: eleven\r\n\r\nhello world";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream);
+
+            assert!(request.is_err())
+        }
+
+        #[test]
+        fn parses_chunked_body() {
+            let input_bytes = b"POST / HTTP/1.1\r\ntransfer-encoding: Chunked\r\n\r\n5;name=value\r\nhello\r\nA\r\n world mas\r\n0\r\nX-Trailer: 1\r\n\r\n";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert_eq!(request.body(), b"hello world mas")
+        }
+
+        #[test]
+        fn empty_chunked_body() {
+            let input_bytes = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream).unwrap();
+
+            assert!(request.body().is_empty())
+        }
+
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nhello\r\n0\r\n\r\n"; "invalid chunk size")]
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n+5\r\nhello\r\n0\r\n\r\n"; "signed chunk size")]
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nhello\r\n0\r\n\r\n"; "chunk longer than size")]
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\nhello"; "unsupported transfer encoding")]
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n5\r\nhello\r\n0\r\n\r\n"; "chunked with content length")]
+        fn invalid_chunked_body(input_bytes: &[u8]) {
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let error = HttpRequest::build(&mut stream).err().unwrap();
+
+            assert!(matches!(error.kind(), NpmErrorKind::InvalidHttpRequest))
+        }
+
+        #[test_case(b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world"; "content length")]
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"; "chunked")]
+        fn body_larger_than_configured_limit(input_bytes: &[u8]) {
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let error = HttpRequest::build_with_options(
+                &mut stream,
+                &RequestOptions {
+                    body_size_limit: 10,
+                },
+            )
+            .err()
+            .unwrap();
+
+            assert!(matches!(error.kind(), NpmErrorKind::PayloadTooLarge))
+        }
+
+        #[test]
+        fn content_length_too_large() {
+            let input_bytes = b"POST / HTTP/1.1\r\nContent-Length: 8001\r\n\r\nhello world";
+            let mut contents = vec![0u8; 1024];
+
+            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+            let mut stream = MockTcpStream {
+                read_data: contents,
+                write_data: Vec::new(),
+            };
+            let request = HttpRequest::build(&mut stream);
+
+            assert!(request.is_err())
+        }
+
+        #[test_case(b"GET / HTTP/1.1\r\nAccept: text"; "headers")]
+        #[test_case(b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello"; "sized body")]
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel"; "chunked body")]
+        fn read_timeout(input_bytes: &[u8]) {
+            // Fails every read after the input as a socket does once its read timeout passes
+            struct TimedOutReader;
+
+            impl Read for TimedOutReader {
+                fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                    Err(io::Error::from(io::ErrorKind::WouldBlock))
+                }
+            }
+
+            let mut reader = BufReader::new(input_bytes.chain(TimedOutReader));
+            let error =
+                HttpRequest::read_from(&mut reader, "127.0.0.1", &RequestOptions::default())
+                    .err()
+                    .unwrap();
+
+            assert!(matches!(error.kind(), NpmErrorKind::RequestTimeout))
+        }
     }
 }
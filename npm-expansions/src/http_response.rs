@@ -1,48 +1,222 @@
-/// A HTTP response object containing the core parts of status line, headers and contents
+use crate::http_headers::HttpHeaders;
+use crate::http_status::HttpStatus;
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use serde::Serialize;
+
+/// A HTTP response object containing the core parts of status line, headers and body. The body
+/// is bytes so that a response may carry binary content e.g. compressed JSON.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus};
+///
+/// let response = HttpResponse::new(HttpStatus::NotFound)
+///     .with_header("Cache-Control", "no-store")
+///     .text("Nothing here");
+///
+/// assert_eq!(
+///     response.into_bytes_vec(),
+///     b"HTTP/1.1 404 Not Found\r\nContent-Length: 12\r\nCache-Control: no-store\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nNothing here"
+/// );
+/// ```
+#[derive(Clone, Debug)]
 pub struct HttpResponse {
-    status_code: String,
-    status_text: String,
-    headers: String,
-    contents: String,
+    status: HttpStatus,
+    headers: HttpHeaders,
+    body: Vec<u8>,
+    send_body: bool,
 }
 
 impl HttpResponse {
-    /// Creates a new response given a status line, headers and contents
-    pub fn new(
-        status_code: &str,
-        status_text: &str,
-        headers: &str,
-        contents: &str,
-    ) -> HttpResponse {
+    /// Creates a new response with the given status, no headers and an empty body
+    pub fn new(status: HttpStatus) -> HttpResponse {
         HttpResponse {
-            status_code: status_code.to_string(),
-            status_text: status_text.to_string(),
-            headers: headers.to_string(),
-            contents: contents.to_string(),
+            status,
+            headers: HttpHeaders::new(),
+            body: Vec::new(),
+            send_body: true,
         }
     }
 
-    /// Returns the status code of the response object
-    pub fn status_code(&self) -> &str {
-        self.status_code.as_str()
+    /// Returns the response with its body left out when it is converted to bytes while keeping
+    /// the Content-Length of the body, as a response to a HEAD request must
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus};
+    ///
+    /// let response = HttpResponse::new(HttpStatus::Ok)
+    ///     .with_body("Hello World!")
+    ///     .without_body();
+    ///
+    /// assert_eq!(response.into_bytes_vec(), b"HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\n");
+    /// ```
+    pub fn without_body(mut self) -> HttpResponse {
+        self.send_body = false;
+        self
+    }
+
+    /// Returns the response with a value added to the given header. A header given several
+    /// values is written as one line per value after the existing headers.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the header e.g. `Connection`
+    /// * `value` - The value of the header e.g. `close`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus};
+    ///
+    /// let response = HttpResponse::new(HttpStatus::Ok)
+    ///     .with_header("Vary", "Origin")
+    ///     .with_header("Connection", "close")
+    ///     .with_header("Vary", "Accept-Encoding");
+    ///
+    /// assert_eq!(
+    ///     response.headers().to_string(),
+    ///     "Vary: Origin\r\nVary: Accept-Encoding\r\nConnection: close"
+    /// );
+    /// ```
+    pub fn with_header(mut self, name: &str, value: &str) -> HttpResponse {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Returns the response with every value of the given headers added in order
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - The headers to add e.g. the pagination headers of a page of results
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{http_headers::HttpHeaders, http_response::HttpResponse, http_status::HttpStatus};
+    ///
+    /// let mut headers = HttpHeaders::new();
+    /// headers.append("X-Total-Count", "25");
+    ///
+    /// let response = HttpResponse::new(HttpStatus::Ok).with_headers(&headers);
+    ///
+    /// assert_eq!(response.headers().get("X-Total-Count"), Some("25"));
+    /// ```
+    pub fn with_headers(mut self, headers: &HttpHeaders) -> HttpResponse {
+        for (name, value) in headers.lines() {
+            self.headers.append(name, value);
+        }
+
+        self
+    }
+
+    /// Returns the response with the given bytes as its body
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The body of the response
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus};
+    ///
+    /// let response = HttpResponse::new(HttpStatus::Ok).with_body(vec![0x1f, 0x8b]);
+    ///
+    /// assert_eq!(response.body(), [0x1f, 0x8b]);
+    /// ```
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> HttpResponse {
+        self.body = body.into();
+        self
+    }
+
+    /// Returns the response with the given text as its body and a plain text `Content-Type`
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The body of the response
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus};
+    ///
+    /// let response = HttpResponse::new(HttpStatus::NotFound).text("NOT FOUND");
+    ///
+    /// assert_eq!(response.headers().get("Content-Type"), Some("text/plain; charset=utf-8"));
+    /// assert_eq!(response.body(), b"NOT FOUND");
+    /// ```
+    pub fn text(mut self, text: &str) -> HttpResponse {
+        self.headers
+            .insert("Content-Type", "text/plain; charset=utf-8");
+        self.with_body(text)
+    }
+
+    /// Returns the response with the given value serialized as its body and a JSON
+    /// `Content-Type`
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to serialize e.g. an expansion
+    ///
+    /// # Failures
+    ///
+    /// The function fails with an internal server error if the value cannot be serialized
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus};
+    ///
+    /// let response = HttpResponse::new(HttpStatus::Ok).json(&["Nice Pistons Mac"]).unwrap();
+    ///
+    /// assert_eq!(response.headers().get("Content-Type"), Some("application/json"));
+    /// assert_eq!(response.body(), br#"["Nice Pistons Mac"]"#);
+    /// ```
+    pub fn json(mut self, value: &impl Serialize) -> Result<HttpResponse, NpmExpansionsError> {
+        let body = serde_json::to_vec(value).map_err(|error| {
+            NpmExpansionsError::new(NpmErrorKind::InternalServerError, &error.to_string())
+        })?;
+
+        self.headers.insert("Content-Type", "application/json");
+        Ok(self.with_body(body))
+    }
+
+    /// Returns the status of the response object
+    pub fn status(&self) -> HttpStatus {
+        self.status
     }
 
-    /// Returns the status line of the response object
+    /// Returns the status code of the response object e.g. 200
+    pub fn status_code(&self) -> u16 {
+        self.status.code()
+    }
+
+    /// Returns the status line of the response object e.g. `200 OK`
     pub fn status_line(&self) -> String {
-        format!("{} {}", self.status_code, self.status_text)
+        self.status.to_string()
     }
 
     /// Returns the headers of the response object
-    pub fn headers(&self) -> &str {
+    pub fn headers(&self) -> &HttpHeaders {
         &self.headers
     }
 
-    /// Returns the contents of the response object
-    pub fn contents(&self) -> &str {
-        &self.contents
+    /// Returns the headers of the response object to be changed e.g. by a middleware
+    pub fn headers_mut(&mut self) -> &mut HttpHeaders {
+        &mut self.headers
+    }
+
+    /// Returns the body of the response object
+    pub fn body(&self) -> &[u8] {
+        &self.body
     }
 
-    /// Converts a request object into a http response vector of bytes
+    /// Converts a response object into a http response vector of bytes. A 304 response has no
+    /// `Content-Length` as it never has a body and the header would describe the body it stands
+    /// in for.
     ///
     /// # Arguments
     ///
@@ -51,38 +225,59 @@ impl HttpResponse {
     /// # Examples
     ///
     /// ```
-    /// use npm_expansions::http_response::HttpResponse;
+    /// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus};
     ///
-    /// let response = HttpResponse::new(
-    ///     "200",
-    ///     "OK",
-    ///     "Content-Type: application/json;q=0.5",
-    ///     "Hello World!",
-    /// ).into_bytes_vec();
+    /// let response = HttpResponse::new(HttpStatus::Ok)
+    ///     .with_header("Content-Type", "application/json;q=0.5")
+    ///     .with_body("Hello World!")
+    ///     .into_bytes_vec();
     /// let example_response = "HTTP/1.1 200 OK\r\nContent-Length: 12\r\nContent-Type: application/json;q=0.5\r\n\r\nHello World!".as_bytes().to_vec();
     ///
     /// assert_eq!(response, example_response);
     /// ```
+    pub fn into_bytes_vec(&self) -> Vec<u8> {
+        let mut bytes = self.head_bytes();
+
+        if self.send_body {
+            bytes.extend_from_slice(&self.body);
+        }
+
+        bytes
+    }
+
+    /// Returns the number of bytes the response is written as, without copying its body
     ///
-    /// # Panics
+    /// # Examples
     ///
-    /// The function panics if string formatting via format! panics
+    /// ```
+    /// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus};
     ///
-    pub fn into_bytes_vec(&self) -> Vec<u8> {
-        let status = self.status_line();
-        let contents = self.contents();
-        let headers = self.headers();
-        let length = contents.len();
-
-        if headers.is_empty() {
-            format!("HTTP/1.1 {status}\r\nContent-Length: {length}\r\n\r\n{contents}")
-                .as_bytes()
-                .to_vec()
-        } else {
-            format!("HTTP/1.1 {status}\r\nContent-Length: {length}\r\n{headers}\r\n\r\n{contents}")
-                .as_bytes()
-                .to_vec()
+    /// let response = HttpResponse::new(HttpStatus::Ok).with_body("Hello World!");
+    ///
+    /// assert_eq!(response.byte_count(), response.into_bytes_vec().len());
+    /// assert_eq!(response.clone().without_body().byte_count(), 39);
+    /// ```
+    pub fn byte_count(&self) -> usize {
+        let body_len = if self.send_body { self.body.len() } else { 0 };
+
+        self.head_bytes().len() + body_len
+    }
+
+    /// Returns the status line and headers of the response followed by the empty line
+    fn head_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!("HTTP/1.1 {}\r\n", self.status).into_bytes();
+
+        if self.status != HttpStatus::NotModified {
+            bytes.extend_from_slice(format!("Content-Length: {}\r\n", self.body.len()).as_bytes());
+        }
+
+        for (name, value) in self.headers.lines() {
+            bytes.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
         }
+
+        bytes.extend_from_slice(b"\r\n");
+
+        bytes
     }
 }
 
@@ -95,19 +290,16 @@ mod tests {
 
         #[test]
         fn correct_reponse_with_headers() {
-            let response = HttpResponse::new(
-                "200",
-                "OK",
-                "Content-Type: application/json;q=0.5",
-                "Hello World!",
-            );
+            let response = HttpResponse::new(HttpStatus::Ok)
+                .with_header("Content-Type", "application/json;q=0.5")
+                .with_body("Hello World!");
 
             assert_eq!(response.into_bytes_vec(), "HTTP/1.1 200 OK\r\nContent-Length: 12\r\nContent-Type: application/json;q=0.5\r\n\r\nHello World!".as_bytes().to_vec())
         }
 
         #[test]
         fn correct_reponse_without_headers() {
-            let response = HttpResponse::new("200", "OK", "", "Hello World!");
+            let response = HttpResponse::new(HttpStatus::Ok).with_body("Hello World!");
 
             assert_eq!(
                 response.into_bytes_vec(),
@@ -116,5 +308,67 @@ mod tests {
                     .to_vec()
             )
         }
+
+        #[test]
+        fn correct_reponse_with_added_header() {
+            let response = HttpResponse::new(HttpStatus::Ok)
+                .with_body("Hello World!")
+                .with_header("Connection", "close");
+
+            assert_eq!(
+                response.into_bytes_vec(),
+                "HTTP/1.1 200 OK\r\nContent-Length: 12\r\nConnection: close\r\n\r\nHello World!"
+                    .as_bytes()
+                    .to_vec()
+            )
+        }
+
+        #[test]
+        fn correct_reponse_with_repeated_header() {
+            let response = HttpResponse::new(HttpStatus::Ok)
+                .with_header("Set-Cookie", "a=1")
+                .with_header("Set-Cookie", "b=2");
+
+            assert_eq!(
+                response.into_bytes_vec(),
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\n\r\n"
+                    .as_bytes()
+                    .to_vec()
+            )
+        }
+
+        #[test]
+        fn not_modified_response_without_content_length() {
+            let response = HttpResponse::new(HttpStatus::NotModified).with_header("ETag", "\"1\"");
+
+            assert_eq!(
+                response.into_bytes_vec(),
+                b"HTTP/1.1 304 Not Modified\r\nETag: \"1\"\r\n\r\n".to_vec()
+            )
+        }
+
+        #[test]
+        fn correct_reponse_with_binary_body() {
+            let response = HttpResponse::new(HttpStatus::Ok).with_body(vec![0, 159, 146, 150]);
+
+            assert_eq!(
+                response.into_bytes_vec(),
+                b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n\x00\x9f\x92\x96".to_vec()
+            )
+        }
+    }
+
+    #[test]
+    fn json_and_text_replace_content_type() {
+        let response = HttpResponse::new(HttpStatus::Ok)
+            .text("plain")
+            .json(&"json")
+            .unwrap();
+
+        assert_eq!(
+            response.headers().get_all("Content-Type"),
+            ["application/json"]
+        );
+        assert_eq!(response.body(), br#""json""#);
     }
 }
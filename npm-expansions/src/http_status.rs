@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// The status of a HTTP response, which pairs each status code with its canonical reason phrase
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::http_status::HttpStatus;
+///
+/// assert_eq!(HttpStatus::NotFound.code(), 404);
+/// assert_eq!(HttpStatus::NotFound.reason_phrase(), "Not Found");
+/// assert_eq!(HttpStatus::NotFound.to_string(), "404 Not Found");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HttpStatus {
+    /// 200 OK
+    Ok,
+    /// 201 Created
+    Created,
+    /// 304 Not Modified
+    NotModified,
+    /// 400 Bad Request
+    BadRequest,
+    /// 404 Not Found
+    NotFound,
+    /// 405 Method Not Allowed
+    MethodNotAllowed,
+    /// 406 Not Acceptable
+    NotAcceptable,
+    /// 408 Request Timeout
+    RequestTimeout,
+    /// 409 Conflict
+    Conflict,
+    /// 413 Content Too Large
+    PayloadTooLarge,
+    /// 415 Unsupported Media Type
+    UnsupportedMediaType,
+    /// 500 Internal Server Error
+    InternalServerError,
+    /// 503 Service Unavailable
+    ServiceUnavailable,
+}
+
+impl HttpStatus {
+    /// Returns the three digit status code e.g. 404
+    pub fn code(self) -> u16 {
+        match self {
+            HttpStatus::Ok => 200,
+            HttpStatus::Created => 201,
+            HttpStatus::NotModified => 304,
+            HttpStatus::BadRequest => 400,
+            HttpStatus::NotFound => 404,
+            HttpStatus::MethodNotAllowed => 405,
+            HttpStatus::NotAcceptable => 406,
+            HttpStatus::RequestTimeout => 408,
+            HttpStatus::Conflict => 409,
+            HttpStatus::PayloadTooLarge => 413,
+            HttpStatus::UnsupportedMediaType => 415,
+            HttpStatus::InternalServerError => 500,
+            HttpStatus::ServiceUnavailable => 503,
+        }
+    }
+
+    /// Returns the reason phrase given to the status code by RFC 9110 e.g. `Not Found`
+    pub fn reason_phrase(self) -> &'static str {
+        match self {
+            HttpStatus::Ok => "OK",
+            HttpStatus::Created => "Created",
+            HttpStatus::NotModified => "Not Modified",
+            HttpStatus::BadRequest => "Bad Request",
+            HttpStatus::NotFound => "Not Found",
+            HttpStatus::MethodNotAllowed => "Method Not Allowed",
+            HttpStatus::NotAcceptable => "Not Acceptable",
+            HttpStatus::RequestTimeout => "Request Timeout",
+            HttpStatus::Conflict => "Conflict",
+            HttpStatus::PayloadTooLarge => "Content Too Large",
+            HttpStatus::UnsupportedMediaType => "Unsupported Media Type",
+            HttpStatus::InternalServerError => "Internal Server Error",
+            HttpStatus::ServiceUnavailable => "Service Unavailable",
+        }
+    }
+}
+
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason_phrase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(HttpStatus::Ok, "200 OK")]
+    #[test_case(HttpStatus::NotModified, "304 Not Modified")]
+    #[test_case(HttpStatus::MethodNotAllowed, "405 Method Not Allowed")]
+    #[test_case(HttpStatus::PayloadTooLarge, "413 Content Too Large")]
+    #[test_case(HttpStatus::ServiceUnavailable, "503 Service Unavailable")]
+    fn status_line(status: HttpStatus, status_line: &str) {
+        assert_eq!(status.to_string(), status_line);
+    }
+}
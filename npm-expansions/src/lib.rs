@@ -5,12 +5,30 @@
 #![warn(missing_docs)]
 #![warn(rustdoc::missing_doc_code_examples)]
 
+/// A typed container of values shared with every route handler
+pub mod app_state;
+
+/// The content codings responses may be compressed with and their negotiation
+pub mod compression;
+
 /// A series of default HTTP response methods
 pub mod default_controller;
 
+/// A npm expansion record and its metadata
+pub mod expansion;
+
 /// A static database of npm expansions and methods to search them
 pub mod expansions_model;
 
+/// A series of functions for checking that expansions spell out the NPM acronym
+pub mod expansion_validator;
+
+/// A utility for reloading the expansions model when its backing file changes
+pub mod expansions_reloader;
+
+/// Hooks which run around every routed request e.g. access logging and CORS
+pub mod middleware;
+
 /// A series of helper functions and modules for processing mime types
 pub mod mime_type;
 
@@ -26,12 +44,30 @@ pub mod npm_controller;
 /// A generic error used to represent server failings
 pub mod npm_expansion_error;
 
+/// A utility for splitting API results into pages using the limit and offset query params
+pub mod pagination;
+
+/// Formatting and parsing of the dates used by HTTP headers e.g. `Last-Modified`
+pub mod http_date;
+
+/// A case-insensitive collection of HTTP request and response headers
+pub mod http_headers;
+
 /// A representation of the different parts of a HTTP request
 pub mod http_request;
 
 /// A representation of a HTTP response
 pub mod http_response;
 
+/// The status codes of HTTP responses and their reason phrases
+pub mod http_status;
+
+/// The options and results of searching the expansions model
+pub mod search;
+
+/// A trigram index used to narrow searches of the expansions model
+pub mod search_index;
+
 /// A series of utilities to handle incoming HTTP requests and to response to them
 pub mod router;
 
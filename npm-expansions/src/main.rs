@@ -1,37 +1,54 @@
 use npm_expansions::{
-    expansions_model::ExpansionsModel,
-    npm_controller::{ControllerFunction, NpmController},
-    router::Router,
-    stream_handler,
+    app_state::AppState,
+    expansion_validator::ValidationOptions,
+    expansions_model::{ExpansionsAccess, ExpansionsModel},
+    expansions_reloader::ExpansionsReloader,
+    http_request::RequestOptions,
+    middleware::{
+        AcceptNegotiation, AccessLog, CacheControl, Compression, ConditionalGet, Cors, RequestId,
+    },
+    npm_controller::NpmController,
+    router::{controller, Router},
+    search::SearchAlgorithm,
+    stream_handler::{self, ConnectionOptions},
     thread_pool::ThreadPool,
 };
-use once_cell::sync::Lazy;
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use std::{env, net::TcpListener};
-
-static EXPANSIONS_MODEL: Lazy<Arc<RwLock<ExpansionsModel>>> =
-    Lazy::new(|| Arc::new(RwLock::new(ExpansionsModel::build("rsc/expansions.txt"))));
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+use std::{
+    env,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+    process, thread,
+    time::Duration,
+};
 
-static ROUTER: Lazy<Arc<Router>> = Lazy::new(|| {
-    Arc::new(Router::new(HashMap::from([
-        (
-            "GET /api/random HTTP/1.1",
-            NpmController::random as ControllerFunction,
-        ),
-        (
-            "GET /api/all HTTP/1.1",
-            NpmController::all as ControllerFunction,
-        ),
-        (
-            "GET /api/search HTTP/1.1",
-            NpmController::search as ControllerFunction,
-        ),
-    ])))
-});
+static DEFAULT_EXPANSIONS_PATH: &str = "rsc/expansions.txt";
 
 static DEFAULT_THREAD_COUNT: usize = 2;
 
+static DEFAULT_QUEUE_SIZE: usize = 64;
+
+static DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 10;
+
+static DEFAULT_RELOAD_INTERVAL_SECS: u64 = 30;
+
+static DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+static MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+
+static MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+static ALL_CACHE_CONTROL: &str = "public, no-cache";
+
+static RANDOM_CACHE_CONTROL: &str = "no-store";
+
 fn main() {
     let thread_count = env::var("THREAD_COUNT")
         .map(|count| count.parse::<usize>().unwrap_or(DEFAULT_THREAD_COUNT))
@@ -42,6 +59,76 @@ fn main() {
         thread_count
     );
 
+    let expansions_path =
+        env::var("EXPANSIONS_PATH").unwrap_or(DEFAULT_EXPANSIONS_PATH.to_string());
+
+    let validation_options = ValidationOptions {
+        strict: env::var("EXPANSIONS_STRICT").is_ok(),
+        ..ValidationOptions::default()
+    };
+
+    let mut expansions_model =
+        match ExpansionsModel::build_with_options(&expansions_path, &validation_options) {
+            Ok(expansions_model) => expansions_model,
+            Err(error) => {
+                println!("Failed to load expansions. Error Message: {}", error);
+                process::exit(1);
+            }
+        };
+
+    expansions_model.set_write_back(env::var("EXPANSIONS_WRITE_BACK").is_ok());
+    expansions_model.set_search_algorithm(
+        env::var("SEARCH_ALGORITHM")
+            .map(|algorithm| algorithm.parse::<SearchAlgorithm>().unwrap_or_default())
+            .unwrap_or_default(),
+    );
+
+    println!(
+        "Loaded expansions from: {} with {}",
+        expansions_path,
+        expansions_model.validation_report()
+    );
+
+    let expansions_model = Arc::new(RwLock::new(expansions_model));
+
+    let reload_interval = env::var("EXPANSIONS_RELOAD_INTERVAL")
+        .map(|secs| secs.parse::<u64>().unwrap_or(DEFAULT_RELOAD_INTERVAL_SECS))
+        .unwrap_or(DEFAULT_RELOAD_INTERVAL_SECS);
+
+    if let Err(watch_err) = ExpansionsReloader::new(&expansions_path).watch(
+        expansions_model.clone(),
+        Duration::from_secs(reload_interval),
+    ) {
+        println!("Failed to start expansions reloader: {}", watch_err)
+    }
+
+    let shared_expansions_model: Arc<RwLock<dyn ExpansionsAccess>> = expansions_model;
+    let router = Arc::new(build_router(AppState::new().with(shared_expansions_model)));
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let default_options = ConnectionOptions::default();
+    let connection_options = ConnectionOptions {
+        request_options: RequestOptions {
+            body_size_limit: env::var("BODY_SIZE_LIMIT")
+                .map(|limit| {
+                    limit
+                        .parse::<usize>()
+                        .unwrap_or(default_options.request_options.body_size_limit)
+                })
+                .unwrap_or(default_options.request_options.body_size_limit),
+        },
+        idle_timeout: timeout_var("KEEP_ALIVE_TIMEOUT", default_options.idle_timeout),
+        max_requests: env::var("MAX_REQUESTS_PER_CONNECTION")
+            .map(|max| max.parse::<usize>().unwrap_or(default_options.max_requests))
+            .unwrap_or(default_options.max_requests),
+        header_timeout: timeout_var("HEADER_READ_TIMEOUT", default_options.header_timeout),
+        body_timeout: timeout_var("BODY_READ_TIMEOUT", default_options.body_timeout),
+        write_timeout: timeout_var("WRITE_TIMEOUT", default_options.write_timeout),
+        request_timeout: timeout_var("REQUEST_TIMEOUT", default_options.request_timeout),
+        shutdown: shutdown.clone(),
+    };
+
     let development_env = env::var("DEV").is_ok();
     let addr = if development_env {
         "0.0.0.0:8000"
@@ -51,16 +138,63 @@ fn main() {
 
     let listener = TcpListener::bind(addr).unwrap();
 
+    if let Err(signal_err) = watch_shutdown_signals(&listener, shutdown.clone()) {
+        println!("Failed to watch for shutdown signals: {}", signal_err)
+    }
+
+    let queue_size = env::var("QUEUE_SIZE")
+        .ok()
+        .and_then(|size| size.parse::<usize>().ok())
+        .filter(|size| *size > 0)
+        .unwrap_or(DEFAULT_QUEUE_SIZE);
+
+    let shutdown_timeout = env::var("SHUTDOWN_TIMEOUT")
+        .map(|secs| secs.parse::<u64>().unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS))
+        .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS);
+
+    let pool = ThreadPool::with_queue_size(thread_count, queue_size)
+        .with_shutdown_timeout(Duration::from_secs(shutdown_timeout));
+
+    let mut accept_backoff = Duration::ZERO;
+
     for stream in listener.incoming() {
-        let mut stream = stream.unwrap();
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let mut stream = match stream {
+            Ok(stream) => {
+                accept_backoff = Duration::ZERO;
+                stream
+            }
+            // Errors such as running out of file descriptors tend to repeat, so each retry waits
+            // twice as long as the last rather than spinning
+            Err(accept_err) => {
+                accept_backoff = (accept_backoff * 2).clamp(MIN_ACCEPT_BACKOFF, MAX_ACCEPT_BACKOFF);
+                println!(
+                    "Failed to accept connection: {}. Retrying in {}ms",
+                    accept_err,
+                    accept_backoff.as_millis()
+                );
+                thread::sleep(accept_backoff);
+                continue;
+            }
+        };
 
-        let pool = ThreadPool::new(thread_count);
+        if pool.is_full() {
+            stream_handler::reject_connection(&mut stream)
+                .unwrap_or_else(|error| println!("Failed to reject connection: {}", error));
+            continue;
+        }
+
+        let router = router.clone();
+        let connection_options = connection_options.clone();
 
         let execution_result = pool.execute(move || {
-            stream_handler::handle_connection(
+            stream_handler::handle_connection_with_options(
                 &mut stream,
-                ROUTER.clone(),
-                EXPANSIONS_MODEL.clone(),
+                router,
+                &connection_options,
             )
             .unwrap_or_else(|error| println!("Fatal server error. Error Message: {}", error));
         });
@@ -69,4 +203,115 @@ fn main() {
             println!("Failed to execute clojure: {:?}", execution_err)
         }
     }
+
+    println!(
+        "Shutting down. Waiting up to {}s for in flight requests",
+        shutdown_timeout
+    );
+
+    let panic_count = pool.shutdown();
+
+    println!(
+        "Server stopped. {} requests panicked while it was running",
+        panic_count
+    );
+}
+
+/// Builds the router of the API routes and its middlewares, giving its handlers the app state
+fn build_router(state: AppState) -> Router {
+    let router = Router::new(HashMap::from([
+        ("GET /api/random", controller(NpmController::random)),
+        ("GET /api/all", controller(NpmController::all)),
+        ("GET /api/search", controller(NpmController::search)),
+        (
+            "GET /api/expansions/{id}",
+            controller(NpmController::expansion),
+        ),
+        (
+            "POST /api/expansions",
+            controller(NpmController::add_expansion),
+        ),
+    ]))
+    .with_state(state)
+    .with_middleware(AccessLog)
+    .with_middleware(RequestId::new());
+
+    let router = match env::var("CORS_ALLOWED_ORIGINS") {
+        Ok(origins) => {
+            let origins: Vec<&str> = origins
+                .split(',')
+                .map(|origin| origin.trim())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+
+            router.with_middleware(Cors::new(&origins))
+        }
+        Err(_) => router,
+    };
+
+    let compression_threshold = env::var("COMPRESSION_THRESHOLD")
+        .map(|threshold| {
+            threshold
+                .parse::<usize>()
+                .unwrap_or(DEFAULT_COMPRESSION_THRESHOLD)
+        })
+        .unwrap_or(DEFAULT_COMPRESSION_THRESHOLD);
+
+    router
+        .with_middleware(AcceptNegotiation::new("/api", &["application/json"]))
+        .with_middleware(
+            CacheControl::new()
+                .with_policy("/api/all", ALL_CACHE_CONTROL)
+                .with_policy("/api/random", RANDOM_CACHE_CONTROL),
+        )
+        .with_middleware(ConditionalGet)
+        .with_middleware(Compression::new(compression_threshold).with_cached_path("/api/all"))
+}
+
+/// Reads a timeout in whole seconds from an environment variable, using the default when it is
+/// unset, not a number or zero
+fn timeout_var(name: &str, default: Duration) -> Duration {
+    env::var(name)
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+        .unwrap_or(default)
+}
+
+/// Sets the shutdown flag on the first SIGINT or SIGTERM and connects to the listener so that a
+/// blocked accept returns and sees the flag. A second signal exits immediately.
+fn watch_shutdown_signals(
+    listener: &TcpListener,
+    shutdown: Arc<AtomicBool>,
+) -> std::io::Result<()> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    let mut wake_addr = listener.local_addr()?;
+
+    if wake_addr.ip().is_unspecified() {
+        wake_addr.set_ip(match wake_addr {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+
+    thread::Builder::new().spawn(move || {
+        for signal in signals.forever() {
+            if shutdown.swap(true, Ordering::SeqCst) {
+                println!("Received signal {} again, exiting immediately", signal);
+                process::exit(1);
+            }
+
+            println!(
+                "Received signal {}, no longer accepting connections",
+                signal
+            );
+
+            if let Err(connect_err) = TcpStream::connect(wake_addr) {
+                println!("Failed to wake the listener: {}", connect_err)
+            }
+        }
+    })?;
+
+    Ok(())
 }
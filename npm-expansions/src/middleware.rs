@@ -0,0 +1,1070 @@
+use crate::compression::ContentEncoding;
+use crate::default_controller::DefaultController;
+use crate::expansion::fnv1a;
+use crate::http_date::parse_http_date;
+use crate::http_request::HttpRequest;
+use crate::http_response::HttpResponse;
+use crate::http_status::HttpStatus;
+use crate::mime_type::matcher;
+use crate::npm_expansion_error::NpmExpansionsError;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// The header holding the id of a request and its response
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// The longest request id accepted from a client
+const REQUEST_ID_SIZE_LIMIT: usize = 128;
+
+/// How long in seconds a browser may cache the answer to a CORS preflight request
+const CORS_MAX_AGE_SECS: u64 = 600;
+
+/// A hook run by the router around every request it routes, so that concerns shared by every
+/// route e.g. logging are kept out of the controller functions.
+///
+/// Middlewares run in the order they were added to the router before the request is routed and
+/// in the reverse order after, so the first middleware added sees the request first and the
+/// response last.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{
+///     http_request::HttpRequest,
+///     http_response::HttpResponse,
+///     middleware::Middleware,
+///     npm_expansion_error::NpmExpansionsError,
+/// };
+///
+/// struct PoweredBy;
+///
+/// impl Middleware for PoweredBy {
+///     fn after(
+///         &self,
+///         _request: &HttpRequest,
+///         response: HttpResponse,
+///     ) -> Result<HttpResponse, NpmExpansionsError> {
+///         Ok(response.with_header("X-Powered-By", "npm-expansions"))
+///     }
+/// }
+/// ```
+pub trait Middleware: Send + Sync {
+    /// Runs before the request is routed. The request may be changed e.g. to add a header. When
+    /// a response is returned the request is answered with it instead of being routed and only
+    /// the middlewares which have already run see the response. An error is answered by the
+    /// DefaultController in the same way as an error from a controller function.
+    fn before(
+        &self,
+        _request: &mut HttpRequest,
+    ) -> Result<Option<HttpResponse>, NpmExpansionsError> {
+        Ok(None)
+    }
+
+    /// Runs with the response to the request, which may be changed or replaced
+    fn after(
+        &self,
+        _request: &HttpRequest,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        Ok(response)
+    }
+}
+
+/// Logs every request and the status and size of its response in the common log format
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{middleware::AccessLog, router::Router};
+/// use std::collections::HashMap;
+///
+/// let router = Router::new(HashMap::new()).with_middleware(AccessLog);
+/// ```
+pub struct AccessLog;
+
+impl AccessLog {
+    fn log_line(request: &HttpRequest, response: &HttpResponse) -> String {
+        let referer = request.headers().get("Referer");
+        let user_agent = request.headers().get("User-Agent");
+
+        format!(
+            "{} - - [{}] \"{}\" {} {} \"{}\" \"{}\"",
+            request.host(),
+            chrono::Utc::now().format("%d/%b/%Y:%H:%M:%S %z"),
+            request.status_line(),
+            response.status_code(),
+            response.byte_count(),
+            referer.unwrap_or("-"),
+            user_agent.unwrap_or("-"),
+        )
+    }
+}
+
+impl Middleware for AccessLog {
+    fn after(
+        &self,
+        request: &HttpRequest,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        println!("{}", Self::log_line(request, &response));
+
+        Ok(response)
+    }
+}
+
+/// Gives every request an id in its `X-Request-Id` header, keeping an id sent by the client when
+/// it is at most 128 visible ASCII characters, and returns the id in the same header of the
+/// response so that a request can be traced through the logs of several services.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{middleware::RequestId, router::Router};
+/// use std::collections::HashMap;
+///
+/// let router = Router::new(HashMap::new()).with_middleware(RequestId::new());
+/// ```
+pub struct RequestId {
+    prefix: u32,
+    count: AtomicU64,
+}
+
+impl RequestId {
+    /// Creates a middleware whose ids are a random prefix, so that ids stay unique across
+    /// restarts, followed by a count of the requests given an id
+    pub fn new() -> RequestId {
+        RequestId {
+            prefix: rand::random(),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn next_id(&self) -> String {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+
+        format!("{:08x}-{:08x}", self.prefix, count)
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        RequestId::new()
+    }
+}
+
+impl Middleware for RequestId {
+    fn before(
+        &self,
+        request: &mut HttpRequest,
+    ) -> Result<Option<HttpResponse>, NpmExpansionsError> {
+        let valid_id = request.headers().get(REQUEST_ID_HEADER).is_some_and(|id| {
+            !id.is_empty()
+                && id.len() <= REQUEST_ID_SIZE_LIMIT
+                && id.bytes().all(|byte| byte.is_ascii_graphic())
+        });
+
+        if !valid_id {
+            let id = self.next_id();
+            request.headers_mut().insert(REQUEST_ID_HEADER, &id);
+        }
+
+        Ok(None)
+    }
+
+    fn after(
+        &self,
+        request: &HttpRequest,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        match request.headers().get(REQUEST_ID_HEADER) {
+            Some(id) => Ok(response.with_header(REQUEST_ID_HEADER, id)),
+            None => Ok(response),
+        }
+    }
+}
+
+/// Lets web pages served from other origins call the API by answering CORS preflight requests
+/// and adding the `Access-Control-Allow-Origin` header to responses for allowed origins.
+/// Requests from other origins are routed as usual but without CORS headers, so browsers keep
+/// their responses from the page.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{middleware::Cors, router::Router};
+/// use std::collections::HashMap;
+///
+/// let cors = Cors::new(&["https://www.npm-expansions.com"])
+///     .with_exposed_headers(&["X-Total-Count", "Link"]);
+/// let router = Router::new(HashMap::new()).with_middleware(cors);
+/// ```
+pub struct Cors {
+    allowed_origins: Vec<String>,
+    allowed_methods: String,
+    allowed_headers: String,
+    exposed_headers: String,
+}
+
+impl Cors {
+    /// Creates a middleware allowing the given origins e.g. `https://www.npm-expansions.com`, or
+    /// any origin given `*`. Preflight requests are allowed the `GET`, `HEAD`, `POST` and
+    /// `OPTIONS` methods and the `Content-Type` header.
+    pub fn new(allowed_origins: &[&str]) -> Cors {
+        Cors {
+            allowed_origins: allowed_origins
+                .iter()
+                .map(|origin| origin.to_string())
+                .collect(),
+            allowed_methods: "GET, HEAD, POST, OPTIONS".to_string(),
+            allowed_headers: "Content-Type".to_string(),
+            exposed_headers: String::new(),
+        }
+    }
+
+    /// Returns the middleware allowing the given methods in preflight requests
+    pub fn with_allowed_methods(mut self, allowed_methods: &[&str]) -> Cors {
+        self.allowed_methods = allowed_methods.join(", ");
+        self
+    }
+
+    /// Returns the middleware allowing the given request headers in preflight requests
+    pub fn with_allowed_headers(mut self, allowed_headers: &[&str]) -> Cors {
+        self.allowed_headers = allowed_headers.join(", ");
+        self
+    }
+
+    /// Returns the middleware letting pages read the given response headers e.g. `Link`
+    pub fn with_exposed_headers(mut self, exposed_headers: &[&str]) -> Cors {
+        self.exposed_headers = exposed_headers.join(", ");
+        self
+    }
+
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.allows_any_origin() || self.allowed_origins.iter().any(|allowed| allowed == origin)
+    }
+}
+
+impl Middleware for Cors {
+    fn before(
+        &self,
+        request: &mut HttpRequest,
+    ) -> Result<Option<HttpResponse>, NpmExpansionsError> {
+        let is_preflight = request.method() == "OPTIONS"
+            && request.headers().contains("Access-Control-Request-Method");
+
+        match request.headers().get("Origin") {
+            Some(origin) if is_preflight && self.allows(origin) => Ok(Some(
+                HttpResponse::new(HttpStatus::Ok)
+                    .with_header("Access-Control-Allow-Methods", &self.allowed_methods)
+                    .with_header("Access-Control-Allow-Headers", &self.allowed_headers)
+                    .with_header("Access-Control-Max-Age", &CORS_MAX_AGE_SECS.to_string()),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    fn after(
+        &self,
+        request: &HttpRequest,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        let origin = match request.headers().get("Origin") {
+            Some(origin) if self.allows(origin) => origin,
+            _ if self.allows_any_origin() => return Ok(response),
+            _ => return Ok(response.with_header("Vary", "Origin")),
+        };
+
+        let mut response = if self.allows_any_origin() {
+            response.with_header("Access-Control-Allow-Origin", "*")
+        } else {
+            response
+                .with_header("Access-Control-Allow-Origin", origin)
+                .with_header("Vary", "Origin")
+        };
+
+        if !self.exposed_headers.is_empty() {
+            response = response.with_header("Access-Control-Expose-Headers", &self.exposed_headers);
+        }
+
+        Ok(response)
+    }
+}
+
+/// Answers requests under a path prefix which do not accept any of the media types produced by
+/// its routes with a 406 response, and requests with a malformed `Accept` header with a 400
+/// response, before they are routed. A request without an `Accept` header accepts anything.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{middleware::AcceptNegotiation, router::Router};
+/// use std::collections::HashMap;
+///
+/// let router = Router::new(HashMap::new())
+///     .with_middleware(AcceptNegotiation::new("/api", &["application/json"]));
+/// ```
+pub struct AcceptNegotiation {
+    path_prefix: String,
+    produces: Vec<&'static str>,
+}
+
+impl AcceptNegotiation {
+    /// Creates a middleware for the routes whose paths start with the given segments e.g. `/api`,
+    /// or every route given `/`, which produce the given media types
+    pub fn new(path_prefix: &str, produces: &[&'static str]) -> AcceptNegotiation {
+        AcceptNegotiation {
+            path_prefix: path_prefix.trim_end_matches('/').to_string(),
+            produces: produces.to_vec(),
+        }
+    }
+
+    fn applies_to(&self, path: &str) -> bool {
+        path.strip_prefix(&self.path_prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+impl Middleware for AcceptNegotiation {
+    fn before(
+        &self,
+        request: &mut HttpRequest,
+    ) -> Result<Option<HttpResponse>, NpmExpansionsError> {
+        if !self.applies_to(request.path()) {
+            return Ok(None);
+        }
+
+        let accept_header = request.headers().get("Accept").unwrap_or("*/*");
+
+        match matcher::best_match(self.produces.clone(), accept_header) {
+            Ok(best) if best.is_empty() => Ok(Some(
+                HttpResponse::new(HttpStatus::NotAcceptable)
+                    .text(&format!("Please accept {}", self.produces.join(", "))),
+            )),
+            Ok(_) => Ok(None),
+            Err(_) => Ok(Some(DefaultController::client_error())),
+        }
+    }
+}
+
+/// Compresses the bodies of text and JSON responses of at least a threshold size with the
+/// content coding the request prefers in its `Accept-Encoding` header, adding the
+/// `Content-Encoding` header and `Vary: Accept-Encoding` so that caches keep each coding apart.
+/// Smaller bodies are sent as is, as compressing them saves too little to be worth the time.
+///
+/// An `ETag` already given to the response is suffixed with the coding e.g. `"1a2b-gzip"`, as
+/// each coding is a different representation.
+///
+/// The compressed bodies of the cached paths are kept between requests for each request
+/// target, including its query, and only compressed again when the `ETag` of the response
+/// changes, which suits routes like `/api/all` whose large body only changes with the
+/// expansions model. Responses without an `ETag` are never kept.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{middleware::Compression, router::Router};
+/// use std::collections::HashMap;
+///
+/// let router = Router::new(HashMap::new())
+///     .with_middleware(Compression::new(1024).with_cached_path("/api/all"));
+/// ```
+pub struct Compression {
+    threshold: usize,
+    cached_paths: Vec<String>,
+    cache: Mutex<HashMap<(String, ContentEncoding), CompressedBody>>,
+}
+
+/// The most compressed bodies kept by the compression middleware, as each query of a cached
+/// path is kept apart
+const MAX_COMPRESSED_BODIES: usize = 64;
+
+/// A compressed body kept by the compression middleware with the `ETag` of the response it was
+/// compressed from
+struct CompressedBody {
+    etag: String,
+    encoded: Vec<u8>,
+}
+
+impl Compression {
+    /// Creates a middleware compressing bodies of at least the given number of bytes
+    pub fn new(threshold: usize) -> Compression {
+        Compression {
+            threshold,
+            cached_paths: Vec::new(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the middleware keeping the compressed bodies of the given path e.g. `/api/all`
+    pub fn with_cached_path(mut self, path: &str) -> Compression {
+        self.cached_paths.push(path.to_string());
+        self
+    }
+
+    fn compresses(&self, response: &HttpResponse) -> bool {
+        let compressible_type =
+            response
+                .headers()
+                .get("Content-Type")
+                .is_some_and(|content_type| {
+                    content_type.starts_with("text/") || content_type.contains("json")
+                });
+
+        compressible_type
+            && response.body().len() >= self.threshold
+            && !response.headers().contains("Content-Encoding")
+    }
+
+    fn encode(
+        &self,
+        request: &HttpRequest,
+        encoding: ContentEncoding,
+        response: &HttpResponse,
+    ) -> Result<Vec<u8>, NpmExpansionsError> {
+        let cached = self
+            .cached_paths
+            .iter()
+            .any(|cached_path| cached_path == request.path());
+
+        let etag = match response.headers().get("ETag") {
+            Some(etag) if cached => etag,
+            _ => return encoding.encode(response.body()),
+        };
+
+        let key = (request.target().to_string(), encoding);
+        let mut cache = self
+            .cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(compressed) = cache.get(&key).filter(|compressed| compressed.etag == etag) {
+            return Ok(compressed.encoded.clone());
+        }
+
+        let encoded = encoding.encode(response.body())?;
+
+        // Bodies compressed before the response last changed are dropped first
+        if cache.len() >= MAX_COMPRESSED_BODIES && !cache.contains_key(&key) {
+            cache.retain(|_, compressed| compressed.etag == etag);
+        }
+
+        if cache.len() < MAX_COMPRESSED_BODIES || cache.contains_key(&key) {
+            cache.insert(
+                key,
+                CompressedBody {
+                    etag: etag.to_string(),
+                    encoded: encoded.clone(),
+                },
+            );
+        }
+
+        Ok(encoded)
+    }
+}
+
+impl Middleware for Compression {
+    fn after(
+        &self,
+        request: &HttpRequest,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        if !self.compresses(&response) {
+            return Ok(response);
+        }
+
+        let response = response.with_header("Vary", "Accept-Encoding");
+
+        // A request accepting no coding is still sent the body as is rather than refused
+        let encoding = ContentEncoding::negotiate(request.headers().get("Accept-Encoding"))
+            .unwrap_or(ContentEncoding::Identity);
+
+        if encoding == ContentEncoding::Identity {
+            return Ok(response);
+        }
+
+        let encoded = self.encode(request, encoding, &response)?;
+        let mut response = response
+            .with_header("Content-Encoding", encoding.name())
+            .with_body(encoded);
+
+        if let Some(etag) = response.headers().get("ETag") {
+            let etag = format!("{}-{}\"", etag.trim_end_matches('"'), encoding.name());
+            response.headers_mut().insert("ETag", &etag);
+        }
+
+        Ok(response)
+    }
+}
+
+/// Answers `GET` and `HEAD` requests whose copy of a response is still current with a 304
+/// response without a body. A successful response without an `ETag` is given one hashed from
+/// its body. A request is answered with a 304 response when its `If-None-Match` header lists
+/// the `ETag` of the response or is `*`, or, without `If-None-Match`, when its
+/// `If-Modified-Since` header is no earlier than the `Last-Modified` header of the response.
+///
+/// The 304 response keeps the headers of the response other than those describing its body
+/// e.g. `Content-Type`, so that caches can update their copy. Tags are compared weakly and
+/// without the coding suffix added by [`Compression`], as each coding holds the same content.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{middleware::ConditionalGet, router::Router};
+/// use std::collections::HashMap;
+///
+/// let router = Router::new(HashMap::new()).with_middleware(ConditionalGet);
+/// ```
+pub struct ConditionalGet;
+
+impl ConditionalGet {
+    /// Returns true if the client's copy of the response is still current. Used by controllers
+    /// which can tell so before building the body of the response.
+    pub(crate) fn is_not_modified(request: &HttpRequest, response: &HttpResponse) -> bool {
+        if let Some(if_none_match) = request.headers().get("If-None-Match") {
+            let etag = response.headers().get("ETag").map(comparable_entity_tag);
+
+            return if_none_match.split(',').map(str::trim).any(|candidate| {
+                candidate == "*" || Some(comparable_entity_tag(candidate)) == etag
+            });
+        }
+
+        let if_modified_since = request
+            .headers()
+            .get("If-Modified-Since")
+            .and_then(parse_http_date);
+        let last_modified = response
+            .headers()
+            .get("Last-Modified")
+            .and_then(parse_http_date);
+
+        matches!(
+            (if_modified_since, last_modified),
+            (Some(if_modified_since), Some(last_modified)) if last_modified <= if_modified_since
+        )
+    }
+
+    /// Returns the 304 response for the given response
+    pub(crate) fn not_modified(response: &HttpResponse) -> HttpResponse {
+        response
+            .headers()
+            .lines()
+            .filter(|(name, _)| {
+                !name.to_ascii_lowercase().starts_with("content-")
+                    || name.eq_ignore_ascii_case("Content-Location")
+            })
+            .fold(
+                HttpResponse::new(HttpStatus::NotModified),
+                |not_modified, (name, value)| not_modified.with_header(name, value),
+            )
+    }
+}
+
+impl Middleware for ConditionalGet {
+    fn after(
+        &self,
+        request: &HttpRequest,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        if !matches!(request.method(), "GET" | "HEAD") || response.status() != HttpStatus::Ok {
+            return Ok(response);
+        }
+
+        let response = if response.headers().contains("ETag") {
+            response
+        } else {
+            let etag = format!("\"{:016x}\"", fnv1a(response.body()));
+            response.with_header("ETag", &etag)
+        };
+
+        if ConditionalGet::is_not_modified(request, &response) {
+            Ok(ConditionalGet::not_modified(&response))
+        } else {
+            Ok(response)
+        }
+    }
+}
+
+/// Returns the opaque part of an entity tag without the `W/` prefix of a weak tag or the coding
+/// suffix added by `Compression` e.g. `1a2b` for `W/"1a2b-gzip"`
+fn comparable_entity_tag(entity_tag: &str) -> &str {
+    let opaque_tag = entity_tag
+        .strip_prefix("W/")
+        .unwrap_or(entity_tag)
+        .trim_matches('"');
+
+    ContentEncoding::PREFERENCE
+        .iter()
+        .find_map(|encoding| opaque_tag.strip_suffix(&format!("-{}", encoding.name())))
+        .unwrap_or(opaque_tag)
+}
+
+/// Adds a `Cache-Control` header to the successful and 304 responses of the routes given a
+/// policy, telling clients and proxies how they may keep the response e.g. only until it is
+/// revalidated for `/api/all` but not at all for `/api/random`. A response which already has a
+/// `Cache-Control` header keeps it.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{middleware::CacheControl, router::Router};
+/// use std::collections::HashMap;
+///
+/// let cache_control = CacheControl::new()
+///     .with_policy("/api/all", "public, no-cache")
+///     .with_policy("/api/random", "no-store");
+/// let router = Router::new(HashMap::new()).with_middleware(cache_control);
+/// ```
+#[derive(Default)]
+pub struct CacheControl {
+    policies: HashMap<String, String>,
+}
+
+impl CacheControl {
+    /// Creates a middleware without any policies
+    pub fn new() -> CacheControl {
+        CacheControl::default()
+    }
+
+    /// Returns the middleware giving the route with the given path e.g. `/api/all` the given
+    /// `Cache-Control` policy e.g. `no-store`
+    pub fn with_policy(mut self, path: &str, policy: &str) -> CacheControl {
+        self.policies.insert(path.to_string(), policy.to_string());
+        self
+    }
+}
+
+impl Middleware for CacheControl {
+    fn after(
+        &self,
+        request: &HttpRequest,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        let cacheable = matches!(response.status(), HttpStatus::Ok | HttpStatus::NotModified);
+
+        match self.policies.get(request.path()) {
+            Some(policy) if cacheable && !response.headers().contains("Cache-Control") => {
+                Ok(response.with_header("Cache-Control", policy))
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn request(status_line: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest::new(
+            "127.0.0.1",
+            status_line,
+            headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<HashMap<String, String>>(),
+            HashMap::new(),
+        )
+    }
+
+    fn ok_response() -> HttpResponse {
+        HttpResponse::new(HttpStatus::Ok).with_body("OK")
+    }
+
+    #[test]
+    fn access_log_line() {
+        let request = request(
+            "GET /api/random HTTP/1.1",
+            &[
+                ("User-Agent", "curl/8.0"),
+                ("Referer", "https://npm-expansions.com"),
+            ],
+        );
+
+        let log_line = AccessLog::log_line(&request, &ok_response());
+
+        assert!(log_line.starts_with("127.0.0.1 - - ["));
+        assert!(log_line.ends_with(
+            "] \"GET /api/random HTTP/1.1\" 200 40 \"https://npm-expansions.com\" \"curl/8.0\""
+        ));
+    }
+
+    #[test]
+    fn request_id_is_added() {
+        let request_id = RequestId::new();
+        let mut first = request("GET / HTTP/1.1", &[]);
+        let mut second = request("GET / HTTP/1.1", &[]);
+
+        request_id.before(&mut first).unwrap();
+        request_id.before(&mut second).unwrap();
+
+        let first_id = first.headers().get("X-Request-Id").unwrap().to_string();
+        let response = request_id.after(&first, ok_response()).unwrap();
+
+        assert_ne!(
+            Some(first_id.as_str()),
+            second.headers().get("X-Request-Id")
+        );
+        assert_eq!(
+            response.headers().get("X-Request-Id"),
+            Some(first_id.as_str())
+        );
+    }
+
+    #[test_case("abc-123", true; "valid id")]
+    #[test_case("", false; "empty id")]
+    #[test_case("abc 123", false; "id with space")]
+    #[test_case(&"a".repeat(129), false; "long id")]
+    fn request_id_from_client(id: &str, kept: bool) {
+        let mut request = request("GET / HTTP/1.1", &[("x-request-id", id)]);
+
+        RequestId::new().before(&mut request).unwrap();
+
+        assert_eq!(request.headers().get("X-Request-Id") == Some(id), kept);
+        assert_eq!(request.headers().get_all("X-Request-Id").len(), 1);
+    }
+
+    #[test]
+    fn cors_allowed_origin() {
+        let cors = Cors::new(&["https://a.com"]).with_exposed_headers(&["Link"]);
+        let request = request("GET /api/all HTTP/1.1", &[("Origin", "https://a.com")]);
+
+        let response = cors.after(&request, ok_response()).unwrap();
+
+        assert_eq!(
+            response.headers().to_string(),
+            "Access-Control-Allow-Origin: https://a.com\r\nVary: Origin\r\nAccess-Control-Expose-Headers: Link"
+        );
+    }
+
+    #[test]
+    fn cors_disallowed_origin() {
+        let cors = Cors::new(&["https://a.com"]);
+        let mut request = request(
+            "OPTIONS /api/all HTTP/1.1",
+            &[
+                ("Origin", "https://b.com"),
+                ("Access-Control-Request-Method", "GET"),
+            ],
+        );
+
+        assert!(cors.before(&mut request).unwrap().is_none());
+        assert_eq!(
+            cors.after(&request, ok_response())
+                .unwrap()
+                .headers()
+                .to_string(),
+            "Vary: Origin"
+        );
+    }
+
+    #[test]
+    fn cors_any_origin() {
+        let cors = Cors::new(&["*"]);
+        let request = request("GET /api/all HTTP/1.1", &[("Origin", "https://b.com")]);
+
+        let response = cors.after(&request, ok_response()).unwrap();
+
+        assert_eq!(
+            response.headers().to_string(),
+            "Access-Control-Allow-Origin: *"
+        );
+    }
+
+    #[test]
+    fn cors_preflight() {
+        let cors = Cors::new(&["https://a.com"]).with_allowed_methods(&["GET", "POST"]);
+        let mut request = request(
+            "OPTIONS /api/expansions HTTP/1.1",
+            &[
+                ("Origin", "https://a.com"),
+                ("Access-Control-Request-Method", "POST"),
+            ],
+        );
+
+        let response = cors.before(&mut request).unwrap().unwrap();
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(
+            response.headers().to_string(),
+            "Access-Control-Allow-Methods: GET, POST\r\nAccess-Control-Allow-Headers: Content-Type\r\nAccess-Control-Max-Age: 600"
+        );
+    }
+
+    #[test_case("/api/random", Some("application/json"), None; "acceptable")]
+    #[test_case("/api/random", Some("text/html;q=0.9, */*;q=0.1"), None; "wildcard")]
+    #[test_case("/api/random", None, None; "no accept header")]
+    #[test_case("/api/random", Some("text/html"), Some(406); "unacceptable")]
+    #[test_case("/api", Some("text/html"), Some(406); "prefix itself")]
+    #[test_case("/api/random", Some("text/"), Some(400); "malformed")]
+    #[test_case("/apix", Some("text/html"), None; "other prefix")]
+    #[test_case("/", Some("text/html"), None; "outside prefix")]
+    fn accept_negotiation(path: &str, accept: Option<&str>, status_code: Option<u16>) {
+        let negotiation = AcceptNegotiation::new("/api", &["application/json"]);
+        let headers: Vec<(&str, &str)> = accept
+            .map(|accept| ("Accept", accept))
+            .into_iter()
+            .collect();
+        let mut request = request(&format!("GET {path} HTTP/1.1"), &headers);
+
+        let response = negotiation.before(&mut request).unwrap();
+
+        assert_eq!(
+            response.as_ref().map(|response| response.status_code()),
+            status_code
+        );
+    }
+
+    fn json_response(body: &str) -> HttpResponse {
+        HttpResponse::new(HttpStatus::Ok)
+            .with_header("Content-Type", "application/json")
+            .with_body(body)
+    }
+
+    #[test_case(Some("gzip"), Some("gzip"); "gzip")]
+    #[test_case(Some("gzip, deflate, br"), Some("br"); "server preference")]
+    #[test_case(Some("br;q=0.5, deflate"), Some("deflate"); "q-values")]
+    #[test_case(Some("identity"), None; "identity")]
+    #[test_case(Some("identity;q=0, *;q=0"), None; "nothing acceptable")]
+    #[test_case(None, None; "no accept encoding")]
+    fn compression_negotiates(accept_encoding: Option<&str>, content_encoding: Option<&str>) {
+        let headers: Vec<(&str, &str)> = accept_encoding
+            .map(|accept_encoding| ("Accept-Encoding", accept_encoding))
+            .into_iter()
+            .collect();
+        let request = request("GET /api/all HTTP/1.1", &headers);
+        let body = r#"["Nice People Meet"]"#.repeat(10);
+
+        let response = Compression::new(100)
+            .after(&request, json_response(&body))
+            .unwrap();
+
+        assert_eq!(response.headers().get("Vary"), Some("Accept-Encoding"));
+        assert_eq!(response.headers().get("Content-Encoding"), content_encoding);
+        assert_eq!(
+            response.body() == body.as_bytes(),
+            content_encoding.is_none()
+        );
+    }
+
+    #[test_case(json_response("[]"); "small body")]
+    #[test_case(HttpResponse::new(HttpStatus::Ok).with_header("Content-Type", "image/png").with_body(vec![0; 200]); "binary body")]
+    #[test_case(json_response(&"a".repeat(200)).with_header("Content-Encoding", "gzip"); "encoded body")]
+    fn compression_skips(response: HttpResponse) {
+        let request = request("GET /api/all HTTP/1.1", &[("Accept-Encoding", "gzip")]);
+
+        let compressed = Compression::new(100)
+            .after(&request, response.clone())
+            .unwrap();
+
+        assert_eq!(compressed.body(), response.body());
+        assert_eq!(compressed.headers().get("Vary"), None);
+    }
+
+    #[test]
+    fn compression_caches_until_etag_changes() {
+        let compression = Compression::new(10).with_cached_path("/api/all");
+        let request = request("GET /api/all HTTP/1.1", &[("Accept-Encoding", "deflate")]);
+        let tagged = |body: &str, etag: &str| json_response(body).with_header("ETag", etag);
+
+        let first = compression
+            .after(&request, tagged(&"a".repeat(100), "\"1\""))
+            .unwrap();
+        // The same ETag is answered with the kept body without compressing the new one
+        let second = compression
+            .after(&request, tagged(&"b".repeat(100), "\"1\""))
+            .unwrap();
+        let changed = compression
+            .after(&request, tagged(&"b".repeat(100), "\"2\""))
+            .unwrap();
+
+        assert_eq!(first.body(), second.body());
+        assert_ne!(changed.body(), first.body());
+        assert_eq!(compression.cache.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn compression_caches_each_query() {
+        let compression = Compression::new(10).with_cached_path("/api/all");
+        let first_page = request(
+            "GET /api/all?limit=1 HTTP/1.1",
+            &[("Accept-Encoding", "gzip")],
+        );
+        let second_page = request(
+            "GET /api/all?limit=1&offset=1 HTTP/1.1",
+            &[("Accept-Encoding", "gzip")],
+        );
+        let tagged = |body: &str| json_response(body).with_header("ETag", "\"1\"");
+
+        let first = compression
+            .after(&first_page, tagged(&"a".repeat(100)))
+            .unwrap();
+        let second = compression
+            .after(&second_page, tagged(&"b".repeat(100)))
+            .unwrap();
+        let repeated = compression
+            .after(&first_page, tagged(&"a".repeat(100)))
+            .unwrap();
+
+        assert_ne!(first.body(), second.body());
+        assert_eq!(repeated.body(), first.body());
+        assert_eq!(compression.cache.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn compression_keeps_limited_bodies() {
+        let compression = Compression::new(10).with_cached_path("/api/all");
+        let response = json_response(&"a".repeat(100)).with_header("ETag", "\"1\"");
+
+        for offset in 0..=MAX_COMPRESSED_BODIES {
+            let request = request(
+                &format!("GET /api/all?offset={offset} HTTP/1.1"),
+                &[("Accept-Encoding", "gzip")],
+            );
+
+            compression.after(&request, response.clone()).unwrap();
+        }
+
+        assert_eq!(
+            compression.cache.lock().unwrap().len(),
+            MAX_COMPRESSED_BODIES
+        );
+    }
+
+    #[test]
+    fn compression_does_not_cache_untagged_responses() {
+        let compression = Compression::new(10).with_cached_path("/api/all");
+        let request = request("GET /api/all HTTP/1.1", &[("Accept-Encoding", "gzip")]);
+
+        let response = compression
+            .after(&request, json_response(&"a".repeat(100)))
+            .unwrap();
+
+        assert_eq!(response.headers().get("Content-Encoding"), Some("gzip"));
+        assert!(compression.cache.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn compression_does_not_cache_other_paths() {
+        let compression = Compression::new(10).with_cached_path("/api/all");
+        let request = request("GET /api/search HTTP/1.1", &[("Accept-Encoding", "gzip")]);
+        let response = json_response(&"a".repeat(100)).with_header("ETag", "\"1\"");
+
+        let response = compression.after(&request, response).unwrap();
+
+        assert_eq!(response.headers().get("Content-Encoding"), Some("gzip"));
+        assert!(compression.cache.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn compression_suffixes_etag() {
+        let request = request("GET /api/all HTTP/1.1", &[("Accept-Encoding", "br")]);
+        let response = json_response(&"a".repeat(100)).with_header("ETag", "W/\"1a2b\"");
+
+        let compressed = Compression::new(10).after(&request, response).unwrap();
+
+        assert_eq!(compressed.headers().get_all("ETag"), ["W/\"1a2b-br\""]);
+    }
+
+    fn conditional_response() -> HttpResponse {
+        json_response("[]")
+            .with_header("ETag", "\"1a2b\"")
+            .with_header("Last-Modified", "Sun, 06 Nov 1994 08:49:37 GMT")
+            .with_header("Vary", "Accept-Encoding")
+    }
+
+    #[test_case(&[("If-None-Match", "\"1a2b\"")], 304; "matching etag")]
+    #[test_case(&[("If-None-Match", "\"9f9f\", W/\"1a2b\"")], 304; "matching weak etag in list")]
+    #[test_case(&[("If-None-Match", "*")], 304; "any etag")]
+    #[test_case(&[("If-None-Match", "\"1a2b-gzip\"")], 304; "compressed etag")]
+    #[test_case(&[("If-None-Match", "\"9f9f\"")], 200; "other etag")]
+    #[test_case(&[("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT")], 304; "not modified since")]
+    #[test_case(&[("If-Modified-Since", "Mon, 07 Nov 1994 08:49:37 GMT")], 304; "modified before")]
+    #[test_case(&[("If-Modified-Since", "Sat, 05 Nov 1994 08:49:37 GMT")], 200; "modified since")]
+    #[test_case(&[("If-Modified-Since", "yesterday")], 200; "invalid date")]
+    #[test_case(&[("If-None-Match", "\"9f9f\""), ("If-Modified-Since", "Mon, 07 Nov 1994 08:49:37 GMT")], 200; "etag over date")]
+    #[test_case(&[], 200; "unconditional")]
+    fn conditional_get(headers: &[(&str, &str)], status_code: u16) {
+        let request = request("GET /api/all HTTP/1.1", headers);
+
+        let response = ConditionalGet
+            .after(&request, conditional_response())
+            .unwrap();
+
+        assert_eq!(response.status_code(), status_code);
+        assert_eq!(response.headers().get("ETag"), Some("\"1a2b\""));
+    }
+
+    #[test]
+    fn conditional_get_not_modified_headers() {
+        let request = request("HEAD /api/all HTTP/1.1", &[("If-None-Match", "\"1a2b\"")]);
+
+        let response = ConditionalGet
+            .after(&request, conditional_response())
+            .unwrap();
+
+        assert_eq!(response.body(), b"");
+        assert_eq!(
+            response.headers().to_string(),
+            "ETag: \"1a2b\"\r\nLast-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\nVary: Accept-Encoding"
+        );
+    }
+
+    #[test]
+    fn conditional_get_tags_body() {
+        let unconditional = request("GET /api/random HTTP/1.1", &[]);
+
+        let first = ConditionalGet
+            .after(&unconditional, json_response("[1]"))
+            .unwrap();
+        let second = ConditionalGet
+            .after(&unconditional, json_response("[2]"))
+            .unwrap();
+        let etag = first.headers().get("ETag").unwrap();
+        let repeated = request("GET /api/random HTTP/1.1", &[("If-None-Match", etag)]);
+
+        assert_ne!(second.headers().get("ETag"), Some(etag));
+        assert_eq!(
+            ConditionalGet
+                .after(&repeated, json_response("[1]"))
+                .unwrap()
+                .status_code(),
+            304
+        );
+    }
+
+    #[test_case("POST /api/expansions HTTP/1.1", HttpStatus::Ok; "other method")]
+    #[test_case("GET /api/all HTTP/1.1", HttpStatus::NotFound; "unsuccessful response")]
+    fn conditional_get_skips(status_line: &str, status: HttpStatus) {
+        let request = request(status_line, &[("If-None-Match", "*")]);
+        let response = HttpResponse::new(status).with_body("[]");
+
+        let response = ConditionalGet.after(&request, response).unwrap();
+
+        assert_eq!(response.status(), status);
+        assert!(!response.headers().contains("ETag"));
+    }
+
+    #[test_case("/api/all", HttpStatus::Ok, Some("public, max-age=60"); "long policy")]
+    #[test_case("/api/random", HttpStatus::Ok, Some("no-store"); "no store policy")]
+    #[test_case("/api/all", HttpStatus::NotModified, Some("public, max-age=60"); "not modified")]
+    #[test_case("/api/all", HttpStatus::InternalServerError, None; "error")]
+    #[test_case("/api/search", HttpStatus::Ok, None; "no policy")]
+    fn cache_control(path: &str, status: HttpStatus, cache_control: Option<&str>) {
+        let middleware = CacheControl::new()
+            .with_policy("/api/all", "public, max-age=60")
+            .with_policy("/api/random", "no-store");
+        let request = request(&format!("GET {path} HTTP/1.1"), &[]);
+
+        let response = middleware
+            .after(&request, HttpResponse::new(status))
+            .unwrap();
+
+        assert_eq!(response.headers().get("Cache-Control"), cache_control);
+    }
+}
@@ -1,31 +1,103 @@
-use crate::expansions_model::ExpansionsAccess;
+use crate::expansion::{content_id, Expansion};
+use crate::expansion_validator::is_npm_expansion;
+use crate::expansions_model::{corpus_hash, ExpansionsAccess};
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use crate::search::{ScoredExpansion, SearchOptions, SearchResults};
+use std::time::SystemTime;
 
 /// A mock expansions model for testing purposes
 pub struct MockExpansionsModel {
-    expansions: Vec<String>,
+    expansions: Vec<Expansion>,
+    corpus_hash: String,
+}
+
+impl MockExpansionsModel {
+    /// Creates a mock expansions model holding the given expansion texts
+    pub fn with_expansions(texts: &[&str]) -> MockExpansionsModel {
+        let expansions: Vec<Expansion> = texts
+            .iter()
+            .map(|text| {
+                let mut expansion = Expansion::new(text);
+                expansion.set_id(&content_id(text));
+                expansion
+            })
+            .collect();
+
+        MockExpansionsModel {
+            corpus_hash: corpus_hash(&expansions),
+            expansions,
+        }
+    }
 }
 
 impl ExpansionsAccess for MockExpansionsModel {
-    fn all(&self) -> &Vec<String> {
+    fn all(&self) -> &Vec<Expansion> {
         &self.expansions
     }
 
-    fn random_expansion(&self) -> String {
-        "no please manager".to_string()
+    fn random_expansion(&self) -> &Expansion {
+        &self.expansions[0]
     }
 
-    fn search(&self, _query: &str) -> Vec<String> {
-        self.expansions[0..10].to_owned()
+    fn search(&self, _query: &str, options: &SearchOptions) -> SearchResults<'_> {
+        SearchResults::rank(
+            self.expansions.iter().map(|expansion| ScoredExpansion {
+                expansion,
+                score: 1.0,
+            }),
+            options,
+        )
+    }
+
+    fn add_expansion(
+        &mut self,
+        mut expansion: Expansion,
+    ) -> Result<&Expansion, NpmExpansionsError> {
+        if !is_npm_expansion(expansion.text()) {
+            return Err(NpmExpansionsError::from(NpmErrorKind::InvalidExpansion));
+        }
+
+        if self
+            .expansions
+            .iter()
+            .any(|existing| existing.text() == expansion.text())
+        {
+            return Err(NpmExpansionsError::from(NpmErrorKind::DuplicateExpansion));
+        }
+
+        expansion.set_id(&content_id(expansion.text()));
+        self.expansions.push(expansion);
+        self.corpus_hash = corpus_hash(&self.expansions);
+
+        Ok(self.expansions.last().unwrap())
+    }
+
+    fn corpus_hash(&self) -> &str {
+        &self.corpus_hash
+    }
+
+    fn last_modified(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH
     }
 }
 
 impl Default for MockExpansionsModel {
     fn default() -> Self {
-        MockExpansionsModel {
-            expansions: "Nacho Pizza Marinade\nNacho Portion Monitor\nNacho Portmanteau Meltdown\nNacho Printing Machine\nNachos Pillage Milwaukee\nNachos Preventing Motivation\nNadie Programa más\nNagging Penguin Matriarchs\nNahi Pata Mujhe!\nNail Polish Makeover\nNail Polishing Minions\nNaive Pac Man\nNaive Props Mutation\nNaive Puppets Marching"
-                .lines()
-                .map(|line| line.to_string())
-                .collect()
-        }
+        MockExpansionsModel::with_expansions(&[
+            "Nacho Pizza Marinade",
+            "Nacho Portion Monitor",
+            "Nacho Portmanteau Meltdown",
+            "Nacho Printing Machine",
+            "Nachos Pillage Milwaukee",
+            "Nachos Preventing Motivation",
+            "Nadie Programa más",
+            "Nagging Penguin Matriarchs",
+            "Nahi Pata Mujhe!",
+            "Nail Polish Makeover",
+            "Nail Polishing Minions",
+            "Naive Pac Man",
+            "Naive Props Mutation",
+            "Naive Puppets Marching",
+        ])
     }
 }
@@ -6,6 +6,7 @@ use std::{
     io,
     io::{Error, Read, Write},
     net::TcpStream,
+    time::Duration,
 };
 
 /// A mock of a TCP stream for testing purposes
@@ -26,7 +27,7 @@ impl Read for MockTcpStream {
 
 impl Write for MockTcpStream {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
-        self.write_data = Vec::from(buf);
+        self.write_data.extend_from_slice(buf);
 
         Ok(buf.len())
     }
@@ -36,10 +37,17 @@ impl Write for MockTcpStream {
     }
 }
 
-/// Trait exposing TcpStream ip address functionality
+/// Trait exposing TcpStream ip address and timeout functionality
 pub trait TcpAddr {
     /// Returns the socket address of the remote peer of this TCP connection.
     fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Sets how long a read waits for data before failing. None means reads wait forever.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Sets how long a write waits for the peer to accept data before failing. None means writes
+    /// wait forever.
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
 }
 
 impl TcpAddr for MockTcpStream {
@@ -49,10 +57,26 @@ impl TcpAddr for MockTcpStream {
             8080,
         )))
     }
+
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 impl TcpAddr for TcpStream {
     fn peer_addr(&self) -> io::Result<SocketAddr> {
         self.peer_addr()
     }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_write_timeout(timeout)
+    }
 }
@@ -1,22 +1,103 @@
+use crate::expansion::Expansion;
 use crate::expansions_model::ExpansionsAccess;
+use crate::http_date::format_http_date;
 use crate::http_request::HttpRequest;
 use crate::http_response::HttpResponse;
+use crate::http_status::HttpStatus;
+use crate::middleware::ConditionalGet;
 use crate::mime_type::matcher;
-use crate::npm_expansion_error::NpmExpansionsError;
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use crate::pagination::Pagination;
+use crate::search::{ScoredExpansion, SearchAlgorithm, SearchOptions};
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, RwLock};
 
 /// A collection of functions which accept a request and expansions model and use
-/// them to produce JSON responses
+/// them to produce JSON responses. Requests which do not accept JSON are expected to be answered
+/// by the `AcceptNegotiation` middleware before reaching them.
 pub struct NpmController {}
 
+/// The number of search results returned when a request does not give a limit
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+/// The JSON body expected when submitting a new npm expansion
+#[derive(Deserialize)]
+struct NewExpansion {
+    expansion: String,
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// An expansion returned in the text format along with its id
+#[derive(Serialize)]
+struct TextExpansion<'a> {
+    id: &'a str,
+    #[serde(rename = "npm-expansion")]
+    npm_expansion: &'a str,
+}
+
+impl<'a> From<&'a Expansion> for TextExpansion<'a> {
+    fn from(expansion: &'a Expansion) -> TextExpansion<'a> {
+        TextExpansion {
+            id: expansion.id(),
+            npm_expansion: expansion.text(),
+        }
+    }
+}
+
+/// An expansion returned by a search in the text format along with its similarity to the search
+/// query
+#[derive(Serialize)]
+struct ScoredText<'a> {
+    #[serde(flatten)]
+    expansion: TextExpansion<'a>,
+    score: f64,
+}
+
+/// An expansion record returned by a search along with its similarity to the search query
+#[derive(Serialize)]
+struct ScoredRecord<'a> {
+    #[serde(flatten)]
+    expansion: &'a Expansion,
+    score: f64,
+}
+
+/// The shape of the expansions returned to the client, chosen through the `format` query param.
+/// `text` returns the expansion text and its id while `record` returns the full expansion record
+/// including its id, author, tags and date added.
+enum ResponseFormat {
+    Text,
+    Record,
+}
+
+impl ResponseFormat {
+    fn from_request(request: &HttpRequest) -> Option<ResponseFormat> {
+        match request
+            .query_params()
+            .get("format")
+            .map(|format| format.as_str())
+        {
+            None | Some("text") => Some(ResponseFormat::Text),
+            Some("record") => Some(ResponseFormat::Record),
+            Some(_) => None,
+        }
+    }
+}
+
 /// The function signature of NpmController functions
 pub type ControllerFunction =
     fn(&HttpRequest, Arc<RwLock<dyn ExpansionsAccess>>) -> Result<HttpResponse, NpmExpansionsError>;
 
 impl NpmController {
-    /// Returns a vector byte representation of a json object containing a random npm expansion.
+    /// Returns a vector byte representation of a json object containing a random npm expansion and its id.
+    /// ```json
+    /// { "id": "4f5ac2a2f4e1d0b1", "npm-expansion": "Nice Pistons Mac" }
+    /// ```
+    /// When the request has the query param `format=record` the full expansion record is returned instead.
     /// ```json
-    /// { "npm-expansion": "Nice Pistons Mac" }
+    /// { "id": "4f5ac2a2f4e1d0b1", "expansion": "Nice Pistons Mac", "author": null, "tags": [], "date_added": null }
     /// ```
     ///
     /// # Arguments
@@ -40,57 +121,92 @@ impl NpmController {
     ///
     /// assert!(response.is_ok());
     /// ```
+    pub fn random(
+        request: &HttpRequest,
+        expansions_model: Arc<RwLock<dyn ExpansionsAccess>>,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        let format = match ResponseFormat::from_request(request) {
+            Some(format) => format,
+            None => return invalid_format_response(),
+        };
+
+        let expansions_model = expansions_model
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let expansion = expansions_model.random_expansion();
+
+        expansion_response(HttpResponse::new(HttpStatus::Ok), expansion, &format)
+    }
+
+    /// Returns a vector byte representation of a json object containing the npm expansion whose id
+    /// matches the `id` path param of the request. Unknown ids produce a 404 response.
+    /// ```json
+    /// { "id": "4f5ac2a2f4e1d0b1", "npm-expansion": "Nice Pistons Mac" }
+    /// ```
+    /// When the request has the query param `format=record` the full expansion record is returned instead.
     ///
-    /// # Failures
+    /// # Arguments
     ///
-    /// The function fails if the given request has invalid headers
+    /// * `request` - An incoming HTTP request with an `id` path param
+    ///
+    /// # Examples
     ///
-    /// ```rust,should_error
-    /// // fails if the given request has invalid headers
+    /// ```
     /// use npm_expansions::{
     ///     npm_controller::NpmController,
     ///     http_request::HttpRequest,
     ///     mock_expansions_model::MockExpansionsModel,
     ///     expansions_model::ExpansionsAccess,
+    ///     expansion::content_id,
     /// };
     /// use std::{collections::HashMap, sync::{Arc, RwLock}};
     ///
     /// let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
-    /// let request = HttpRequest::new("127.0.0.1", "GET /random HTTP/1.1", HashMap::from([("Accept".to_string(), "text/".to_string())]), HashMap::new());
+    /// let request = HttpRequest::new("127.0.0.1", "GET /expansions/id HTTP/1.1", HashMap::from([("Accept".to_string(), "application/json".to_string())]), HashMap::new())
+    ///     .with_path_params(HashMap::from([("id".to_string(), content_id("Nacho Pizza Marinade"))]));
+    /// let response = NpmController::expansion(&request, mock_expansions_model);
     ///
-    /// NpmController::random(&request, mock_expansions_model);
+    /// assert_eq!(response.unwrap().status_code(), 200);
     /// ```
-    pub fn random(
+    pub fn expansion(
         request: &HttpRequest,
         expansions_model: Arc<RwLock<dyn ExpansionsAccess>>,
     ) -> Result<HttpResponse, NpmExpansionsError> {
-        let headers = request.headers();
-        let accept_header = headers.get("Accept").or_else(|| headers.get("accept"));
-        let best = matcher::best_match(
-            Vec::from(["application/json"]),
-            accept_header.unwrap_or(&"".to_string()),
-        )?;
-
-        let response = match best.as_str() {
-            "application/json" => HttpResponse::new(
-                "200",
-                "OK",
-                "Content-Type: application/json",
-                &format!(
-                    "{{\"npm-expansion\": \"{}\"}}",
-                    expansions_model.read().unwrap().random_expansion()
-                ),
-            ),
-            _ => not_acceptable_response(),
+        let format = match ResponseFormat::from_request(request) {
+            Some(format) => format,
+            None => return invalid_format_response(),
         };
 
-        Ok(response)
+        let id = request.path_param("id").unwrap_or_default();
+        let expansions_model = expansions_model
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        match expansions_model.find(id) {
+            Some(expansion) => {
+                expansion_response(HttpResponse::new(HttpStatus::Ok), expansion, &format)
+            }
+            None => error_response(
+                HttpStatus::NotFound,
+                &format!("No expansion has the id {id}"),
+            ),
+        }
     }
 
-    /// Returns a vector byte representation of a json array containing all npm expansions
+    /// Returns a vector byte representation of a json array containing all npm expansions and their ids
     /// ```json
-    /// ["Nice Pistons Mac", "Nicole Pasta Mcdougle"]
+    /// [{ "id": "4f5ac2a2f4e1d0b1", "npm-expansion": "Nice Pistons Mac" }, { "id": "9c1e0b7a3d2f4e56", "npm-expansion": "Nicole Pasta Mcdougle" }]
     /// ```
+    /// When the request has the query param `format=record` an array of full expansion records is returned instead.
+    ///
+    /// The optional `limit` and `offset` query params return a single page of expansions. The response
+    /// has an `X-Total-Count` header with the number of expansions and a `Link` header pointing to the
+    /// next and previous pages. Invalid `limit` or `offset` values produce a 400 response.
+    ///
+    /// The response has an `ETag` of the corpus hash of the expansions model and its
+    /// `Last-Modified` time, so that clients can ask for the expansions only once they change. A
+    /// request whose `If-None-Match` or `If-Modified-Since` header shows its copy is still current
+    /// is answered with a 304 response without serializing the expansions.
     ///
     /// # Arguments
     ///
@@ -113,63 +229,55 @@ impl NpmController {
     ///
     /// assert!(response.is_ok());
     /// ```
-    ///
-    /// # Failures
-    ///
-    /// The function fails if the given request has invalid headers
-    ///
-    /// ```rust,should_error
-    /// // fails if the given request has invalid headers
-    /// use npm_expansions::{
-    ///     npm_controller::NpmController,
-    ///     http_request::HttpRequest,
-    ///     mock_expansions_model::MockExpansionsModel,
-    ///     expansions_model::ExpansionsAccess,
-    /// };
-    /// use std::{collections::HashMap, sync::{Arc, RwLock}};
-    ///
-    /// let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
-    /// let request = HttpRequest::new("127.0.0.1", "GET /all HTTP/1.1", HashMap::from([("Accept".to_string(), "text/".to_string())]), HashMap::new());
-    ///
-    /// NpmController::all(&request, mock_expansions_model);
-    /// ```
     pub fn all(
         request: &HttpRequest,
         expansions_model: Arc<RwLock<dyn ExpansionsAccess>>,
     ) -> Result<HttpResponse, NpmExpansionsError> {
-        let headers = request.headers();
-        let accept_header = headers.get("Accept").or_else(|| headers.get("accept"));
-        let best = matcher::best_match(
-            Vec::from(["application/json"]),
-            accept_header.unwrap_or(&"".to_string()),
-        )?;
-
-        let string_expansions: Vec<String> = expansions_model
-            .read()
-            .unwrap()
-            .all()
-            .iter()
-            .map(|expansion| format!("\"{expansion}\""))
-            .collect();
+        let format = match ResponseFormat::from_request(request) {
+            Some(format) => format,
+            None => return invalid_format_response(),
+        };
 
-        let response = match best.as_str() {
-            "application/json" => HttpResponse::new(
-                "200",
-                "OK",
-                "Content-Type: application/json",
-                &format!("[{}]", string_expansions.join(",")),
-            ),
-            _ => not_acceptable_response(),
+        let pagination = match Pagination::from_query_params(request.query_params(), None) {
+            Ok(pagination) => pagination,
+            Err(error) => return error_response(HttpStatus::BadRequest, error.message()),
         };
 
-        Ok(response)
+        let expansions_model = expansions_model
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let response = HttpResponse::new(HttpStatus::Ok)
+            .with_headers(&pagination.headers(request, expansions_model.all().len()))
+            .with_header("ETag", &format!("\"{}\"", expansions_model.corpus_hash()))
+            .with_header(
+                "Last-Modified",
+                &format_http_date(expansions_model.last_modified()),
+            );
+
+        if ConditionalGet::is_not_modified(request, &response) {
+            return Ok(ConditionalGet::not_modified(&response));
+        }
+
+        let all_expansions: Vec<&Expansion> = expansions_model.all().iter().collect();
+
+        expansions_response(response, pagination.page(&all_expansions), &format)
     }
 
     /// Returns a vector byte representation of a json array containing the top ten matches of npm expansions given
     /// a request with a search_query field
     /// ```json
-    /// ["Nice Pistons Mac", "Nicole Pasta Mcdougle"]
+    /// [{ "id": "4f5ac2a2f4e1d0b1", "npm-expansion": "Nice Pistons Mac", "score": 0.93 }, { "id": "9c1e0b7a3d2f4e56", "npm-expansion": "Nicole Pasta Mcdougle", "score": 0.87 }]
     /// ```
+    /// When the request has the query param `format=record` an array of full expansion records is returned instead.
+    ///
+    /// In either format each result has a `score` field with its similarity to the query from 0 to 1.
+    ///
+    /// The optional `limit` (default 10) and `offset` query params page through the ranked matches and the optional
+    /// `min_score` query param (default 0) leaves out matches with a lower score. The optional `algorithm` query param
+    /// chooses how matches are scored (`jaro_winkler`, `levenshtein`, `word_prefix` or `substring`) and defaults to the
+    /// search algorithm of the expansions model. The response has an `X-Total-Count` header with the number of matches
+    /// and a `Link` header pointing to the next and previous pages. Invalid `limit`, `offset`, `min_score` or `algorithm`
+    /// values produce a 400 response.
     ///
     /// # Arguments
     ///
@@ -192,13 +300,80 @@ impl NpmController {
     ///
     /// assert!(response.is_ok());
     /// ```
+    pub fn search(
+        request: &HttpRequest,
+        expansions_model: Arc<RwLock<dyn ExpansionsAccess>>,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        let format = match ResponseFormat::from_request(request) {
+            Some(format) => format,
+            None => return invalid_format_response(),
+        };
+
+        let pagination =
+            match Pagination::from_query_params(request.query_params(), Some(DEFAULT_SEARCH_LIMIT))
+            {
+                Ok(pagination) => pagination,
+                Err(error) => return error_response(HttpStatus::BadRequest, error.message()),
+            };
+
+        let min_score = match min_score(request) {
+            Ok(min_score) => min_score,
+            Err(error) => return error_response(HttpStatus::BadRequest, error.message()),
+        };
+
+        let algorithm = match request
+            .query_params()
+            .get("algorithm")
+            .map(|algorithm| algorithm.parse::<SearchAlgorithm>())
+            .transpose()
+        {
+            Ok(algorithm) => algorithm,
+            Err(error) => return error_response(HttpStatus::BadRequest, error.message()),
+        };
+
+        let search_options = SearchOptions {
+            limit: pagination
+                .limit
+                .map(|limit| pagination.offset.saturating_add(limit)),
+            min_score,
+            algorithm,
+        };
+
+        let default = String::from(" ");
+        let search_string = request.query_params().get("query").unwrap_or(&default);
+        let expansions_model = expansions_model
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let results = expansions_model.search(search_string, &search_options);
+        let page = Pagination {
+            limit: None,
+            ..pagination
+        }
+        .page(&results.matches);
+
+        scored_expansions_response(
+            HttpResponse::new(HttpStatus::Ok)
+                .with_headers(&pagination.headers(request, results.total)),
+            page,
+            &format,
+        )
+    }
+
+    /// Adds the npm expansion found in the JSON body of the request to the expansions model and
+    /// returns the created expansion record as a json object. Invalid expansions produce a 400 response
+    /// and expansions which already exist produce a 409 response.
+    /// ```json
+    /// { "id": "15", "expansion": "Nice People Meet", "author": "hiccup246", "tags": ["people"], "date_added": "2023-03-25" }
+    /// ```
+    ///
+    /// # Arguments
     ///
-    /// # Failures
+    /// * `request` - An incoming HTTP request with a body of the form
+    ///   `{ "expansion": "Nice People Meet", "author": "hiccup246", "tags": ["people"] }` where author and tags are optional
     ///
-    /// The function fails if the given request has invalid headers
+    /// # Examples
     ///
-    /// ```rust,should_error
-    /// // fails if the given request has invalid headers
+    /// ```
     /// use npm_expansions::{
     ///     npm_controller::NpmController,
     ///     http_request::HttpRequest,
@@ -207,58 +382,155 @@ impl NpmController {
     /// };
     /// use std::{collections::HashMap, sync::{Arc, RwLock}};
     ///
-    /// let request = HttpRequest::new("127.0.0.1", "GET /search HTTP/1.1", HashMap::from([("Accept".to_string(), "text/".to_string())]), HashMap::new());
     /// let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+    /// let request = HttpRequest::new(
+    ///     "127.0.0.1",
+    ///     "POST /expansions HTTP/1.1",
+    ///     HashMap::from([
+    ///         ("Accept".to_string(), "application/json".to_string()),
+    ///         ("Content-Type".to_string(), "application/json".to_string()),
+    ///     ]),
+    ///     HashMap::new(),
+    /// )
+    /// .with_body(br#"{"expansion": "Nice People Meet"}"#);
+    /// let response = NpmController::add_expansion(&request, mock_expansions_model);
     ///
-    /// NpmController::search(&request, mock_expansions_model);
+    /// assert_eq!(response.unwrap().status_code(), 201);
     /// ```
-    pub fn search(
+    pub fn add_expansion(
         request: &HttpRequest,
         expansions_model: Arc<RwLock<dyn ExpansionsAccess>>,
     ) -> Result<HttpResponse, NpmExpansionsError> {
-        let headers = request.headers();
-        let accept_header = headers.get("Accept").or_else(|| headers.get("accept"));
-        let best = matcher::best_match(
-            Vec::from(["application/json"]),
-            accept_header.unwrap_or(&"".to_string()),
-        )?;
+        let content_type = request.headers().get("Content-Type");
+        let is_json = content_type.is_some_and(|content_type| {
+            matcher::best_match(Vec::from(["application/json"]), content_type)
+                .is_ok_and(|mime_type| mime_type == "application/json")
+        });
 
-        let default = String::from(" ");
-        let search_string = request.query_params().get("query").unwrap_or(&default);
-        let top_ten: Vec<String> = expansions_model
-            .read()
-            .unwrap()
-            .search(search_string)
-            .iter()
-            .map(|expansion| format!("\"{expansion}\""))
-            .collect();
+        if !is_json {
+            return Ok(HttpResponse::new(HttpStatus::UnsupportedMediaType)
+                .text("Please send application/json"));
+        }
 
-        let response = match best.as_str() {
-            "application/json" => HttpResponse::new(
-                "200",
-                "OK",
-                "Content-Type: application/json",
-                &format!("[{}]", top_ten.join(",")),
-            ),
-            _ => not_acceptable_response(),
+        let new_expansion = match serde_json::from_slice::<NewExpansion>(request.body()) {
+            Ok(new_expansion) => new_expansion,
+            Err(_) => {
+                return error_response(
+                    HttpStatus::BadRequest,
+                    "Expected a JSON body of the form {\"expansion\": \"...\"}",
+                )
+            }
         };
 
-        Ok(response)
+        let mut expansion = Expansion::new(&new_expansion.expansion).with_tags(new_expansion.tags);
+
+        if let Some(author) = new_expansion.author {
+            expansion = expansion.with_author(&author);
+        }
+
+        let mut expansions_model = expansions_model
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        match expansions_model.add_expansion(expansion) {
+            Ok(added) => HttpResponse::new(HttpStatus::Created).json(added),
+            Err(error) => match error.kind() {
+                NpmErrorKind::InvalidExpansion => {
+                    error_response(HttpStatus::BadRequest, error.message())
+                }
+                NpmErrorKind::DuplicateExpansion => {
+                    error_response(HttpStatus::Conflict, error.message())
+                }
+                _ => Err(error),
+            },
+        }
+    }
+}
+
+fn expansion_response(
+    response: HttpResponse,
+    expansion: &Expansion,
+    format: &ResponseFormat,
+) -> Result<HttpResponse, NpmExpansionsError> {
+    match format {
+        ResponseFormat::Text => response.json(&TextExpansion::from(expansion)),
+        ResponseFormat::Record => response.json(expansion),
+    }
+}
+
+fn expansions_response(
+    response: HttpResponse,
+    expansions: &[&Expansion],
+    format: &ResponseFormat,
+) -> Result<HttpResponse, NpmExpansionsError> {
+    match format {
+        ResponseFormat::Text => response.json(
+            &expansions
+                .iter()
+                .map(|expansion| TextExpansion::from(*expansion))
+                .collect::<Vec<TextExpansion>>(),
+        ),
+        ResponseFormat::Record => response.json(&expansions),
     }
 }
 
-fn not_acceptable_response() -> HttpResponse {
-    HttpResponse::new(
-        "406",
-        "NOT ACCEPTABLE",
-        "",
-        "Please accept application/json",
+fn scored_expansions_response(
+    response: HttpResponse,
+    scored_expansions: &[ScoredExpansion],
+    format: &ResponseFormat,
+) -> Result<HttpResponse, NpmExpansionsError> {
+    match format {
+        ResponseFormat::Text => response.json(
+            &scored_expansions
+                .iter()
+                .map(|scored| ScoredText {
+                    expansion: TextExpansion::from(scored.expansion),
+                    score: scored.score,
+                })
+                .collect::<Vec<ScoredText>>(),
+        ),
+        ResponseFormat::Record => response.json(
+            &scored_expansions
+                .iter()
+                .map(|scored| ScoredRecord {
+                    expansion: scored.expansion,
+                    score: scored.score,
+                })
+                .collect::<Vec<ScoredRecord>>(),
+        ),
+    }
+}
+
+fn min_score(request: &HttpRequest) -> Result<f64, NpmExpansionsError> {
+    match request.query_params().get("min_score") {
+        Some(min_score) => match min_score.parse::<f64>() {
+            Ok(min_score) if (0.0..=1.0).contains(&min_score) => Ok(min_score),
+            _ => Err(NpmExpansionsError::new(
+                NpmErrorKind::InvalidQueryParam,
+                &format!(
+                    "The min_score query param must be a number from 0 to 1 but was {min_score}"
+                ),
+            )),
+        },
+        None => Ok(0.0),
+    }
+}
+
+fn invalid_format_response() -> Result<HttpResponse, NpmExpansionsError> {
+    error_response(
+        HttpStatus::BadRequest,
+        "The format query param must be either text or record",
     )
 }
 
+fn error_response(status: HttpStatus, message: &str) -> Result<HttpResponse, NpmExpansionsError> {
+    HttpResponse::new(status).json(&serde_json::json!({ "error": message }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::expansion::content_id;
     use crate::mock_expansions_model::MockExpansionsModel;
     use std::collections::HashMap;
     use test_case::test_case;
@@ -281,6 +553,7 @@ mod tests {
     #[test_case(NpmController::random; "random")]
     #[test_case(NpmController::all; "all")]
     #[test_case(NpmController::search; "search")]
+    #[allow(clippy::len_zero)]
     fn valid_request_returns_content(controller_function: ControllerFunction) {
         let request = HttpRequest::new(
             "127.0.0.1",
@@ -300,31 +573,33 @@ mod tests {
     }
 
     #[test_case(NpmController::random; "random")]
-    #[test_case(NpmController::all; "all")]
-    #[test_case(NpmController::search; "search")]
-    fn invalid_request_headers(controller_function: ControllerFunction) {
+    fn lower_case_accept_header(controller_function: ControllerFunction) {
         let request = HttpRequest::new(
             "127.0.0.1",
             "GET / HTTP/1.1",
-            HashMap::from([("Accept".to_string(), "text/".to_string())]),
+            HashMap::from([("accept".to_string(), "text/html".to_string())]),
             HashMap::new(),
         );
 
         let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
-        assert!(controller_function(&request, mock_expansions_model).is_err())
+        assert!(controller_function(&request, mock_expansions_model).is_ok())
     }
 
-    #[test_case(NpmController::random; "random")]
-    fn lower_case_accept_header(controller_function: ControllerFunction) {
+    #[test_case(NpmController::random, "ACCEPT"; "random upper case")]
+    #[test_case(NpmController::all, "AcCePt"; "all mixed case")]
+    #[test_case(NpmController::search, "accept"; "search lower case")]
+    fn any_case_accept_header(controller_function: ControllerFunction, name: &str) {
         let request = HttpRequest::new(
             "127.0.0.1",
             "GET / HTTP/1.1",
-            HashMap::from([("accept".to_string(), "text/html".to_string())]),
+            HashMap::from([(name.to_string(), "application/json".to_string())]),
             HashMap::new(),
         );
 
         let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
-        assert!(controller_function(&request, mock_expansions_model).is_ok())
+        let response = controller_function(&request, mock_expansions_model).unwrap();
+
+        assert_eq!(response.status_code(), 200)
     }
 
     #[test_case(NpmController::random; "random")]
@@ -339,4 +614,477 @@ mod tests {
         let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
         assert!(controller_function(&request, mock_expansions_model).is_ok())
     }
+
+    #[test_case(NpmController::random; "random")]
+    #[test_case(NpmController::all; "all")]
+    #[test_case(NpmController::search; "search")]
+    fn record_format(controller_function: ControllerFunction) {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET / HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::from([("format".to_string(), "record".to_string())]),
+        );
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = controller_function(&request, mock_expansions_model).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let record = body.as_array().map_or(&body, |records| &records[0]);
+
+        assert_eq!(record["id"], content_id("Nacho Pizza Marinade"));
+        assert_eq!(record["expansion"], "Nacho Pizza Marinade")
+    }
+
+    #[test_case(NpmController::random; "random")]
+    #[test_case(NpmController::all; "all")]
+    #[test_case(NpmController::search; "search")]
+    fn invalid_format(controller_function: ControllerFunction) {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET / HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::from([("format".to_string(), "xml".to_string())]),
+        );
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = controller_function(&request, mock_expansions_model).unwrap();
+
+        assert_eq!(response.status_code(), 400)
+    }
+
+    #[test_case(NpmController::random; "random")]
+    #[test_case(NpmController::expansion; "expansion")]
+    fn text_format_includes_id(controller_function: ControllerFunction) {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET / HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::new(),
+        )
+        .with_path_params(HashMap::from([(
+            "id".to_string(),
+            content_id("Nacho Pizza Marinade"),
+        )]));
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = controller_function(&request, mock_expansions_model).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["id"], content_id("Nacho Pizza Marinade"));
+        assert_eq!(body["npm-expansion"], "Nacho Pizza Marinade")
+    }
+
+    #[test]
+    fn expansion_not_found() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/expansions/missing HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::new(),
+        )
+        .with_path_params(HashMap::from([("id".to_string(), "missing".to_string())]));
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = NpmController::expansion(&request, mock_expansions_model).unwrap();
+
+        assert_eq!(response.status_code(), 404)
+    }
+
+    #[test]
+    fn paginated_all() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/all?limit=5&offset=5 HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::from([
+                ("limit".to_string(), "5".to_string()),
+                ("offset".to_string(), "5".to_string()),
+            ]),
+        );
+
+        let mock_expansions_model = MockExpansionsModel::default();
+        let corpus_hash = mock_expansions_model.corpus_hash().to_string();
+        let response =
+            NpmController::all(&request, Arc::new(RwLock::new(mock_expansions_model))).unwrap();
+        let body: Vec<serde_json::Value> = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body[0]["npm-expansion"], "Nachos Preventing Motivation");
+        assert_eq!(body[0]["id"], content_id("Nachos Preventing Motivation"));
+        assert_eq!(body.len(), 5);
+        assert_eq!(
+            response.headers().to_string(),
+            format!("X-Total-Count: 14\r\nLink: </api/all?limit=5&offset=10>; rel=\"next\", </api/all?limit=5&offset=0>; rel=\"prev\"\r\nETag: \"{corpus_hash}\"\r\nLast-Modified: Thu, 01 Jan 1970 00:00:00 GMT\r\nContent-Type: application/json")
+        )
+    }
+
+    #[test]
+    fn all_etag_changes_with_expansions() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/all HTTP/1.1",
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let mock_expansions_model: Arc<RwLock<dyn ExpansionsAccess>> =
+            Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let etag = |model: &Arc<RwLock<dyn ExpansionsAccess>>| {
+            NpmController::all(&request, model.clone())
+                .unwrap()
+                .headers()
+                .get("ETag")
+                .map(str::to_string)
+        };
+
+        let first = etag(&mock_expansions_model);
+
+        assert_eq!(etag(&mock_expansions_model), first);
+
+        mock_expansions_model
+            .write()
+            .unwrap()
+            .add_expansion(Expansion::new("Nice People Meet"))
+            .unwrap();
+
+        assert_ne!(etag(&mock_expansions_model), first);
+    }
+
+    #[test_case("If-None-Match", "\"{corpus_hash}\"", 304; "matching etag")]
+    #[test_case("If-None-Match", "W/\"{corpus_hash}-gzip\"", 304; "compressed etag")]
+    #[test_case("If-None-Match", "\"0\"", 200; "other etag")]
+    #[test_case("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT", 304; "not modified since")]
+    #[test_case("If-Modified-Since", "yesterday", 200; "invalid date")]
+    fn all_answers_current_copy_without_body(header: &str, value: &str, status_code: u16) {
+        let mock_expansions_model: Arc<RwLock<dyn ExpansionsAccess>> =
+            Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let corpus_hash = mock_expansions_model
+            .read()
+            .unwrap()
+            .corpus_hash()
+            .to_string();
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/all HTTP/1.1",
+            HashMap::from([(
+                header.to_string(),
+                value.replace("{corpus_hash}", &corpus_hash),
+            )]),
+            HashMap::new(),
+        );
+
+        let response = NpmController::all(&request, mock_expansions_model).unwrap();
+
+        assert_eq!(response.status_code(), status_code);
+        assert_eq!(response.body().is_empty(), status_code == 304);
+        assert_eq!(
+            response.headers().get("ETag"),
+            Some(format!("\"{corpus_hash}\"").as_str())
+        );
+    }
+
+    fn expansion_texts(body: &[serde_json::Value]) -> Vec<&str> {
+        body.iter()
+            .map(|item| item["npm-expansion"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn search_defaults_to_ten_results() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/search?query=abc HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::from([("query".to_string(), "abc".to_string())]),
+        );
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = NpmController::search(&request, mock_expansions_model).unwrap();
+        let body: Vec<serde_json::Value> = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body.len(), 10)
+    }
+
+    #[test]
+    fn search_results_include_score() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/search?query=abc HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::from([("query".to_string(), "abc".to_string())]),
+        );
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = NpmController::search(&request, mock_expansions_model).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body[0]["score"], 1.0);
+        assert_eq!(body[0]["npm-expansion"], "Nacho Pizza Marinade");
+        assert_eq!(body[0]["id"], content_id("Nacho Pizza Marinade"))
+    }
+
+    #[test]
+    fn search_records_include_score() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/search?query=abc&format=record HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::from([
+                ("query".to_string(), "abc".to_string()),
+                ("format".to_string(), "record".to_string()),
+            ]),
+        );
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = NpmController::search(&request, mock_expansions_model).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body[0]["score"], 1.0);
+        assert_eq!(body[0]["expansion"], "Nacho Pizza Marinade")
+    }
+
+    #[test]
+    fn search_with_offset_and_limit() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/search?query=abc&limit=3&offset=12 HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::from([
+                ("query".to_string(), "abc".to_string()),
+                ("limit".to_string(), "3".to_string()),
+                ("offset".to_string(), "12".to_string()),
+            ]),
+        );
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = NpmController::search(&request, mock_expansions_model).unwrap();
+        let body: Vec<serde_json::Value> = serde_json::from_slice(response.body()).unwrap();
+        let ids: Vec<&str> = body
+            .iter()
+            .map(|item| item["id"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(
+            expansion_texts(&body),
+            ["Naive Props Mutation", "Naive Puppets Marching"]
+        );
+        assert_eq!(
+            ids,
+            [
+                content_id("Naive Props Mutation"),
+                content_id("Naive Puppets Marching")
+            ]
+        );
+        assert_eq!(response.headers().get("X-Total-Count"), Some("14"))
+    }
+
+    #[test_case(NpmController::all, "limit", "0"; "all zero limit")]
+    #[test_case(NpmController::all, "offset", "abc"; "all invalid offset")]
+    #[test_case(NpmController::search, "limit", "-5"; "search negative limit")]
+    #[test_case(NpmController::search, "offset", "1.5"; "search invalid offset")]
+    #[test_case(NpmController::search, "min_score", "1.5"; "search min score above one")]
+    #[test_case(NpmController::search, "min_score", "NaN"; "search min score not a number")]
+    #[test_case(NpmController::search, "algorithm", "soundex"; "search unknown algorithm")]
+    fn invalid_pagination(controller_function: ControllerFunction, key: &str, value: &str) {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET / HTTP/1.1",
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            HashMap::from([(key.to_string(), value.to_string())]),
+        );
+
+        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let response = controller_function(&request, mock_expansions_model).unwrap();
+
+        assert_eq!(response.status_code(), 400)
+    }
+
+    const ADVERSARIAL_EXPANSIONS: [&str; 5] = [
+        "Nacho \"Pizza\" Marinade",
+        "Nacho \\ Portion \\\" Monitor",
+        "Nacho\u{0007}Printing\nMachine\t\r",
+        "Nadie Programa más 🌮",
+        "Nachos </script> Pillage Milwaukee",
+    ];
+
+    fn adversarial_request(status_line: &str, query_params: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest::new(
+            "127.0.0.1",
+            status_line,
+            HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+            query_params
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    fn adversarial_model() -> Arc<RwLock<MockExpansionsModel>> {
+        Arc::new(RwLock::new(MockExpansionsModel::with_expansions(
+            &ADVERSARIAL_EXPANSIONS,
+        )))
+    }
+
+    #[test]
+    fn random_escapes_json() {
+        let request = adversarial_request("GET /api/random HTTP/1.1", &[]);
+        let response = NpmController::random(&request, adversarial_model()).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["npm-expansion"], ADVERSARIAL_EXPANSIONS[0]);
+        assert_eq!(body["id"], content_id(ADVERSARIAL_EXPANSIONS[0]))
+    }
+
+    #[test]
+    fn all_escapes_json() {
+        let request = adversarial_request("GET /api/all HTTP/1.1", &[]);
+        let response = NpmController::all(&request, adversarial_model()).unwrap();
+        let body: Vec<serde_json::Value> = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(expansion_texts(&body), ADVERSARIAL_EXPANSIONS)
+    }
+
+    #[test]
+    fn all_records_escape_json() {
+        let request = adversarial_request("GET /api/all HTTP/1.1", &[("format", "record")]);
+        let response = NpmController::all(&request, adversarial_model()).unwrap();
+        let body: Vec<Expansion> = serde_json::from_slice(response.body()).unwrap();
+        let texts: Vec<&str> = body.iter().map(|expansion| expansion.text()).collect();
+
+        assert_eq!(texts, ADVERSARIAL_EXPANSIONS)
+    }
+
+    #[test]
+    fn search_escapes_json() {
+        let request = adversarial_request(
+            "GET /api/search HTTP/1.1",
+            &[("query", "\"</script>\u{0000}")],
+        );
+        let response = NpmController::search(&request, adversarial_model()).unwrap();
+        let body: Vec<serde_json::Value> = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(expansion_texts(&body), ADVERSARIAL_EXPANSIONS)
+    }
+
+    #[test_case(ADVERSARIAL_EXPANSIONS[1]; "backslashes")]
+    #[test_case(ADVERSARIAL_EXPANSIONS[2]; "control characters")]
+    #[test_case(ADVERSARIAL_EXPANSIONS[3]; "non ascii")]
+    fn expansion_escapes_json(text: &str) {
+        let request = adversarial_request("GET /api/expansions/id HTTP/1.1", &[])
+            .with_path_params(HashMap::from([("id".to_string(), content_id(text))]));
+        let response = NpmController::expansion(&request, adversarial_model()).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["npm-expansion"], text)
+    }
+
+    #[test]
+    fn not_found_escapes_json() {
+        let id = "\"}\\\n</script>";
+        let request = adversarial_request("GET /api/expansions/id HTTP/1.1", &[])
+            .with_path_params(HashMap::from([("id".to_string(), id.to_string())]));
+        let response = NpmController::expansion(&request, adversarial_model()).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["error"], format!("No expansion has the id {id}"))
+    }
+
+    mod add_expansion {
+        use super::*;
+
+        fn post_request(body: &[u8]) -> HttpRequest {
+            HttpRequest::new(
+                "127.0.0.1",
+                "POST /api/expansions HTTP/1.1",
+                HashMap::from([
+                    ("Accept".to_string(), "application/json".to_string()),
+                    ("Content-Type".to_string(), "application/json".to_string()),
+                ]),
+                HashMap::new(),
+            )
+            .with_body(body)
+        }
+
+        #[test]
+        fn created() {
+            let request = post_request(br#"{"expansion": "Nice People Meet"}"#);
+            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            let response =
+                NpmController::add_expansion(&request, mock_expansions_model.clone()).unwrap();
+
+            assert_eq!(response.status_code(), 201);
+            assert_eq!(mock_expansions_model.read().unwrap().all().len(), 15);
+        }
+
+        #[test]
+        fn created_escapes_json() {
+            let body = serde_json::json!({
+                "expansion": "Nice \"Pun\" Machine",
+                "author": "</script>\u{0001}\\",
+                "tags": ["más\n🌮"],
+            });
+            let request = post_request(body.to_string().as_bytes());
+            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            let response = NpmController::add_expansion(&request, mock_expansions_model).unwrap();
+            let record: Expansion = serde_json::from_slice(response.body()).unwrap();
+
+            assert_eq!(record.text(), "Nice \"Pun\" Machine");
+            assert_eq!(record.author(), Some("</script>\u{0001}\\"));
+            assert_eq!(record.tags(), ["más\n🌮".to_string()])
+        }
+
+        #[test]
+        fn created_with_metadata() {
+            let request = post_request(
+                br#"{"expansion": "Nice People Meet", "author": "hiccup246", "tags": ["people"]}"#,
+            );
+            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            let response = NpmController::add_expansion(&request, mock_expansions_model).unwrap();
+            let record: Expansion = serde_json::from_slice(response.body()).unwrap();
+
+            assert_eq!(record.id(), content_id("Nice People Meet"));
+            assert_eq!(record.author(), Some("hiccup246"));
+            assert_eq!(record.tags(), ["people".to_string()])
+        }
+
+        #[test]
+        fn invalid_json() {
+            let request = post_request(b"Nice People Meet");
+            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            let response = NpmController::add_expansion(&request, mock_expansions_model).unwrap();
+
+            assert_eq!(response.status_code(), 400)
+        }
+
+        #[test]
+        fn invalid_expansion() {
+            let request = post_request(br#"{"expansion": "Not An Acronym"}"#);
+            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            let response = NpmController::add_expansion(&request, mock_expansions_model).unwrap();
+
+            assert_eq!(response.status_code(), 400)
+        }
+
+        #[test]
+        fn duplicate_expansion() {
+            let request = post_request(br#"{"expansion": "Nacho Pizza Marinade"}"#);
+            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            let response = NpmController::add_expansion(&request, mock_expansions_model).unwrap();
+
+            assert_eq!(response.status_code(), 409)
+        }
+
+        #[test]
+        fn missing_content_type() {
+            let request = HttpRequest::new(
+                "127.0.0.1",
+                "POST /api/expansions HTTP/1.1",
+                HashMap::from([("Accept".to_string(), "application/json".to_string())]),
+                HashMap::new(),
+            )
+            .with_body(br#"{"expansion": "Nice People Meet"}"#);
+            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            let response = NpmController::add_expansion(&request, mock_expansions_model).unwrap();
+
+            assert_eq!(response.status_code(), 415)
+        }
+    }
 }
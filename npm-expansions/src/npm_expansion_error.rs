@@ -27,6 +27,18 @@ pub enum NpmErrorKind {
     InternalServerError,
     /// A HTTP request has a route not supported by the server
     RouteNotFound,
+    /// A submitted expansion does not spell out the NPM acronym
+    InvalidExpansion,
+    /// A submitted expansion already exists in the expansions model
+    DuplicateExpansion,
+    /// The expansions data file could not be read or contains no valid expansions
+    ExpansionsLoadError,
+    /// A HTTP request has a query param with an invalid value
+    InvalidQueryParam,
+    /// A HTTP request has a body larger than the body size limit
+    PayloadTooLarge,
+    /// A HTTP request was not received before its read timeout or deadline passed
+    RequestTimeout,
 }
 
 impl NpmExpansionsError {
@@ -50,6 +62,11 @@ impl NpmExpansionsError {
     pub fn kind(&self) -> &NpmErrorKind {
         &self.kind
     }
+
+    /// Returns the message of a NpmExpansionsError
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl fmt::Display for NpmExpansionsError {
@@ -0,0 +1,328 @@
+use crate::http_headers::HttpHeaders;
+use crate::http_request::HttpRequest;
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use std::collections::HashMap;
+
+/// A window over a list of results described by the `limit` and `offset` query params
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pagination {
+    /// The maximum number of results in the page. None means every remaining result
+    pub limit: Option<usize>,
+    /// The number of results skipped before the page starts
+    pub offset: usize,
+}
+
+impl Pagination {
+    /// Builds a pagination from the `limit` and `offset` query params of a request, using the
+    /// given default limit when no `limit` param is present
+    ///
+    /// # Arguments
+    ///
+    /// * `query_params` - The query params of an incoming HTTP request
+    /// * `default_limit` - The limit used when the request does not give one
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::pagination::Pagination;
+    /// use std::collections::HashMap;
+    ///
+    /// let query_params = HashMap::from([("limit".to_string(), "5".to_string())]);
+    /// let pagination = Pagination::from_query_params(&query_params, None).unwrap();
+    ///
+    /// assert_eq!(pagination, Pagination { limit: Some(5), offset: 0 });
+    /// ```
+    ///
+    /// # Failures
+    ///
+    /// The function fails if `limit` is not a positive integer or `offset` is not a non-negative integer
+    ///
+    /// ```rust,should_error
+    /// // fails if the limit is zero
+    /// use npm_expansions::pagination::Pagination;
+    /// use std::collections::HashMap;
+    ///
+    /// let query_params = HashMap::from([("limit".to_string(), "0".to_string())]);
+    ///
+    /// Pagination::from_query_params(&query_params, None);
+    /// ```
+    pub fn from_query_params(
+        query_params: &HashMap<String, String>,
+        default_limit: Option<usize>,
+    ) -> Result<Pagination, NpmExpansionsError> {
+        let limit = match query_params.get("limit") {
+            Some(limit) => match limit.parse::<usize>() {
+                Ok(limit) if limit > 0 => Some(limit),
+                _ => {
+                    return Err(NpmExpansionsError::new(
+                        NpmErrorKind::InvalidQueryParam,
+                        &format!(
+                            "The limit query param must be a positive integer but was {limit}"
+                        ),
+                    ))
+                }
+            },
+            None => default_limit,
+        };
+
+        let offset = match query_params.get("offset") {
+            Some(offset) => offset.parse::<usize>().map_err(|_| {
+                NpmExpansionsError::new(
+                    NpmErrorKind::InvalidQueryParam,
+                    &format!(
+                        "The offset query param must be a non-negative integer but was {offset}"
+                    ),
+                )
+            })?,
+            None => 0,
+        };
+
+        Ok(Pagination { limit, offset })
+    }
+
+    /// Returns the page of the given results
+    pub fn page<'a, T>(&self, results: &'a [T]) -> &'a [T] {
+        let start = self.offset.min(results.len());
+        let end = match self.limit {
+            Some(limit) => start.saturating_add(limit).min(results.len()),
+            None => results.len(),
+        };
+
+        &results[start..end]
+    }
+
+    /// Returns the offset of the next page if there are results after the current page
+    pub fn next_offset(&self, total: usize) -> Option<usize> {
+        let next = self.offset.saturating_add(self.limit?);
+
+        (next < total).then_some(next)
+    }
+
+    /// Returns the offset of the previous page if the current page does not start at the first result
+    pub fn prev_offset(&self) -> Option<usize> {
+        if self.offset == 0 {
+            return None;
+        }
+
+        Some(
+            self.offset
+                .saturating_sub(self.limit.unwrap_or(self.offset)),
+        )
+    }
+
+    /// Returns the headers describing the page i.e. an `X-Total-Count` header with the
+    /// total number of results and a `Link` header with the next and previous pages
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The incoming HTTP request the page was built from
+    /// * `total` - The total number of results across every page
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{http_request::HttpRequest, pagination::Pagination};
+    /// use std::collections::HashMap;
+    ///
+    /// let request = HttpRequest::new("127.0.0.1", "GET /api/all?limit=10 HTTP/1.1", HashMap::new(), HashMap::from([("limit".to_string(), "10".to_string())]));
+    /// let pagination = Pagination { limit: Some(10), offset: 0 };
+    ///
+    /// assert_eq!(
+    ///     pagination.headers(&request, 25).to_string(),
+    ///     "X-Total-Count: 25\r\nLink: </api/all?limit=10&offset=10>; rel=\"next\""
+    /// );
+    /// ```
+    pub fn headers(&self, request: &HttpRequest, total: usize) -> HttpHeaders {
+        let links: Vec<String> = [
+            (self.next_offset(total), "next"),
+            (self.prev_offset(), "prev"),
+        ]
+        .into_iter()
+        .filter_map(|(offset, rel)| {
+            offset.map(|offset| format!("<{}>; rel=\"{rel}\"", page_uri(request, offset)))
+        })
+        .collect();
+
+        let mut headers = HttpHeaders::new();
+        headers.append("X-Total-Count", &total.to_string());
+
+        if !links.is_empty() {
+            headers.append("Link", &links.join(", "));
+        }
+
+        headers
+    }
+}
+
+/// Returns the uri of the request with the given offset, keeping every value of a repeated
+/// query param in its order
+fn page_uri(request: &HttpRequest, offset: usize) -> String {
+    let offset = [offset.to_string()];
+    let mut keys: Vec<&str> = request
+        .query_params()
+        .keys()
+        .map(String::as_str)
+        .filter(|key| *key != "offset")
+        .chain(["offset"])
+        .collect();
+
+    keys.sort();
+
+    let query_string: Vec<String> = keys
+        .iter()
+        .flat_map(|&key| {
+            let values = match key {
+                "offset" => offset.as_slice(),
+                _ => request.query_param_values(key),
+            };
+
+            values
+                .iter()
+                .map(move |value| format!("{}={}", percent_encode(key), percent_encode(value)))
+        })
+        .collect();
+
+    format!("{}?{}", request.path(), query_string.join("&"))
+}
+
+/// Percent encodes every byte of a query param other than the RFC 3986 unreserved characters
+fn percent_encode(component: &str) -> String {
+    component
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_tcp_stream::MockTcpStream;
+    use test_case::test_case;
+
+    fn query_params(params: &[(&str, &str)]) -> HashMap<String, String> {
+        params
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn default_limit() {
+        let pagination = Pagination::from_query_params(&HashMap::new(), Some(10)).unwrap();
+
+        assert_eq!(
+            pagination,
+            Pagination {
+                limit: Some(10),
+                offset: 0
+            }
+        )
+    }
+
+    #[test_case("limit", "0"; "zero limit")]
+    #[test_case("limit", "-1"; "negative limit")]
+    #[test_case("limit", "ten"; "non numeric limit")]
+    #[test_case("offset", "-1"; "negative offset")]
+    #[test_case("offset", "1.5"; "non integer offset")]
+    fn invalid_query_params(key: &str, value: &str) {
+        let error =
+            Pagination::from_query_params(&query_params(&[(key, value)]), None).unwrap_err();
+
+        assert!(matches!(error.kind(), NpmErrorKind::InvalidQueryParam))
+    }
+
+    #[test_case(Some(2), 0, &[1, 2]; "first page")]
+    #[test_case(Some(2), 4, &[5]; "last page")]
+    #[test_case(Some(2), 10, &[]; "offset past end")]
+    #[test_case(None, 3, &[4, 5]; "no limit")]
+    fn page(limit: Option<usize>, offset: usize, expected: &[i32]) {
+        let pagination = Pagination { limit, offset };
+
+        assert_eq!(pagination.page(&[1, 2, 3, 4, 5]), expected)
+    }
+
+    #[test]
+    fn headers_with_next_and_prev() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/search?query=abc&limit=2&offset=2 HTTP/1.1",
+            HashMap::new(),
+            query_params(&[("query", "abc"), ("limit", "2"), ("offset", "2")]),
+        );
+        let pagination = Pagination {
+            limit: Some(2),
+            offset: 2,
+        };
+
+        assert_eq!(
+            pagination.headers(&request, 5).to_string(),
+            "X-Total-Count: 5\r\nLink: </api/search?limit=2&offset=4&query=abc>; rel=\"next\", </api/search?limit=2&offset=0&query=abc>; rel=\"prev\""
+        )
+    }
+
+    #[test]
+    fn headers_encode_query_params() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/search?query=Nacho%20Pizza%26m%C3%A1s&limit=2 HTTP/1.1",
+            HashMap::new(),
+            query_params(&[("query", "Nacho Pizza&más"), ("limit", "2")]),
+        );
+        let pagination = Pagination {
+            limit: Some(2),
+            offset: 0,
+        };
+
+        assert_eq!(
+            pagination.headers(&request, 5).to_string(),
+            "X-Total-Count: 5\r\nLink: </api/search?limit=2&offset=2&query=Nacho%20Pizza%26m%C3%A1s>; rel=\"next\""
+        )
+    }
+
+    #[test]
+    fn headers_keep_repeated_query_params() {
+        let input_bytes = b"GET /api/search?tag=people&limit=2&tag=food HTTP/1.1\r\n\r\n";
+        let mut contents = vec![0u8; 1024];
+
+        contents[..input_bytes.len()].clone_from_slice(input_bytes);
+
+        let mut stream = MockTcpStream {
+            read_data: contents,
+            write_data: Vec::new(),
+        };
+        let request = HttpRequest::build(&mut stream).unwrap();
+        let pagination = Pagination {
+            limit: Some(2),
+            offset: 0,
+        };
+
+        assert_eq!(
+            pagination.headers(&request, 5).to_string(),
+            "X-Total-Count: 5\r\nLink: </api/search?limit=2&offset=2&tag=people&tag=food>; rel=\"next\""
+        )
+    }
+
+    #[test]
+    fn headers_without_links() {
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/all HTTP/1.1",
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let pagination = Pagination {
+            limit: None,
+            offset: 0,
+        };
+
+        assert_eq!(
+            pagination.headers(&request, 5).to_string(),
+            "X-Total-Count: 5"
+        )
+    }
+}
@@ -1,7 +1,10 @@
+use crate::app_state::AppState;
 use crate::default_controller::DefaultController;
 use crate::expansions_model::ExpansionsAccess;
 use crate::http_request::HttpRequest;
 use crate::http_response::HttpResponse;
+use crate::http_status::HttpStatus;
+use crate::middleware::Middleware;
 use crate::npm_controller::ControllerFunction;
 use crate::npm_expansion_error::NpmExpansionsError;
 use std::{
@@ -9,34 +12,206 @@ use std::{
     sync::{Arc, RwLock},
 };
 
-/// A type representing a series of http routes and their associated controller functions
+/// A boxed function answering the requests of a route. It may carry its own state and is given
+/// the app state of the router, from which it can take shared values e.g. the expansions model.
+pub type Handler =
+    Box<dyn Fn(&HttpRequest, &AppState) -> Result<HttpResponse, NpmExpansionsError> + Send + Sync>;
+
+/// A type representing a series of http routes and their associated handlers.
+/// Each route is a method and path, optionally followed by a HTTP version which is ignored so
+/// that a route answers requests of any version.
+///
+/// A path segment wrapped in braces e.g. `{id}` matches any single non empty segment and a final
+/// segment of the form `{*name}` matches the rest of the path. Both are made available to the
+/// handler through `HttpRequest::path_params`.
 /// # Examples
 ///
 /// ```
-/// # use crate::npm_expansions::npm_controller::{NpmController, ControllerFunction};
+/// # use crate::npm_expansions::{npm_controller::NpmController, router::{controller, handler, Routes}};
 /// # use std::collections::HashMap;
-/// HashMap::from([
-///     (
-///         "GET / HTTP/1.1",
-///         NpmController::random as ControllerFunction,
-///     )
+/// let routes: Routes = HashMap::from([
+///     ("GET / HTTP/1.1", controller(NpmController::random)),
+///     ("GET /api/expansions/{id}", controller(NpmController::expansion)),
 /// ]);
 /// ```
-pub type Routes = HashMap<&'static str, ControllerFunction>;
+pub type Routes = HashMap<&'static str, Handler>;
 
-/// A struct which stores a route
+/// Returns a handler which calls the given function, for closures which capture their own state
+///
+/// # Arguments
+///
+/// * `function` - A function answering a request given the app state of the router
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{http_response::HttpResponse, http_status::HttpStatus, router::handler};
+/// use std::time::Instant;
+///
+/// let started = Instant::now();
+/// let uptime = handler(move |_request, _state| {
+///     let uptime = started.elapsed().as_secs().to_string();
+///
+///     Ok(HttpResponse::new(HttpStatus::Ok).with_body(uptime))
+/// });
+/// ```
+pub fn handler(
+    function: impl Fn(&HttpRequest, &AppState) -> Result<HttpResponse, NpmExpansionsError>
+        + Send
+        + Sync
+        + 'static,
+) -> Handler {
+    Box::new(function)
+}
+
+/// Returns a handler which calls a controller function with the expansions model held by the app
+/// state of the router. A request is answered with a 500 response when the app state does not
+/// hold an `Arc<RwLock<dyn ExpansionsAccess>>`.
+///
+/// # Arguments
+///
+/// * `controller_function` - A controller function e.g. `NpmController::random`
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{npm_controller::NpmController, router::controller};
+///
+/// let random = controller(NpmController::random);
+/// ```
+pub fn controller(controller_function: ControllerFunction) -> Handler {
+    Box::new(move |request, state| {
+        let expansions_model = state.require::<Arc<RwLock<dyn ExpansionsAccess>>>()?;
+
+        controller_function(request, expansions_model.clone())
+    })
+}
+
+/// A struct which stores a route, the app state given to its handlers and the middlewares run
+/// around every request it routes
 pub struct Router {
-    routes_config: Routes,
+    route_trees: HashMap<String, RouteNode>,
+    middlewares: Vec<Box<dyn Middleware>>,
+    state: AppState,
+}
+
+/// A node of a route tree holding the routes which continue past one path segment
+#[derive(Default)]
+struct RouteNode {
+    literals: HashMap<String, RouteNode>,
+    param: Option<Box<RouteNode>>,
+    wildcard: Option<Endpoint>,
+    endpoint: Option<Endpoint>,
+}
+
+/// The handler of a route with the names of its path params in path order
+struct Endpoint {
+    handler: Handler,
+    param_names: Vec<String>,
+}
+
+enum RouteSegment<'a> {
+    Literal(&'a str),
+    Param(&'a str),
+    Wildcard(&'a str),
 }
 
 impl Router {
-    /// Creates a new router given a RouteConfig
+    /// Creates a new router given a RouteConfig. Routes which are not a method followed by a path
+    /// starting with `/` can never match a request and are ignored.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if two routes have the same method and path or a wildcard is not
+    /// the last segment of its path.
     pub fn new(routes_config: Routes) -> Router {
-        Router { routes_config }
+        let mut route_trees: HashMap<String, RouteNode> = HashMap::new();
+
+        for (route, handler) in routes_config {
+            let parts: Vec<&str> = route.split(' ').collect();
+
+            let (method, path) = match parts.as_slice() {
+                [method, path] | [method, path, _] => (*method, *path),
+                _ => continue,
+            };
+
+            let Some(path) = path.strip_prefix('/') else {
+                continue;
+            };
+
+            route_trees.entry(method.to_string()).or_default().insert(
+                route,
+                path.split('/').collect::<Vec<&str>>().as_slice(),
+                handler,
+            );
+        }
+
+        Router {
+            route_trees,
+            middlewares: Vec::new(),
+            state: AppState::new(),
+        }
+    }
+
+    /// Returns the router giving the app state to its handlers
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The app state holding the values shared with handlers
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{
+    ///     app_state::AppState, expansions_model::ExpansionsAccess,
+    ///     mock_expansions_model::MockExpansionsModel, router::Router,
+    /// };
+    /// use std::{collections::HashMap, sync::{Arc, RwLock}};
+    ///
+    /// let expansions_model: Arc<RwLock<dyn ExpansionsAccess>> =
+    ///     Arc::new(RwLock::new(MockExpansionsModel::default()));
+    /// let router = Router::new(HashMap::new()).with_state(AppState::new().with(expansions_model));
+    /// ```
+    pub fn with_state(mut self, state: AppState) -> Router {
+        self.state = state;
+        self
+    }
+
+    /// Returns the router with a middleware run around every request after those already added
+    ///
+    /// # Arguments
+    ///
+    /// * `middleware` - A Middleware to run before and after each request is routed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{middleware::{AccessLog, RequestId}, router::Router};
+    /// use std::collections::HashMap;
+    ///
+    /// let router = Router::new(HashMap::new())
+    ///     .with_middleware(AccessLog)
+    ///     .with_middleware(RequestId::new());
+    /// ```
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Router {
+        self.middlewares.push(Box::new(middleware));
+        self
     }
 
-    /// Returns a byte response to an incoming request by matching the requests status line
-    /// to its own routes config field
+    /// Returns a byte response to an incoming request by matching the requests method and path
+    /// to its routes. At each path segment a literal segment is preferred over a path param which
+    /// is preferred over a wildcard, falling back to the next choice when the rest of the path
+    /// does not match, so the same route is used whatever order the routes were given in.
+    ///
+    /// A path with routes for other methods is answered with a 405 response listing them in an
+    /// `Allow` header, while a path without any routes is answered with a 404 response. `HEAD`
+    /// requests are answered by the `GET` route of a path without the response body and
+    /// `OPTIONS` requests are answered with the `Allow` header of the path, or of the whole
+    /// server for `OPTIONS *`, unless the routes say otherwise.
+    ///
+    /// The `before` hooks of the middlewares are run in order ahead of routing and the `after`
+    /// hooks of those which ran in reverse order with the response. Errors from the middlewares
+    /// and controller functions are answered by the DefaultController.
     ///
     /// # Arguments
     ///
@@ -48,51 +223,312 @@ impl Router {
     /// # use npm_expansions::{
     /// #    router::{Routes, Router},
     /// #    http_request::HttpRequest,
-    /// #    mock_expansions_model::MockExpansionsModel,
-    /// #    npm_expansion_error::NpmExpansionsError,
-    /// #    expansions_model::ExpansionsAccess,
-    /// #    npm_controller::ControllerFunction,
+    /// #    router::handler,
     /// #    http_response::HttpResponse,
+    /// #    http_status::HttpStatus,
     /// # };
-    /// # use std::{collections::HashMap, sync::{Arc, RwLock}};
+    /// # use std::collections::HashMap;
     ///
     ///
-    /// # let actual_route: ControllerFunction = |_,_| Ok(HttpResponse::new("200", "OK", "", "actual_route"));
+    /// # let actual_route = handler(|_, _| Ok(HttpResponse::new(HttpStatus::Ok).with_body("actual_route")));
     /// # let route_config: Routes =
     /// #     HashMap::from([("GET / HTTP/1.1", actual_route)]);
     /// let router = Router::new(route_config);
-    /// let request = HttpRequest::new("127.0.0.1", "GET / HTTP/1.1", HashMap::new(),  HashMap::new());
-    /// let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
-    /// let response = router.route_request(&request, mock_expansions_model);
+    /// let request = HttpRequest::new("127.0.0.1", "GET / HTTP/1.0", HashMap::new(),  HashMap::new());
+    /// let response = router.route_request(&request);
+    ///
+    /// assert_eq!(response.unwrap().body(), b"actual_route");
     ///
-    /// assert_eq!(response.unwrap().contents(), "actual_route");
+    /// let request = HttpRequest::new("127.0.0.1", "DELETE / HTTP/1.1", HashMap::from([("Accept".to_string(), "*/*".to_string())]),  HashMap::new());
+    /// let response = router.route_request(&request).unwrap();
+    ///
+    /// assert_eq!(response.status_code(), 405);
+    /// assert_eq!(response.headers().get("Allow"), Some("GET, HEAD, OPTIONS"));
     /// ```
-    pub fn route_request(
+    pub fn route_request(&self, request: &HttpRequest) -> Result<HttpResponse, NpmExpansionsError> {
+        let mut request = request.clone();
+        let mut ran = 0;
+        let mut answered = None;
+
+        for middleware in &self.middlewares {
+            ran += 1;
+
+            match middleware.before(&mut request) {
+                Ok(None) => continue,
+                Ok(Some(response)) => answered = Some(Ok(response)),
+                Err(error) => answered = Some(Err(error)),
+            }
+
+            break;
+        }
+
+        let response = match answered.unwrap_or_else(|| self.dispatch(&request)) {
+            Ok(response) => response,
+            Err(error) => DefaultController::from_error(&error),
+        };
+
+        let response = if request.method() == "HEAD" {
+            response.without_body()
+        } else {
+            response
+        };
+
+        self.run_after(&self.middlewares[..ran], &request, response)
+    }
+
+    /// Runs the `after` hooks of every middleware with a response made without routing the
+    /// request e.g. to a request which could not be read, so that it is still logged
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request being answered
+    /// * `response` - The response to the request
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{
+    ///     http_request::HttpRequest, http_response::HttpResponse, http_status::HttpStatus,
+    ///     middleware::RequestId, router::Router,
+    /// };
+    /// use std::collections::HashMap;
+    ///
+    /// let router = Router::new(HashMap::new()).with_middleware(RequestId::new());
+    /// let request = HttpRequest::new(
+    ///     "127.0.0.1",
+    ///     "GET / HTTP/1.1",
+    ///     HashMap::from([("X-Request-Id".to_string(), "abc".to_string())]),
+    ///     HashMap::new(),
+    /// );
+    /// let response = HttpResponse::new(HttpStatus::BadRequest);
+    ///
+    /// let response = router.finish_response(&request, response).unwrap();
+    ///
+    /// assert_eq!(response.headers().to_string(), "X-Request-Id: abc");
+    /// ```
+    pub fn finish_response(
         &self,
         request: &HttpRequest,
-        expansions_model: Arc<RwLock<dyn ExpansionsAccess>>,
+        response: HttpResponse,
     ) -> Result<HttpResponse, NpmExpansionsError> {
-        let status_line = request.status_line_path();
-        let controller_function = self.routes_config.get(status_line.as_str());
+        self.run_after(&self.middlewares, request, response)
+    }
+
+    fn run_after(
+        &self,
+        middlewares: &[Box<dyn Middleware>],
+        request: &HttpRequest,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, NpmExpansionsError> {
+        middlewares
+            .iter()
+            .rev()
+            .try_fold(response, |response, middleware| {
+                middleware.after(request, response)
+            })
+    }
+
+    fn dispatch(&self, request: &HttpRequest) -> Result<HttpResponse, NpmExpansionsError> {
+        let method = request.method();
+        let path = request.path();
+
+        let route = self.find_route(method, path).or_else(|| match method {
+            "HEAD" => self.find_route("GET", path),
+            _ => None,
+        });
+
+        match route {
+            Some((endpoint, path_params)) if path_params.is_empty() => {
+                return (endpoint.handler)(request, &self.state);
+            }
+            Some((endpoint, path_params)) => {
+                return (endpoint.handler)(
+                    &request.clone().with_path_params(path_params),
+                    &self.state,
+                );
+            }
+            None => {}
+        }
+
+        let allowed_methods = self.allowed_methods(path);
+
+        if allowed_methods.is_empty() {
+            return Ok(DefaultController::not_found());
+        }
+
+        let allow = allowed_methods.join(", ");
+
+        if method == "OPTIONS" {
+            return Ok(HttpResponse::new(HttpStatus::Ok).with_header("Allow", &allow));
+        }
+
+        Ok(DefaultController::method_not_allowed().with_header("Allow", &allow))
+    }
+
+    fn find_route(&self, method: &str, path: &str) -> Option<(&Endpoint, HashMap<String, String>)> {
+        let segments = path_segments(path)?;
+        let mut param_values = Vec::new();
+
+        let endpoint = self
+            .route_trees
+            .get(method)?
+            .find(&segments, &mut param_values)?;
+
+        let path_params = endpoint
+            .param_names
+            .iter()
+            .cloned()
+            .zip(param_values)
+            .collect();
+
+        Some((endpoint, path_params))
+    }
+
+    /// Returns the sorted methods with a route for the path, or for any path given `*`, adding
+    /// `HEAD` for paths with a `GET` route and `OPTIONS` for paths with any route
+    fn allowed_methods(&self, path: &str) -> Vec<&str> {
+        let segments = path_segments(path);
 
-        if let Some(controller_function) = controller_function {
-            return controller_function(request, expansions_model);
+        let mut allowed_methods: Vec<&str> = self
+            .route_trees
+            .iter()
+            .filter(|(_, route_tree)| match &segments {
+                Some(segments) => route_tree.find(segments, &mut Vec::new()).is_some(),
+                None => path == "*",
+            })
+            .map(|(method, _)| method.as_str())
+            .collect();
+
+        if allowed_methods.contains(&"GET") {
+            allowed_methods.push("HEAD");
+        }
+
+        if !allowed_methods.is_empty() {
+            allowed_methods.push("OPTIONS");
+        }
+
+        allowed_methods.sort_unstable();
+        allowed_methods.dedup();
+
+        allowed_methods
+    }
+}
+
+/// Splits a path into the segments following its leading `/`
+fn path_segments(path: &str) -> Option<Vec<&str>> {
+    Some(path.strip_prefix('/')?.split('/').collect())
+}
+
+impl RouteNode {
+    fn insert(&mut self, route: &str, segments: &[&str], handler: Handler) {
+        let mut node = self;
+        let mut param_names = Vec::new();
+
+        for (position, segment) in segments.iter().enumerate() {
+            match RouteSegment::parse(segment) {
+                RouteSegment::Literal(literal) => {
+                    node = node.literals.entry(literal.to_string()).or_default();
+                }
+                RouteSegment::Param(name) => {
+                    param_names.push(name.to_string());
+                    node = node.param.get_or_insert_with(Box::default);
+                }
+                RouteSegment::Wildcard(name) => {
+                    assert!(
+                        position == segments.len() - 1,
+                        "The wildcard in route {route} must be its last segment"
+                    );
+
+                    param_names.push(name.to_string());
+
+                    return Self::set_endpoint(&mut node.wildcard, route, handler, param_names);
+                }
+            }
+        }
+
+        Self::set_endpoint(&mut node.endpoint, route, handler, param_names)
+    }
+
+    fn set_endpoint(
+        endpoint: &mut Option<Endpoint>,
+        route: &str,
+        handler: Handler,
+        param_names: Vec<String>,
+    ) {
+        assert!(
+            endpoint.is_none(),
+            "Route {route} has the same method and path as another route"
+        );
+
+        *endpoint = Some(Endpoint {
+            handler,
+            param_names,
+        });
+    }
+
+    /// Finds the endpoint matching the remaining segments of a path, pushing the value of each
+    /// path param and wildcard on the way
+    fn find(&self, segments: &[&str], param_values: &mut Vec<String>) -> Option<&Endpoint> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return self.endpoint.as_ref();
+        };
+
+        if let Some(endpoint) = self
+            .literals
+            .get(*segment)
+            .and_then(|node| node.find(rest, param_values))
+        {
+            return Some(endpoint);
+        }
+
+        if segment.is_empty() {
+            return None;
+        }
+
+        if let Some(node) = &self.param {
+            param_values.push(segment.to_string());
+
+            if let Some(endpoint) = node.find(rest, param_values) {
+                return Some(endpoint);
+            }
+
+            param_values.pop();
         }
 
-        DefaultController::not_found(request)
+        let endpoint = self.wildcard.as_ref()?;
+        param_values.push(segments.join("/"));
+
+        Some(endpoint)
+    }
+}
+
+impl RouteSegment<'_> {
+    fn parse(segment: &str) -> RouteSegment<'_> {
+        match segment
+            .strip_prefix('{')
+            .and_then(|segment| segment.strip_suffix('}'))
+        {
+            Some(name) => match name.strip_prefix('*') {
+                Some(name) => RouteSegment::Wildcard(name),
+                None => RouteSegment::Param(name),
+            },
+            None => RouteSegment::Literal(segment),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::mock_expansions_model::MockExpansionsModel;
+    use crate::npm_controller::NpmController;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     use super::*;
 
     #[test]
     fn route_response() {
-        let controller_function: ControllerFunction =
-            |_, _| Ok(HttpResponse::new("200", "OK", "", "actual_route"));
+        let controller_function =
+            handler(|_, _| Ok(HttpResponse::new(HttpStatus::Ok).with_body("actual_route")));
         let route_config: Routes = HashMap::from([("GET / HTTP/1.1", controller_function)]);
 
         let router = Router::new(route_config);
@@ -102,16 +538,15 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
         );
-        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
-        let response = router.route_request(&request, mock_expansions_model);
+        let response = router.route_request(&request);
 
-        assert_eq!(response.unwrap().contents(), "actual_route")
+        assert_eq!(response.unwrap().body(), b"actual_route")
     }
 
     #[test]
     fn route_not_found() {
-        let not_found: ControllerFunction =
-            |_, _| Ok(HttpResponse::new("404", "NOT FOUND", "", "not found"));
+        let not_found =
+            handler(|_, _| Ok(HttpResponse::new(HttpStatus::NotFound).with_body("not found")));
         let route_config: Routes = HashMap::from([("404", not_found)]);
 
         let router = Router::new(route_config);
@@ -121,8 +556,7 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
         );
-        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
-        let response = router.route_request(&request, mock_expansions_model);
+        let response = router.route_request(&request);
 
         assert!(response.is_ok())
     }
@@ -136,9 +570,333 @@ mod tests {
             HashMap::new(),
             HashMap::new(),
         );
-        let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
-        let response = router.route_request(&request, mock_expansions_model);
+        let response = router.route_request(&request);
 
         assert!(response.is_ok())
     }
+
+    #[test]
+    fn route_with_path_params() {
+        let controller_function = handler(|request, _| {
+            Ok(HttpResponse::new(HttpStatus::Ok)
+                .with_body(request.path_params().get("id").unwrap().as_str()))
+        });
+        let route_config: Routes =
+            HashMap::from([("GET /api/expansions/{id} HTTP/1.1", controller_function)]);
+
+        let router = Router::new(route_config);
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/expansions/abc?format=record HTTP/1.1",
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let response = router.route_request(&request);
+
+        assert_eq!(response.unwrap().body(), b"abc")
+    }
+
+    #[test]
+    fn literal_route_preferred_over_path_params() {
+        let literal = handler(|_, _| Ok(HttpResponse::new(HttpStatus::Ok).with_body("literal")));
+        let param = handler(|_, _| Ok(HttpResponse::new(HttpStatus::Ok).with_body("param")));
+        let route_config: Routes = HashMap::from([
+            ("GET /api/{name}/{id} HTTP/1.1", param),
+            ("GET /api/expansions/{id} HTTP/1.1", literal),
+        ]);
+
+        let router = Router::new(route_config);
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/expansions/abc HTTP/1.1",
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let response = router.route_request(&request);
+
+        assert_eq!(response.unwrap().body(), b"literal")
+    }
+
+    #[test]
+    fn path_params_do_not_match_empty_segments() {
+        let controller_function =
+            handler(|_, _| Ok(HttpResponse::new(HttpStatus::Ok).with_body("actual_route")));
+        let route_config: Routes =
+            HashMap::from([("GET /api/expansions/{id} HTTP/1.1", controller_function)]);
+
+        let router = Router::new(route_config);
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/expansions/ HTTP/1.1",
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let response = router.route_request(&request);
+
+        assert_ne!(response.unwrap().body(), b"actual_route")
+    }
+
+    #[test]
+    fn controller_uses_expansions_model_from_state() {
+        let expansions_model: Arc<RwLock<dyn ExpansionsAccess>> =
+            Arc::new(RwLock::new(MockExpansionsModel::default()));
+        let route_config: Routes =
+            HashMap::from([("GET /api/random", controller(NpmController::random))]);
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET /api/random HTTP/1.1",
+            HashMap::new(),
+            HashMap::new(),
+        );
+
+        let with_state =
+            Router::new(route_config).with_state(AppState::new().with(expansions_model));
+        let without_state = Router::new(HashMap::from([(
+            "GET /api/random",
+            controller(NpmController::random),
+        )]));
+
+        assert_eq!(
+            with_state.route_request(&request).unwrap().status_code(),
+            200
+        );
+        assert_eq!(
+            without_state.route_request(&request).unwrap().status_code(),
+            500
+        );
+    }
+
+    #[test]
+    fn handlers_share_state() {
+        struct Greeting(&'static str);
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let count = requests.clone();
+        let greet = handler(move |_, state| {
+            count.fetch_add(1, Ordering::SeqCst);
+
+            Ok(HttpResponse::new(HttpStatus::Ok).with_body(state.require::<Greeting>()?.0))
+        });
+        let router = Router::new(HashMap::from([("GET /", greet)]))
+            .with_state(AppState::new().with(Greeting("hello")));
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            "GET / HTTP/1.1",
+            HashMap::new(),
+            HashMap::new(),
+        );
+
+        assert_eq!(router.route_request(&request).unwrap().body(), b"hello");
+        assert_eq!(router.route_request(&request).unwrap().body(), b"hello");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    /// Routes a request through routes whose controller functions answer with the path params
+    fn route(routes: &[&'static str], status_line: &str) -> HttpResponse {
+        fn echo_route(request: &HttpRequest) -> Result<HttpResponse, NpmExpansionsError> {
+            let mut path_params: Vec<String> = request
+                .path_params()
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            path_params.sort();
+
+            Ok(HttpResponse::new(HttpStatus::Ok).with_body(path_params.join("&")))
+        }
+
+        let route_config: Routes = routes
+            .iter()
+            .map(|route| (*route, handler(|request, _| echo_route(request))))
+            .collect();
+
+        let router = Router::new(route_config);
+        let request = HttpRequest::new(
+            "127.0.0.1",
+            status_line,
+            HashMap::from([("Accept".to_string(), "*/*".to_string())]),
+            HashMap::new(),
+        );
+
+        router.route_request(&request).unwrap()
+    }
+
+    /// Returns the path params a request was routed with, or the status code if it was not routed
+    fn routed_to(routes: &[&'static str], status_line: &str) -> String {
+        let response = route(routes, status_line);
+
+        if response.status_code() == 200 {
+            String::from_utf8_lossy(response.body()).to_string()
+        } else {
+            response.status_code().to_string()
+        }
+    }
+
+    #[test]
+    fn ignores_http_version() {
+        let routes = ["GET /api/random HTTP/1.1", "GET /api/all"];
+
+        assert_eq!(routed_to(&routes, "GET /api/random HTTP/1.0"), "");
+        assert_eq!(routed_to(&routes, "GET /api/all HTTP/1.1"), "");
+        assert_eq!(routed_to(&routes, "GET /api/all HTTP/2"), "");
+    }
+
+    #[test]
+    fn matches_method() {
+        let routes = ["POST /api/expansions"];
+
+        assert_eq!(routed_to(&routes, "POST /api/expansions HTTP/1.1"), "");
+        assert_eq!(routed_to(&routes, "GET /api/expansions HTTP/1.1"), "405");
+    }
+
+    #[test]
+    fn wildcard_matches_rest_of_path() {
+        let routes = ["GET /static/{*path}"];
+
+        assert_eq!(
+            routed_to(&routes, "GET /static/css/main.css HTTP/1.1"),
+            "path=css/main.css"
+        );
+        assert_eq!(routed_to(&routes, "GET /static/ HTTP/1.1"), "404");
+        assert_eq!(routed_to(&routes, "GET /static HTTP/1.1"), "404");
+    }
+
+    #[test]
+    fn overlapping_routes_prefer_literal_then_param_then_wildcard() {
+        let routes = [
+            "GET /api/{*rest}",
+            "GET /api/{name}/{id}",
+            "GET /api/expansions/{id}",
+            "GET /api/expansions/random",
+        ];
+
+        assert_eq!(
+            routed_to(&routes, "GET /api/expansions/random HTTP/1.1"),
+            ""
+        );
+        assert_eq!(
+            routed_to(&routes, "GET /api/expansions/abc HTTP/1.1"),
+            "id=abc"
+        );
+        assert_eq!(
+            routed_to(&routes, "GET /api/search/abc HTTP/1.1"),
+            "id=abc&name=search"
+        );
+        assert_eq!(
+            routed_to(&routes, "GET /api/search/abc/def HTTP/1.1"),
+            "rest=search/abc/def"
+        );
+    }
+
+    #[test]
+    fn falls_back_when_literal_branch_does_not_match() {
+        let routes = ["GET /api/expansions/random", "GET /api/{name}/count"];
+
+        assert_eq!(
+            routed_to(&routes, "GET /api/expansions/count HTTP/1.1"),
+            "name=expansions"
+        );
+    }
+
+    #[test]
+    fn ignores_invalid_routes() {
+        let routes = ["GET", "GET api/all", "GET /api/all HTTP/1.1 extra"];
+
+        assert_eq!(routed_to(&routes, "GET /api/all HTTP/1.1"), "404");
+    }
+
+    #[test]
+    #[should_panic(expected = "same method and path")]
+    fn duplicate_routes_panic() {
+        routed_to(
+            &["GET /api/all HTTP/1.0", "GET /api/all HTTP/1.1"],
+            "GET / HTTP/1.1",
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "must be its last segment")]
+    fn wildcard_before_last_segment_panics() {
+        routed_to(&["GET /static/{*path}/index"], "GET / HTTP/1.1");
+    }
+
+    const API_ROUTES: [&str; 3] = [
+        "GET /api/random",
+        "GET /api/expansions/{id}",
+        "POST /api/expansions",
+    ];
+
+    #[test]
+    fn wrong_method_is_not_allowed() {
+        let response = route(&API_ROUTES, "POST /api/random HTTP/1.1");
+
+        assert_eq!(response.status_code(), 405);
+        assert_eq!(response.headers().get("Allow"), Some("GET, HEAD, OPTIONS"));
+
+        let response = route(&API_ROUTES, "DELETE /api/expansions HTTP/1.1");
+
+        assert_eq!(response.status_code(), 405);
+        assert_eq!(response.headers().get("Allow"), Some("OPTIONS, POST"));
+    }
+
+    #[test]
+    fn unknown_path_is_not_found() {
+        let response = route(&API_ROUTES, "POST /api/unknown HTTP/1.1");
+
+        assert_eq!(response.status_code(), 404);
+        assert!(!response.headers().contains("Allow"));
+    }
+
+    #[test]
+    fn head_uses_get_route_without_body() {
+        let response = route(&API_ROUTES, "HEAD /api/expansions/abc HTTP/1.1");
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(
+            response.into_bytes_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn head_of_unknown_path_has_no_body() {
+        let response = route(&API_ROUTES, "HEAD /api/unknown HTTP/1.1");
+
+        assert_eq!(response.status_code(), 404);
+        assert!(response.into_bytes_vec().ends_with(b"\r\n\r\n"));
+    }
+
+    #[test]
+    fn options_lists_allowed_methods() {
+        let response = route(&API_ROUTES, "OPTIONS /api/expansions/abc HTTP/1.1");
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.headers().get("Allow"), Some("GET, HEAD, OPTIONS"));
+
+        let response = route(&API_ROUTES, "OPTIONS * HTTP/1.1");
+
+        assert_eq!(
+            response.headers().to_string(),
+            "Allow: GET, HEAD, OPTIONS, POST"
+        );
+    }
+
+    #[test]
+    fn options_of_unknown_path_is_not_found() {
+        let response = route(&API_ROUTES, "OPTIONS /api/unknown HTTP/1.1");
+
+        assert_eq!(response.status_code(), 404);
+    }
+
+    #[test]
+    fn explicit_head_and_options_routes_are_used() {
+        let routes = ["GET /api/all", "HEAD /api/all", "OPTIONS /api/all"];
+
+        assert_eq!(routed_to(&routes, "OPTIONS /api/all HTTP/1.1"), "");
+        assert_eq!(
+            route(&routes, "OPTIONS /api/all HTTP/1.1")
+                .headers()
+                .to_string(),
+            ""
+        );
+    }
 }
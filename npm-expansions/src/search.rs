@@ -0,0 +1,465 @@
+use crate::expansion::Expansion;
+use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
+use levenshtein::levenshtein;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    fmt,
+    str::FromStr,
+};
+use strsim::jaro_winkler;
+
+/// Enum for the different algorithms used to score how similar an expansion is to a search query
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SearchAlgorithm {
+    /// The Jaro-Winkler similarity of the whole expansion and query
+    #[default]
+    JaroWinkler,
+    /// The case-insensitive Levenshtein distance of the whole expansion and query,
+    /// normalised by the length of the longer of the two
+    Levenshtein,
+    /// The fraction of query words which begin a word of the expansion, ignoring case
+    WordPrefix,
+    /// Whether the expansion contains the query ignoring case, weighted by how much of the
+    /// expansion the query covers
+    Substring,
+}
+
+impl SearchAlgorithm {
+    /// Returns the similarity of the given expansion text to the given query from 0 (no similarity) to 1 (identical)
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text of a npm expansion
+    /// * `query` - A search query
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::search::SearchAlgorithm;
+    ///
+    /// assert_eq!(SearchAlgorithm::WordPrefix.score("Nacho Pizza Marinade", "nac piz"), 1.0);
+    /// assert_eq!(SearchAlgorithm::Substring.score("Nacho Pizza Marinade", "burger"), 0.0);
+    /// ```
+    pub fn score(&self, text: &str, query: &str) -> f64 {
+        match self {
+            SearchAlgorithm::JaroWinkler => jaro_winkler(text, query),
+            SearchAlgorithm::Levenshtein => {
+                let text = text.to_lowercase();
+                let query = query.to_lowercase();
+                let longest = text.chars().count().max(query.chars().count());
+
+                if longest == 0 {
+                    return 1.0;
+                }
+
+                1.0 - levenshtein(&text, &query) as f64 / longest as f64
+            }
+            SearchAlgorithm::WordPrefix => {
+                let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+                let query_words: Vec<String> =
+                    query.split_whitespace().map(str::to_lowercase).collect();
+
+                if query_words.is_empty() {
+                    return 0.0;
+                }
+
+                let matched_words = query_words
+                    .iter()
+                    .filter(|query_word| words.iter().any(|word| word.starts_with(*query_word)))
+                    .count();
+
+                matched_words as f64 / query_words.len() as f64
+            }
+            SearchAlgorithm::Substring => {
+                let text = text.to_lowercase();
+                let query = query.trim().to_lowercase();
+
+                if query.is_empty() || !text.contains(&query) {
+                    return 0.0;
+                }
+
+                query.chars().count() as f64 / text.chars().count() as f64
+            }
+        }
+    }
+}
+
+impl FromStr for SearchAlgorithm {
+    type Err = NpmExpansionsError;
+
+    fn from_str(algorithm: &str) -> Result<Self, Self::Err> {
+        match algorithm {
+            "jaro_winkler" => Ok(SearchAlgorithm::JaroWinkler),
+            "levenshtein" => Ok(SearchAlgorithm::Levenshtein),
+            "word_prefix" => Ok(SearchAlgorithm::WordPrefix),
+            "substring" => Ok(SearchAlgorithm::Substring),
+            _ => Err(NpmExpansionsError::new(
+                NpmErrorKind::InvalidQueryParam,
+                &format!(
+                    "The algorithm must be one of jaro_winkler, levenshtein, word_prefix or substring but was {algorithm}"
+                ),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SearchAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let algorithm = match self {
+            SearchAlgorithm::JaroWinkler => "jaro_winkler",
+            SearchAlgorithm::Levenshtein => "levenshtein",
+            SearchAlgorithm::WordPrefix => "word_prefix",
+            SearchAlgorithm::Substring => "substring",
+        };
+
+        write!(f, "{algorithm}")
+    }
+}
+
+/// Options controlling which matches a search returns
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchOptions {
+    /// The maximum number of matches returned. None means every match
+    pub limit: Option<usize>,
+    /// The minimum similarity score from 0 to 1 a match must have to be returned
+    pub min_score: f64,
+    /// The algorithm used to score expansions. None means the default algorithm of the expansions model
+    pub algorithm: Option<SearchAlgorithm>,
+}
+
+/// A npm expansion matched by a search and how similar it is to the search query
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoredExpansion<'a> {
+    /// The matched expansion
+    pub expansion: &'a Expansion,
+    /// The similarity of the expansion to the search query from 0 (no similarity) to 1 (identical)
+    pub score: f64,
+}
+
+/// The matches of a search ranked best first along with the total number of matches
+/// before the limit of the search was applied
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchResults<'a> {
+    /// The matches ranked by score, best match first
+    pub matches: Vec<ScoredExpansion<'a>>,
+    /// The number of expansions which met the minimum score of the search
+    pub total: usize,
+}
+
+impl<'a> SearchResults<'a> {
+    /// Ranks the given scored expansions, dropping those with no similarity or a score below
+    /// the minimum score and keeping at most limit matches. When a limit is given only the best
+    /// matches seen so far are kept in a bounded heap rather than sorting every match.
+    /// Matches with the same score keep the order they were given in.
+    ///
+    /// # Arguments
+    ///
+    /// * `scored_expansions` - Every expansion scored against a search query
+    /// * `options` - The limit and minimum score of the search
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{
+    ///     expansion::Expansion,
+    ///     search::{ScoredExpansion, SearchOptions, SearchResults},
+    /// };
+    ///
+    /// let nacho = Expansion::new("Nacho Pizza Marinade");
+    /// let nail = Expansion::new("Nail Polish Makeover");
+    /// let results = SearchResults::rank(
+    ///     vec![
+    ///         ScoredExpansion { expansion: &nail, score: 0.4 },
+    ///         ScoredExpansion { expansion: &nacho, score: 0.9 },
+    ///     ],
+    ///     &SearchOptions { limit: Some(5), min_score: 0.5, algorithm: None },
+    /// );
+    ///
+    /// assert_eq!(results.total, 1);
+    /// assert_eq!(results.matches[0].expansion.text(), "Nacho Pizza Marinade");
+    /// ```
+    pub fn rank(
+        scored_expansions: impl IntoIterator<Item = ScoredExpansion<'a>>,
+        options: &SearchOptions,
+    ) -> SearchResults<'a> {
+        let mut total = 0;
+        let mut best_matches: BinaryHeap<Reverse<RankedExpansion>> = BinaryHeap::new();
+        let mut all_matches: Vec<RankedExpansion> = Vec::new();
+
+        let ranked_expansions = scored_expansions
+            .into_iter()
+            .filter(|scored| scored.score > 0.0 && scored.score >= options.min_score)
+            .enumerate()
+            .map(|(position, scored)| RankedExpansion { position, scored });
+
+        for ranked in ranked_expansions {
+            total += 1;
+
+            match options.limit {
+                Some(limit) => {
+                    best_matches.push(Reverse(ranked));
+
+                    if best_matches.len() > limit {
+                        best_matches.pop();
+                    }
+                }
+                None => all_matches.push(ranked),
+            }
+        }
+
+        all_matches.extend(best_matches.into_iter().map(|Reverse(ranked)| ranked));
+        all_matches.sort_unstable_by(|a, b| b.cmp(a));
+
+        SearchResults {
+            matches: all_matches
+                .into_iter()
+                .map(|ranked| ranked.scored)
+                .collect(),
+            total,
+        }
+    }
+}
+
+/// A scored expansion and the position it was given to the ranking in. A ranked expansion is
+/// greater than another if it has a higher score or the same score and an earlier position.
+struct RankedExpansion<'a> {
+    position: usize,
+    scored: ScoredExpansion<'a>,
+}
+
+impl Ord for RankedExpansion<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.scored
+            .score
+            .total_cmp(&other.scored.score)
+            .then_with(|| other.position.cmp(&self.position))
+    }
+}
+
+impl PartialOrd for RankedExpansion<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RankedExpansion<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RankedExpansion<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn scored(expansions: &[Expansion]) -> Vec<ScoredExpansion<'_>> {
+        expansions
+            .iter()
+            .enumerate()
+            .map(|(index, expansion)| ScoredExpansion {
+                expansion,
+                score: (index + 1) as f64 / 10.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rank_orders_best_first() {
+        let expansions = vec![
+            Expansion::new("Nacho Pizza Marinade"),
+            Expansion::new("Nacho Portion Monitor"),
+        ];
+
+        let results = SearchResults::rank(scored(&expansions), &SearchOptions::default());
+
+        assert_eq!(results.matches[0].expansion.text(), "Nacho Portion Monitor");
+        assert_eq!(results.total, 2)
+    }
+
+    #[test]
+    fn rank_applies_limit_after_counting() {
+        let expansions = vec![Expansion::new("Nacho Pizza Marinade"); 5];
+        let options = SearchOptions {
+            limit: Some(2),
+            ..SearchOptions::default()
+        };
+
+        let results = SearchResults::rank(scored(&expansions), &options);
+
+        assert_eq!(results.matches.len(), 2);
+        assert_eq!(results.total, 5)
+    }
+
+    #[test]
+    fn rank_drops_matches_below_min_score() {
+        let expansions = vec![Expansion::new("Nacho Pizza Marinade"); 5];
+        let options = SearchOptions {
+            min_score: 0.25,
+            ..SearchOptions::default()
+        };
+
+        let results = SearchResults::rank(scored(&expansions), &options);
+
+        assert!(results.matches.iter().all(|scored| scored.score >= 0.25));
+        assert_eq!(results.total, 3)
+    }
+
+    #[test]
+    fn rank_keeps_order_of_equal_scores() {
+        let expansions = [
+            Expansion::new("Nacho Pizza Marinade"),
+            Expansion::new("Nacho Portion Monitor"),
+            Expansion::new("Nacho Portmanteau Meltdown"),
+        ];
+        let equally_scored = expansions.iter().map(|expansion| ScoredExpansion {
+            expansion,
+            score: 0.5,
+        });
+        let options = SearchOptions {
+            limit: Some(2),
+            ..SearchOptions::default()
+        };
+
+        let results = SearchResults::rank(equally_scored, &options);
+
+        assert_eq!(
+            results
+                .matches
+                .iter()
+                .map(|scored| scored.expansion.text())
+                .collect::<Vec<&str>>(),
+            ["Nacho Pizza Marinade", "Nacho Portion Monitor"]
+        )
+    }
+
+    #[test]
+    fn rank_with_limit_matches_full_sort() {
+        let expansions = vec![Expansion::new("Nacho Pizza Marinade"); 50];
+        let options = SearchOptions {
+            limit: Some(7),
+            ..SearchOptions::default()
+        };
+
+        let limited = SearchResults::rank(scored_pseudo_randomly(&expansions), &options);
+        let mut full = SearchResults::rank(
+            scored_pseudo_randomly(&expansions),
+            &SearchOptions::default(),
+        );
+        full.matches.truncate(7);
+
+        assert_eq!(limited.matches, full.matches);
+        assert_eq!(limited.total, 50)
+    }
+
+    fn scored_pseudo_randomly(expansions: &[Expansion]) -> Vec<ScoredExpansion<'_>> {
+        expansions
+            .iter()
+            .enumerate()
+            .map(|(index, expansion)| ScoredExpansion {
+                expansion,
+                score: ((index * 37) % 50 + 1) as f64 / 50.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rank_drops_matches_without_similarity() {
+        let expansion = Expansion::new("Nacho Pizza Marinade");
+        let scored = ScoredExpansion {
+            expansion: &expansion,
+            score: 0.0,
+        };
+
+        assert_eq!(
+            SearchResults::rank(vec![scored], &SearchOptions::default()).total,
+            0
+        )
+    }
+
+    #[test_case(SearchAlgorithm::JaroWinkler; "jaro winkler")]
+    #[test_case(SearchAlgorithm::Levenshtein; "levenshtein")]
+    #[test_case(SearchAlgorithm::WordPrefix; "word prefix")]
+    #[test_case(SearchAlgorithm::Substring; "substring")]
+    fn exact_match_scores_one(algorithm: SearchAlgorithm) {
+        assert_eq!(
+            algorithm.score("Nacho Pizza Marinade", "Nacho Pizza Marinade"),
+            1.0
+        )
+    }
+
+    #[test_case(SearchAlgorithm::JaroWinkler; "jaro winkler")]
+    #[test_case(SearchAlgorithm::Levenshtein; "levenshtein")]
+    #[test_case(SearchAlgorithm::WordPrefix; "word prefix")]
+    #[test_case(SearchAlgorithm::Substring; "substring")]
+    fn closer_match_scores_higher(algorithm: SearchAlgorithm) {
+        assert!(
+            algorithm.score("Nacho Pizza Marinade", "Nacho Pizza")
+                > algorithm.score("Nail Polish Makeover", "Nacho Pizza")
+        )
+    }
+
+    #[test_case(SearchAlgorithm::JaroWinkler; "jaro winkler")]
+    #[test_case(SearchAlgorithm::Levenshtein; "levenshtein")]
+    #[test_case(SearchAlgorithm::WordPrefix; "word prefix")]
+    #[test_case(SearchAlgorithm::Substring; "substring")]
+    fn unrelated_query_scores_zero(algorithm: SearchAlgorithm) {
+        assert_eq!(algorithm.score("Nacho", "zzzz"), 0.0)
+    }
+
+    #[test]
+    fn levenshtein_is_normalised() {
+        assert_eq!(
+            SearchAlgorithm::Levenshtein.score("Nacho Pizza Marinade", "nacho pizza marinadE"),
+            1.0
+        );
+        assert_eq!(SearchAlgorithm::Levenshtein.score("abcd", "abxy"), 0.5)
+    }
+
+    #[test]
+    fn word_prefix_counts_matched_words() {
+        assert_eq!(
+            SearchAlgorithm::WordPrefix.score("Nacho Pizza Marinade", "pizz burger"),
+            0.5
+        )
+    }
+
+    #[test]
+    fn word_prefix_empty_query() {
+        assert_eq!(
+            SearchAlgorithm::WordPrefix.score("Nacho Pizza Marinade", " "),
+            0.0
+        )
+    }
+
+    #[test]
+    fn substring_ignores_case() {
+        assert_eq!(SearchAlgorithm::Substring.score("Nacho", "NACHO"), 1.0)
+    }
+
+    #[test]
+    fn substring_matches_across_words() {
+        assert!(SearchAlgorithm::Substring.score("Nacho Pizza Marinade", "o piz") > 0.0)
+    }
+
+    #[test_case("jaro_winkler", SearchAlgorithm::JaroWinkler; "jaro winkler")]
+    #[test_case("levenshtein", SearchAlgorithm::Levenshtein; "levenshtein")]
+    #[test_case("word_prefix", SearchAlgorithm::WordPrefix; "word prefix")]
+    #[test_case("substring", SearchAlgorithm::Substring; "substring")]
+    fn parse_algorithm(name: &str, expected: SearchAlgorithm) {
+        let algorithm: SearchAlgorithm = name.parse().unwrap();
+
+        assert_eq!(algorithm, expected);
+        assert_eq!(algorithm.to_string(), name)
+    }
+
+    #[test]
+    fn parse_unknown_algorithm() {
+        let error = "soundex".parse::<SearchAlgorithm>().unwrap_err();
+
+        assert!(matches!(error.kind(), NpmErrorKind::InvalidQueryParam))
+    }
+}
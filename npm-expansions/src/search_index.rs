@@ -0,0 +1,197 @@
+use crate::expansion::Expansion;
+use std::collections::{HashMap, HashSet};
+
+/// The fraction of the informative trigrams of a query which an expansion must share to be a candidate
+const MIN_TRIGRAM_OVERLAP: f64 = 0.2;
+
+/// Trigrams found in more than this fraction of expansions say too little about a query to narrow it
+const MAX_TRIGRAM_FREQUENCY: f64 = 0.5;
+
+type Trigram = [char; 3];
+
+/// A trigram index over the words of a list of expansions, built when the expansions are loaded so
+/// a search only scores the expansions which share enough trigrams with its query.
+///
+/// Each word is lowercased and padded with two leading spaces and one trailing space before being
+/// split into trigrams e.g. "Nacho" gives "  n", " na", "nac", "ach", "cho" and "ho ". Query words
+/// get no trailing space so that a partly typed word still matches the words it begins.
+#[derive(Clone, Debug, Default)]
+pub struct SearchIndex {
+    postings: HashMap<Trigram, Vec<usize>>,
+    len: usize,
+}
+
+impl SearchIndex {
+    /// Builds an index over the given expansions where each expansion is identified by its position
+    ///
+    /// # Arguments
+    ///
+    /// * `expansions` - The expansions to index
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::{expansion::Expansion, search_index::SearchIndex};
+    ///
+    /// let index = SearchIndex::build(&[
+    ///     Expansion::new("Nacho Pizza Marinade"),
+    ///     Expansion::new("Nail Polish Makeover"),
+    ///     Expansion::new("Newts Prefer MS-DOS"),
+    /// ]);
+    ///
+    /// assert_eq!(index.candidates("pizza"), Some(vec![0]));
+    /// ```
+    pub fn build(expansions: &[Expansion]) -> SearchIndex {
+        let mut index = SearchIndex::default();
+
+        for (position, expansion) in expansions.iter().enumerate() {
+            index.insert(position, expansion.text());
+        }
+
+        index
+    }
+
+    /// Adds the expansion text found at the given position to the index. Positions must be
+    /// inserted in increasing order.
+    pub fn insert(&mut self, position: usize, text: &str) {
+        let trigrams: HashSet<Trigram> = text
+            .split_whitespace()
+            .flat_map(|word| trigrams(&format!("  {} ", word.to_lowercase())))
+            .collect();
+
+        for trigram in trigrams {
+            self.postings.entry(trigram).or_default().push(position);
+        }
+
+        self.len = self.len.max(position + 1);
+    }
+
+    /// Returns the positions, in increasing order, of the expansions sharing enough trigrams with
+    /// the given query to be worth scoring. Returns None when the query has no informative trigrams,
+    /// e.g. it is blank or only contains trigrams common to most expansions, in which case every
+    /// expansion should be scored.
+    pub fn candidates(&self, query: &str) -> Option<Vec<usize>> {
+        let query_trigrams: HashSet<Trigram> = query
+            .split_whitespace()
+            .flat_map(|word| trigrams(&format!("  {}", word.to_lowercase())))
+            .collect();
+
+        let max_frequency = (self.len as f64 * MAX_TRIGRAM_FREQUENCY) as usize;
+        let mut informative_trigrams = 0;
+        let mut shared_trigrams: Vec<u16> = vec![0; self.len];
+
+        for trigram in &query_trigrams {
+            match self.postings.get(trigram) {
+                Some(positions) if positions.len() > max_frequency => continue,
+                Some(positions) => {
+                    for position in positions {
+                        shared_trigrams[*position] = shared_trigrams[*position].saturating_add(1);
+                    }
+                }
+                None => {}
+            }
+
+            informative_trigrams += 1;
+        }
+
+        if informative_trigrams == 0 {
+            return None;
+        }
+
+        let min_shared = ((informative_trigrams as f64 * MIN_TRIGRAM_OVERLAP).ceil() as u16).max(1);
+
+        Some(
+            shared_trigrams
+                .iter()
+                .enumerate()
+                .filter(|(_, shared)| **shared >= min_shared)
+                .map(|(position, _)| position)
+                .collect(),
+        )
+    }
+}
+
+fn trigrams(padded_word: &str) -> Vec<Trigram> {
+    let chars: Vec<char> = padded_word.chars().collect();
+
+    chars
+        .windows(3)
+        .map(|window| [window[0], window[1], window[2]])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(texts: &[&str]) -> SearchIndex {
+        SearchIndex::build(
+            &texts
+                .iter()
+                .map(|text| Expansion::new(text))
+                .collect::<Vec<Expansion>>(),
+        )
+    }
+
+    #[test]
+    fn candidates_share_trigrams() {
+        let index = index(&[
+            "Nacho Pizza Marinade",
+            "Nail Polish Makeover",
+            "Newts Prefer MS-DOS",
+        ]);
+
+        assert_eq!(index.candidates("Nail Polsh"), Some(vec![1]))
+    }
+
+    #[test]
+    fn candidates_match_word_prefixes() {
+        let index = index(&[
+            "Nacho Pizza Marinade",
+            "Nail Polish Makeover",
+            "Newts Prefer MS-DOS",
+        ]);
+
+        assert_eq!(index.candidates("pref"), Some(vec![2]))
+    }
+
+    #[test]
+    fn candidates_ignore_case() {
+        let index = index(&["Nacho Pizza Marinade", "Nail Polish Makeover"]);
+
+        assert_eq!(index.candidates("PIZZA"), Some(vec![0]))
+    }
+
+    #[test]
+    fn unrelated_query_has_no_candidates() {
+        let index = index(&["Nacho Pizza Marinade", "Nail Polish Makeover"]);
+
+        assert_eq!(index.candidates("zzzz"), Some(vec![]))
+    }
+
+    #[test]
+    fn blank_query_scores_everything() {
+        let index = index(&["Nacho Pizza Marinade", "Nail Polish Makeover"]);
+
+        assert_eq!(index.candidates(" "), None)
+    }
+
+    #[test]
+    fn common_trigrams_score_everything() {
+        let index = index(&[
+            "Nacho Pizza Marinade",
+            "Nacho Portion Monitor",
+            "Nacho Printing Machine",
+        ]);
+
+        assert_eq!(index.candidates("nacho"), None)
+    }
+
+    #[test]
+    fn inserted_expansions_are_candidates() {
+        let mut index = index(&["Nacho Pizza Marinade"]);
+        index.insert(1, "Nail Polish Makeover");
+
+        assert_eq!(index.candidates("polish"), Some(vec![1]))
+    }
+}
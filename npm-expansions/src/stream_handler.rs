@@ -1,136 +1,430 @@
 use crate::default_controller::DefaultController;
-use crate::expansions_model::ExpansionsAccess;
-use crate::http_request::HttpRequest;
+use crate::http_request::{HttpRequest, RequestOptions};
 use crate::http_response::HttpResponse;
+use crate::http_status::HttpStatus;
 use crate::mock_tcp_stream::TcpAddr;
 use crate::npm_expansion_error::{NpmErrorKind, NpmExpansionsError};
 use crate::router::Router;
 use std::{
+    any::Any,
     collections::HashMap,
-    io::{Read, Write},
-    sync::{Arc, RwLock},
+    io::{self, BufRead, BufReader, Read, Write},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_BODY_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEFAULT_MAX_REQUESTS: usize = 100;
+
+/// Options controlling how a connection and the requests sent on it are handled
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::stream_handler::ConnectionOptions;
+/// use std::time::Duration;
+///
+/// let options = ConnectionOptions {
+///     idle_timeout: Duration::from_secs(10),
+///     ..ConnectionOptions::default()
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConnectionOptions {
+    /// The options used to read each request e.g. the body size limit
+    pub request_options: RequestOptions,
+    /// How long a kept alive connection waits for its next request before it is closed. Must not
+    /// be zero. Defaults to 5 seconds.
+    pub idle_timeout: Duration,
+    /// The most requests answered on one connection before it is closed, so that a client cannot
+    /// hold on to a worker thread forever. Defaults to 100.
+    pub max_requests: usize,
+    /// How long each read of a request's status line and headers waits for data. Must not be
+    /// zero. Defaults to 10 seconds.
+    pub header_timeout: Duration,
+    /// How long each read of a request's body waits for data. Must not be zero. Defaults to 10
+    /// seconds.
+    pub body_timeout: Duration,
+    /// How long each write of a response waits for the client to accept data. Must not be zero.
+    /// Defaults to 10 seconds.
+    pub write_timeout: Duration,
+    /// The longest a whole request may take, from its first byte until its response has been
+    /// written, so that a client sending or accepting a little data at a time cannot hold on to a
+    /// worker thread. A request which takes longer to arrive is answered with a 408 response.
+    /// Defaults to 30 seconds.
+    pub request_timeout: Duration,
+    /// Set when the server is shutting down, after which the request being answered on each
+    /// connection is answered with `Connection: close` and no further requests are read.
+    /// Defaults to a flag which is never set.
+    pub shutdown: Arc<AtomicBool>,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        ConnectionOptions {
+            request_options: RequestOptions::default(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_requests: DEFAULT_MAX_REQUESTS,
+            header_timeout: DEFAULT_HEADER_TIMEOUT,
+            body_timeout: DEFAULT_BODY_TIMEOUT,
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
 /// Handles a http stream by building a request object and routing the request to a controller. The controllers response
 /// is then written to the stream. If the produced response is a failure then a failure response
 /// is attempted using the DefaultController i.e. 400, 500, 404 etc. If the fail response
-/// produces a error then the error is returned as this indicates a fatal server error.
+/// produces a error then the error is returned as this indicates a fatal server error. If a
+/// controller panics the request is answered with a 500 response before the panic continues.
+///
+/// Requests are answered in turn until the client closes the connection, a request asks for it
+/// to be closed, it is idle for longer than the idle timeout, the max requests are answered or
+/// the server is shutting down.
+/// A request which is not received before its read timeouts or deadline pass is answered with a
+/// 408 response and the connection is closed. A response which is not written before the write
+/// timeout or the deadline passes closes the connection.
 ///
 /// # Arguments
 ///
 /// * `stream` - An incoming TCP stream
 /// * `router` - A Router object which can route a stream to a controller
-/// * `expansions_model` - A ExpansionsModel which produces NPM expansions and acts as persistent database
 ///
 pub fn handle_connection(
     stream: &mut (impl Read + Write + TcpAddr),
     router: Arc<Router>,
-    expansions_model: Arc<RwLock<dyn ExpansionsAccess>>,
 ) -> Result<(), NpmExpansionsError> {
-    let response = respond_to_request(stream, router, expansions_model);
-
-    if let Err(res) = response {
-        respond_to_request_error(stream, &res)
-    } else {
-        Ok(())
-    }
+    handle_connection_with_options(stream, router, &ConnectionOptions::default())
 }
 
-fn respond_to_request(
+/// Handles a http stream in the same way as [`handle_connection`] using the given options e.g.
+/// a larger body size limit or a shorter idle timeout
+///
+/// # Arguments
+///
+/// * `stream` - An incoming TCP stream
+/// * `router` - A Router object which can route a stream to a controller
+/// * `options` - The options controlling how the connection and its requests are handled
+///
+pub fn handle_connection_with_options(
     stream: &mut (impl Read + Write + TcpAddr),
     router: Arc<Router>,
-    expansions_model: Arc<RwLock<dyn ExpansionsAccess>>,
+    options: &ConnectionOptions,
 ) -> Result<(), NpmExpansionsError> {
-    let request = HttpRequest::build(stream)?;
-    let response = router.route_request(&request, expansions_model)?;
+    let host = stream
+        .peer_addr()
+        .map(|socket_addr| socket_addr.ip().to_string())
+        .unwrap_or("-".to_string());
+
+    let mut reader = BufReader::new(TimeoutStream::new(
+        stream,
+        options.idle_timeout,
+        options.write_timeout,
+    ));
+
+    for request_count in 1..=options.max_requests.max(1) {
+        if options.shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+
+        reader.get_mut().wait_for(options.idle_timeout, None);
+
+        if !has_next_request(&mut reader) {
+            break;
+        }
 
-    log_request(&request, &response);
+        // The request deadline starts once the first byte of the request has arrived
+        reader.get_mut().wait_for(
+            options.header_timeout,
+            Some(Instant::now() + options.request_timeout),
+        );
+
+        let keep_alive = respond_to_request(
+            &mut reader,
+            &host,
+            router.clone(),
+            options,
+            request_count < options.max_requests,
+        )?;
+
+        if !keep_alive {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Answers a connection with a 503 response without reading its request. Used when the server is
+/// too busy to handle the connection so that the client can retry later instead of waiting.
+///
+/// # Arguments
+///
+/// * `stream` - An incoming TCP stream
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::{mock_tcp_stream::MockTcpStream, stream_handler};
+///
+/// let mut stream = MockTcpStream {
+///     read_data: Vec::new(),
+///     write_data: Vec::new(),
+/// };
+///
+/// stream_handler::reject_connection(&mut stream).unwrap();
+///
+/// assert!(stream.write_data.starts_with(b"HTTP/1.1 503 Service Unavailable"));
+/// ```
+///
+/// # Failures
+///
+/// The function fails if the response cannot be written to the stream
+pub fn reject_connection(stream: &mut impl Write) -> Result<(), NpmExpansionsError> {
+    let response = HttpResponse::new(HttpStatus::ServiceUnavailable)
+        .with_header("Retry-After", "1")
+        .with_header("Connection", "close")
+        .text("SERVICE UNAVAILABLE");
 
     stream
         .write_all(response.into_bytes_vec().as_slice())
         .or(Err(NpmExpansionsError::from(
             NpmErrorKind::InternalServerError,
-        )))?;
+        )))
+}
 
-    Ok(())
+/// A stream whose reads and writes each wait at most their timeout and fail once the deadline
+/// has passed, so that a client sending a request or accepting its response slowly cannot hold
+/// on to a worker thread. The socket timeouts are only set when they change.
+struct TimeoutStream<'a, S> {
+    stream: &'a mut S,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    deadline: Option<Instant>,
+    socket_read_timeout: Option<Duration>,
+    socket_write_timeout: Option<Duration>,
 }
 
-fn respond_to_request_error(
-    stream: &mut (impl Read + Write + TcpAddr),
-    error: &NpmExpansionsError,
-) -> Result<(), NpmExpansionsError> {
-    let http_request = HttpRequest::build(stream).unwrap_or_else(|_error| {
-        HttpRequest::new(
-            "",
-            "",
-            HashMap::from([(
-                "Accept".to_string(),
-                "text/html,application/json".to_string(),
-            )]),
-            HashMap::new(),
-        )
+impl<'a, S: TcpAddr> TimeoutStream<'a, S> {
+    fn new(
+        stream: &'a mut S,
+        read_timeout: Duration,
+        write_timeout: Duration,
+    ) -> TimeoutStream<'a, S> {
+        TimeoutStream {
+            stream,
+            read_timeout,
+            write_timeout,
+            deadline: None,
+            socket_read_timeout: None,
+            socket_write_timeout: None,
+        }
+    }
+
+    /// Sets how long each following read waits for data and the deadline after which reads and
+    /// writes fail
+    fn wait_for(&mut self, read_timeout: Duration, deadline: Option<Instant>) {
+        self.read_timeout = read_timeout;
+        self.deadline = deadline;
+    }
+
+    /// Returns the given timeout shortened to the time left before the deadline, failing if the
+    /// deadline has passed
+    fn remaining(&self, timeout: Duration) -> io::Result<Duration> {
+        let timeout = match self.deadline {
+            Some(deadline) => deadline
+                .saturating_duration_since(Instant::now())
+                .min(timeout),
+            None => timeout,
+        };
+
+        if timeout.is_zero() {
+            return Err(io::Error::from(io::ErrorKind::TimedOut));
+        }
+
+        Ok(timeout)
+    }
+}
+
+impl<S: Read + TcpAddr> Read for TimeoutStream<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = Some(self.remaining(self.read_timeout)?);
+
+        if self.socket_read_timeout != timeout {
+            self.stream.set_read_timeout(timeout)?;
+            self.socket_read_timeout = timeout;
+        }
+
+        self.stream.read(buf)
+    }
+}
+
+impl<S: Write + TcpAddr> Write for TimeoutStream<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let timeout = Some(self.remaining(self.write_timeout)?);
+
+        if self.socket_write_timeout != timeout {
+            self.stream.set_write_timeout(timeout)?;
+            self.socket_write_timeout = timeout;
+        }
+
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Returns false if the client closed the connection or sent nothing before the idle timeout
+fn has_next_request(reader: &mut impl BufRead) -> bool {
+    reader.fill_buf().is_ok_and(|buffer| !buffer.is_empty())
+}
+
+/// Reads, routes and answers a single request. Returns true if the connection should be kept
+/// open for another request.
+fn respond_to_request(
+    reader: &mut BufReader<TimeoutStream<impl Read + Write + TcpAddr>>,
+    host: &str,
+    router: Arc<Router>,
+    options: &ConnectionOptions,
+    keep_alive_allowed: bool,
+) -> Result<bool, NpmExpansionsError> {
+    let request = HttpRequest::read_head(reader, host).and_then(|request| {
+        reader.get_mut().read_timeout = options.body_timeout;
+        request.read_body(reader, &options.request_options)
     });
 
-    let error_response = match error.kind() {
-        NpmErrorKind::InvalidRequestHeaders => DefaultController::client_error(&http_request),
-        NpmErrorKind::TooManyRequestHeaders => DefaultController::client_error(&http_request),
-        NpmErrorKind::InternalServerError => {
-            DefaultController::internal_server_error(&http_request)
+    let (response, keep_alive) = match request {
+        Ok(request) => {
+            let routed = panic::catch_unwind(AssertUnwindSafe(|| router.route_request(&request)));
+
+            let response = match routed {
+                Ok(response) => response?,
+                Err(panic) => respond_to_panic(reader, &router, &request, panic),
+            };
+            // The flag is read after routing so that a shutdown during the request closes it
+            let keep_alive = keep_alive_allowed
+                && request.keep_alive()
+                && !options.shutdown.load(Ordering::SeqCst);
+
+            (response, keep_alive)
         }
-        NpmErrorKind::InvalidHttpRequest => DefaultController::internal_server_error(&http_request),
-        NpmErrorKind::SupportedMimeTypeError => {
-            DefaultController::internal_server_error(&http_request)
+        // Whatever is left of a request which could not be read is still on the stream, so
+        // the connection is closed rather than reading it as the next request
+        Err(error) => {
+            // The deadline may be why the request could not be read, so only the write timeout
+            // limits writing the response before the connection is closed
+            reader.get_mut().wait_for(options.header_timeout, None);
+
+            let request = fallback_request(host);
+            let response = DefaultController::from_error(&error);
+            let response = router.finish_response(&request, response)?;
+
+            (response, false)
         }
-        NpmErrorKind::InvalidRequestMimeType => DefaultController::client_error(&http_request),
-        NpmErrorKind::RouteNotFound => DefaultController::not_found(&http_request),
-    }?;
+    };
+
+    let response = if keep_alive {
+        response
+            .with_header("Connection", "keep-alive")
+            .with_header(
+                "Keep-Alive",
+                &format!("timeout={}", options.idle_timeout.as_secs()),
+            )
+    } else {
+        response.with_header("Connection", "close")
+    };
 
-    log_request(&http_request, &error_response);
+    write_response(reader, &response)?;
 
-    stream
-        .write_all(error_response.into_bytes_vec().as_slice())
+    Ok(keep_alive)
+}
+
+/// Answers a request whose controller panicked with a 500 response and closes the connection by
+/// continuing the panic, which the thread pool then catches and counts
+fn respond_to_panic(
+    reader: &mut BufReader<impl Read + Write>,
+    router: &Router,
+    request: &HttpRequest,
+    panic: Box<dyn Any + Send>,
+) -> ! {
+    let error = NpmExpansionsError::from(NpmErrorKind::InternalServerError);
+    let response = router.finish_response(request, DefaultController::from_error(&error));
+
+    if let Ok(response) = response {
+        let _ = write_response(reader, &response.with_header("Connection", "close"));
+    }
+
+    panic::resume_unwind(panic)
+}
+
+fn write_response(
+    reader: &mut BufReader<impl Read + Write>,
+    response: &HttpResponse,
+) -> Result<(), NpmExpansionsError> {
+    reader
+        .get_mut()
+        .write_all(response.into_bytes_vec().as_slice())
         .or(Err(NpmExpansionsError::from(
             NpmErrorKind::InternalServerError,
-        )))?;
-
-    Ok(())
+        )))
 }
 
-fn log_request(request: &HttpRequest, response: &HttpResponse) {
-    let referer = request
-        .headers()
-        .get("Referer")
-        .or_else(|| request.headers().get("referer"));
-    let user_agent = request
-        .headers()
-        .get("User-Agent")
-        .or_else(|| request.headers().get("user-agent"));
-
-    println!(
-        "{} - - [{}] \"{}\" {} {} \"{}\" \"{}\"",
-        request.host(),
-        chrono::Utc::now().format("%d/%b/%Y:%H:%M:%S %z"),
-        request.status_line(),
-        response.status_code(),
-        response.into_bytes_vec().len(),
-        referer.unwrap_or(&"-".to_string()),
-        user_agent.unwrap_or(&"-".to_string()),
-    );
+/// The request used to answer a request which could not be read. It accepts any mime type as
+/// the headers of the unread request are unknown.
+fn fallback_request(host: &str) -> HttpRequest {
+    HttpRequest::new(
+        host,
+        "",
+        HashMap::from([("Accept".to_string(), "*/*".to_string())]),
+        HashMap::new(),
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::app_state::AppState;
+    use crate::expansions_model::ExpansionsAccess;
     use crate::mock_expansions_model::MockExpansionsModel;
     use crate::mock_tcp_stream::MockTcpStream;
-    use crate::npm_controller::{ControllerFunction, NpmController};
+    use crate::npm_controller::NpmController;
+    use crate::router::{controller, handler};
+    use std::sync::RwLock;
+
+    fn router() -> Arc<Router> {
+        let expansions_model: Arc<RwLock<dyn ExpansionsAccess>> =
+            Arc::new(RwLock::new(MockExpansionsModel::default()));
+
+        Arc::new(
+            Router::new(HashMap::from([(
+                "GET / HTTP/1.1",
+                controller(NpmController::random),
+            )]))
+            .with_state(AppState::new().with(expansions_model)),
+        )
+    }
 
     mod respond_to_request {
         use super::*;
 
-        #[test]
-        fn valid_http_stream() {
-            let input_bytes = b"GET / HTTP/1.1\r\nAccept: text/html\r\n\r\n";
+        fn respond(input_bytes: &[u8]) -> Result<bool, NpmExpansionsError> {
             let mut contents = vec![0u8; 1024];
 
             contents[..input_bytes.len()].clone_from_slice(input_bytes);
@@ -140,116 +434,410 @@ mod tests {
                 write_data: Vec::new(),
             };
 
-            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            respond_to_request(
+                &mut BufReader::new(TimeoutStream::new(
+                    &mut stream,
+                    Duration::from_secs(5),
+                    Duration::from_secs(5),
+                )),
+                "127.0.0.1",
+                router(),
+                &ConnectionOptions::default(),
+                true,
+            )
+        }
 
-            let router = Arc::new(Router::new(HashMap::from([(
-                "GET / HTTP/1.1",
-                NpmController::random as ControllerFunction,
-            )])));
+        #[test]
+        fn valid_http_stream() {
+            let response = respond(b"GET / HTTP/1.1\r\nAccept: text/html\r\n\r\n");
+
+            assert!(response.unwrap());
+        }
 
-            let response = respond_to_request(&mut stream, router, mock_expansions_model);
+        #[test]
+        fn no_not_found_route() {
+            let response =
+                respond(b"GET /non-existant/route HTTP/1.1\r\nAccept: text/html\r\n\r\n");
 
             assert!(response.is_ok());
         }
 
         #[test]
-        fn no_not_found_route() {
-            let input_bytes = b"GET /non-existant/route HTTP/1.1\r\nAccept: text/html\r\n\r\n";
+        fn invalid_request_closes_connection() {
+            let response = respond(b"");
+
+            assert!(!response.unwrap());
+        }
+    }
+
+    mod connection_handler {
+        use super::*;
+        use std::cell::Cell;
+        use test_case::test_case;
+
+        fn stream(input_bytes: &[u8]) -> MockTcpStream {
             let mut contents = vec![0u8; 1024];
 
             contents[..input_bytes.len()].clone_from_slice(input_bytes);
 
-            let mut stream = MockTcpStream {
+            MockTcpStream {
                 read_data: contents,
                 write_data: Vec::new(),
-            };
+            }
+        }
 
-            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+        /// A stream which repeats the given requests for as long as it is read
+        fn repeating_stream(input_bytes: &[u8]) -> MockTcpStream {
+            MockTcpStream {
+                read_data: input_bytes.to_vec(),
+                write_data: Vec::new(),
+            }
+        }
 
-            let router = Arc::new(Router::new(HashMap::from([(
-                "GET / HTTP/1.1",
-                NpmController::random as ControllerFunction,
-            )])));
+        fn responses(stream: &MockTcpStream) -> Vec<String> {
+            String::from_utf8_lossy(&stream.write_data)
+                .split("HTTP/1.1 ")
+                .skip(1)
+                .map(|response| response.to_string())
+                .collect()
+        }
 
-            let response = respond_to_request(&mut stream, router, mock_expansions_model);
+        fn handle(stream: &mut MockTcpStream, options: &ConnectionOptions) {
+            handle_connection_with_options(stream, router(), options).unwrap();
+        }
+
+        #[test]
+        fn valid_http_stream() {
+            let mut stream = stream(b"GET / HTTP/1.1\r\nAccept: text/html\r\n\r\n");
+
+            let response = handle_connection(&mut stream, router());
 
             assert!(response.is_ok());
         }
 
         #[test]
-        fn invalid_request() {
-            let input_bytes = b"";
-            let mut contents = vec![0u8; 1024];
+        fn invalid_http_stream() {
+            let mut stream = stream(b"");
 
-            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+            let response = handle_connection(&mut stream, router());
 
-            let mut stream = MockTcpStream {
-                read_data: contents,
-                write_data: Vec::new(),
-            };
+            assert!(response.is_ok());
+        }
 
-            let router = Arc::new(Router::new(HashMap::from([(
-                "GET / HTTP/1.1",
-                NpmController::random as ControllerFunction,
-            )])));
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nhello\r\n0\r\n\r\n"; "bad chunk size")]
+        #[test_case(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello"; "conflicting content lengths")]
+        #[test_case(b"POST / HTTP/1.1\r\nContent-Length: abc\r\n\r\nhello"; "invalid content length")]
+        #[test_case(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n5\r\nhello\r\n0\r\n\r\n"; "chunked with content length")]
+        fn malformed_body_is_client_error(input_bytes: &[u8]) {
+            let mut stream = stream(input_bytes);
 
-            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            handle(&mut stream, &ConnectionOptions::default());
 
-            let response = respond_to_request(&mut stream, router, mock_expansions_model);
+            let responses = responses(&stream);
 
-            assert!(response.is_err());
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].starts_with("400 Bad Request"));
+            assert!(responses[0].contains("Connection: close"));
         }
-    }
 
-    mod connection_handler {
-        use super::*;
+        #[test]
+        fn invalid_query_param_is_client_error() {
+            let mut stream =
+                stream(b"GET /?query=%FF HTTP/1.1\r\nAccept: application/json\r\n\r\n");
+
+            handle(&mut stream, &ConnectionOptions::default());
+
+            let responses = responses(&stream);
+
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].starts_with("400 Bad Request"));
+        }
 
         #[test]
-        fn valid_http_stream() {
-            let input_bytes = b"GET / HTTP/1.1\r\nAccept: text/html\r\n\r\n";
-            let mut contents = vec![0u8; 1024];
+        fn body_too_large() {
+            let mut stream = stream(
+                b"POST / HTTP/1.1\r\nAccept: text/plain\r\nContent-Length: 11\r\n\r\nhello world",
+            );
+
+            handle(
+                &mut stream,
+                &ConnectionOptions {
+                    request_options: RequestOptions { body_size_limit: 5 },
+                    ..ConnectionOptions::default()
+                },
+            );
+
+            let responses = responses(&stream);
+
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].starts_with("413"));
+            assert!(responses[0].contains("Connection: close"));
+        }
 
-            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+        #[test]
+        fn keeps_connection_alive_until_max_requests() {
+            let mut stream =
+                repeating_stream(b"GET / HTTP/1.1\r\nAccept: application/json\r\n\r\n");
+
+            handle(
+                &mut stream,
+                &ConnectionOptions {
+                    max_requests: 3,
+                    ..ConnectionOptions::default()
+                },
+            );
+
+            let responses = responses(&stream);
+
+            assert_eq!(responses.len(), 3);
+            assert!(responses[0].starts_with("200"));
+            assert!(responses[0].contains("Connection: keep-alive\r\nKeep-Alive: timeout=5"));
+            assert!(responses[2].contains("Connection: close"));
+        }
 
-            let mut stream = MockTcpStream {
-                read_data: contents,
-                write_data: Vec::new(),
-            };
+        #[test]
+        fn closes_connection_when_asked() {
+            let mut stream = repeating_stream(
+                b"GET / HTTP/1.1\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+            );
 
-            let router = Arc::new(Router::new(HashMap::from([(
-                "GET / HTTP/1.1",
-                NpmController::random as ControllerFunction,
-            )])));
+            handle(&mut stream, &ConnectionOptions::default());
+
+            let responses = responses(&stream);
 
-            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].contains("Connection: close"));
+        }
+
+        #[test]
+        fn http_1_0_closes_connection_by_default() {
+            let mut stream =
+                repeating_stream(b"GET / HTTP/1.0\r\nAccept: application/json\r\n\r\n");
 
-            let response = handle_connection(&mut stream, router, mock_expansions_model);
+            handle(&mut stream, &ConnectionOptions::default());
 
-            assert!(response.is_ok());
+            assert_eq!(responses(&stream).len(), 1);
         }
 
         #[test]
-        fn invalid_http_stream() {
-            let input_bytes = b"";
-            let mut contents = vec![0u8; 1024];
+        fn http_1_0_keep_alive() {
+            let mut stream = repeating_stream(
+                b"GET / HTTP/1.0\r\nAccept: application/json\r\nConnection: keep-alive\r\n\r\n",
+            );
+
+            handle(
+                &mut stream,
+                &ConnectionOptions {
+                    max_requests: 2,
+                    ..ConnectionOptions::default()
+                },
+            );
+
+            assert_eq!(responses(&stream).len(), 2);
+        }
 
-            contents[..input_bytes.len()].clone_from_slice(input_bytes);
+        #[test]
+        fn closes_connection_once_shutting_down() {
+            let mut stream = stream(
+                b"GET / HTTP/1.1\r\nAccept: application/json\r\n\r\nGET / HTTP/1.1\r\nAccept: application/json\r\n\r\n",
+            );
+            let options = ConnectionOptions::default();
+            let shutdown = options.shutdown.clone();
+            // The flag is set while the first of the two pipelined requests is being answered
+            let router = Arc::new(Router::new(HashMap::from([(
+                "GET / HTTP/1.1",
+                handler(move |_, _| {
+                    shutdown.store(true, Ordering::SeqCst);
+                    Ok(HttpResponse::new(HttpStatus::Ok).with_body("OK"))
+                }),
+            )])));
 
-            let mut stream = MockTcpStream {
-                read_data: contents,
-                write_data: Vec::new(),
-            };
+            handle_connection_with_options(&mut stream, router, &options).unwrap();
+
+            let responses = responses(&stream);
 
-            let mock_expansions_model = Arc::new(RwLock::new(MockExpansionsModel::default()));
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].starts_with("200"));
+            assert!(responses[0].contains("Connection: close"));
+        }
 
+        #[test]
+        fn panicking_controller_responds_with_500() {
+            let mut stream =
+                repeating_stream(b"GET / HTTP/1.1\r\nAccept: application/json\r\n\r\n");
             let router = Arc::new(Router::new(HashMap::from([(
                 "GET / HTTP/1.1",
-                NpmController::random as ControllerFunction,
+                handler(|_, _| panic!("The controller panicked")),
             )])));
 
-            let response = handle_connection(&mut stream, router, mock_expansions_model);
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| handle_connection(&mut stream, router)));
 
-            assert!(response.is_ok());
+            let responses = responses(&stream);
+
+            assert!(result.is_err());
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].starts_with("500"));
+            assert!(responses[0].contains("Connection: close"));
+        }
+
+        /// A client which sends its request a byte at a time, waiting between each, then stops
+        /// sending as if its socket read timed out
+        struct SlowStream {
+            read_data: Vec<u8>,
+            position: usize,
+            delay: Duration,
+            write_data: Vec<u8>,
+            read_timeouts: Cell<usize>,
+            write_timeouts: Cell<usize>,
+        }
+
+        impl SlowStream {
+            fn new(input_bytes: &[u8], delay: Duration) -> SlowStream {
+                SlowStream {
+                    read_data: input_bytes.to_vec(),
+                    position: 0,
+                    delay,
+                    write_data: Vec::new(),
+                    read_timeouts: Cell::new(0),
+                    write_timeouts: Cell::new(0),
+                }
+            }
+        }
+
+        impl Read for SlowStream {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.position == self.read_data.len() || buf.is_empty() {
+                    return Err(io::Error::from(io::ErrorKind::WouldBlock));
+                }
+
+                std::thread::sleep(self.delay);
+                buf[0] = self.read_data[self.position];
+                self.position += 1;
+
+                Ok(1)
+            }
+        }
+
+        impl Write for SlowStream {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.write_data.extend_from_slice(buf);
+
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl TcpAddr for SlowStream {
+            fn peer_addr(&self) -> io::Result<std::net::SocketAddr> {
+                Ok(([127, 0, 0, 1], 8080).into())
+            }
+
+            fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+                self.read_timeouts.set(self.read_timeouts.get() + 1);
+
+                Ok(())
+            }
+
+            fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+                self.write_timeouts.set(self.write_timeouts.get() + 1);
+
+                Ok(())
+            }
+        }
+
+        fn handle_slow(stream: &mut SlowStream, options: &ConnectionOptions) -> Vec<String> {
+            handle_connection_with_options(stream, router(), options).unwrap();
+
+            String::from_utf8_lossy(&stream.write_data)
+                .split("HTTP/1.1 ")
+                .skip(1)
+                .map(|response| response.to_string())
+                .collect()
+        }
+
+        #[test]
+        fn request_slower_than_deadline_times_out() {
+            let mut stream = SlowStream::new(
+                b"GET / HTTP/1.1\r\nAccept: text/html\r\n\r\n",
+                Duration::from_millis(5),
+            );
+
+            let responses = handle_slow(
+                &mut stream,
+                &ConnectionOptions {
+                    request_timeout: Duration::from_millis(50),
+                    ..ConnectionOptions::default()
+                },
+            );
+
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].starts_with("408 Request Timeout"));
+            assert!(responses[0].contains("Connection: close"));
+            assert!(stream.position < stream.read_data.len());
+        }
+
+        #[test]
+        fn stalled_headers_time_out() {
+            let mut stream = SlowStream::new(b"GET / HTTP/1.1\r\nAccept: te", Duration::ZERO);
+
+            let responses = handle_slow(&mut stream, &ConnectionOptions::default());
+
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].starts_with("408 Request Timeout"));
+        }
+
+        #[test]
+        fn request_within_deadline_is_answered() {
+            let mut stream = SlowStream::new(
+                b"GET / HTTP/1.1\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+                Duration::ZERO,
+            );
+
+            let responses = handle_slow(&mut stream, &ConnectionOptions::default());
+
+            assert_eq!(responses.len(), 1);
+            assert!(responses[0].starts_with("200 OK"));
+        }
+
+        #[test]
+        fn socket_timeouts_are_only_set_when_changed() {
+            let mut stream = SlowStream::new(
+                b"GET / HTTP/1.1\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+                Duration::ZERO,
+            );
+
+            handle_slow(&mut stream, &ConnectionOptions::default());
+
+            // Once for the idle timeout and once for the header timeout
+            assert_eq!(stream.read_timeouts.get(), 2);
+            assert_eq!(stream.write_timeouts.get(), 1);
+        }
+
+        #[test]
+        fn write_after_deadline_times_out() {
+            let mut stream = SlowStream::new(b"", Duration::ZERO);
+            let mut timeout_stream =
+                TimeoutStream::new(&mut stream, Duration::from_secs(5), Duration::from_secs(5));
+
+            timeout_stream.wait_for(Duration::from_secs(5), Some(Instant::now()));
+
+            let error = timeout_stream.write(b"HTTP/1.1 200 OK").unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+            assert!(stream.write_data.is_empty());
+        }
+
+        #[test]
+        fn closed_connection_is_not_answered() {
+            let mut stream = repeating_stream(b"");
+
+            handle(&mut stream, &ConnectionOptions::default());
+
+            assert!(stream.write_data.is_empty());
         }
     }
 }
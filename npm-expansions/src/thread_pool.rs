@@ -1,39 +1,106 @@
 use std::io::Error;
 use std::{
+    panic::{self, AssertUnwindSafe},
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{self, SendError},
         Arc, Mutex,
     },
     thread,
+    time::{Duration, Instant},
 };
 
+/// The number of jobs per worker which can wait in the queue of a pool made with `new`
+const DEFAULT_QUEUE_SIZE_PER_WORKER: usize = 16;
+
+/// How often dropping a pool with a shutdown timeout checks whether its workers have finished
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 /// A ThreadPool represented as workers which instantiate new threads to execute clojured provided by the sender as Jobs
+///
+/// Jobs wait in a bounded queue until a worker is free. A job which panics does not take its
+/// worker down with it, instead the panic is counted and the worker moves on to the next job.
+/// Dropping the pool stops it accepting jobs and waits for the queued and running jobs to finish,
+/// or for the shutdown timeout if one is set.
+///
+/// # Examples
+///
+/// ```
+/// use npm_expansions::thread_pool::ThreadPool;
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+///
+/// let pool = ThreadPool::with_queue_size(2, 8).with_shutdown_timeout(Duration::from_secs(5));
+/// let (sender, receiver) = mpsc::channel();
+///
+/// for job in 0..4 {
+///     let sender = sender.clone();
+///     pool.execute(move || sender.send(job).unwrap()).unwrap();
+/// }
+///
+/// drop(pool);
+///
+/// let mut finished: Vec<i32> = receiver.try_iter().collect();
+/// finished.sort();
+/// assert_eq!(finished, [0, 1, 2, 3]);
+/// ```
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    workers: Mutex<Vec<Worker>>,
+    sender: Option<mpsc::SyncSender<Job>>,
+    state: Arc<PoolState>,
+    queue_size: usize,
+    shutdown_timeout: Option<Duration>,
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/// The parts of a pool shared with its workers
+struct PoolState {
+    receiver: Mutex<mpsc::Receiver<Job>>,
+    queued: AtomicUsize,
+    panics: AtomicUsize,
+}
+
 impl ThreadPool {
     /// Create a new ThreadPool.
     ///
-    /// The size is the number of threads in the pool.
+    /// The size is the number of threads in the pool. Up to 16 jobs per thread can wait in the
+    /// queue for a free thread.
     ///
     /// # Panics
     ///
     /// The `new` function will panic if the size is zero.
     pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::with_queue_size(size, size * DEFAULT_QUEUE_SIZE_PER_WORKER)
+    }
+
+    /// Create a new ThreadPool whose queue holds at most the given number of jobs waiting for a
+    /// free thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The number of threads in the pool
+    /// * `queue_size` - The number of jobs which can wait for a free thread
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if the size or queue size is zero.
+    pub fn with_queue_size(size: usize, queue_size: usize) -> ThreadPool {
         assert!(size > 0);
+        assert!(queue_size > 0);
 
-        let (sender, receiver) = mpsc::channel();
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
 
-        let receiver = Arc::new(Mutex::new(receiver));
+        let state = Arc::new(PoolState {
+            receiver: Mutex::new(receiver),
+            queued: AtomicUsize::new(0),
+            panics: AtomicUsize::new(0),
+        });
 
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            if let Ok(worker) = Worker::new(id, Arc::clone(&receiver)) {
+            if let Ok(worker) = Worker::new(id, Arc::clone(&state)) {
                 workers.push(worker);
             } else {
                 println!("Failed to create new worker")
@@ -41,20 +108,39 @@ impl ThreadPool {
         }
 
         ThreadPool {
-            workers,
+            workers: Mutex::new(workers),
             sender: Some(sender),
+            state,
+            queue_size,
+            shutdown_timeout: None,
         }
     }
 
-    /// Takes a clojure and executes it using workers from the ThreadPool
+    /// Returns the pool with a limit on how long dropping it waits for queued and running jobs
+    /// to finish. Workers still busy after the timeout are left to be stopped by the process
+    /// exiting. Without a timeout dropping the pool waits for every job.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> ThreadPool {
+        self.shutdown_timeout = Some(shutdown_timeout);
+        self
+    }
+
+    /// Takes a clojure and executes it using workers from the ThreadPool. When the queue is full
+    /// the function blocks until a worker takes a job from it. Any worker whose thread has died
+    /// is replaced before the job is queued.
     pub fn execute<F>(&self, f: F) -> Result<(), SendError<Box<dyn FnOnce() + Send + 'static>>>
     where
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
 
+        self.respawn_dead_workers();
+
         if let Some(sender) = self.sender.as_ref() {
-            sender.send(job)?;
+            self.state.queued.fetch_add(1, Ordering::SeqCst);
+
+            sender.send(job).inspect_err(|_| {
+                self.state.queued.fetch_sub(1, Ordering::SeqCst);
+            })?;
 
             Ok(())
         } else {
@@ -63,37 +149,143 @@ impl ThreadPool {
             Ok(())
         }
     }
-}
 
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
+    /// Returns true if the queue is full so that executing another job would block until a
+    /// worker is free. Callers can use this to turn work away instead of waiting.
+    pub fn is_full(&self) -> bool {
+        self.state.queued.load(Ordering::SeqCst) >= self.queue_size
+    }
+
+    /// Returns the number of jobs which have panicked since the pool was created
+    pub fn panic_count(&self) -> usize {
+        self.state.panics.load(Ordering::SeqCst)
+    }
+
+    fn respawn_dead_workers(&self) {
+        let mut workers = self
+            .workers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        for worker in workers.iter_mut() {
+            if !worker
+                .thread
+                .as_ref()
+                .is_some_and(|thread| thread.is_finished())
+            {
+                continue;
+            }
+
+            match Worker::new(worker.id, Arc::clone(&self.state)) {
+                Ok(new_worker) => {
+                    println!("Worker {} died and has been respawned", worker.id);
+                    *worker = new_worker;
+                }
+                Err(spawn_err) => println!(
+                    "Failed to respawn worker {}. Error Message: {}",
+                    worker.id, spawn_err
+                ),
+            }
+        }
+    }
+
+    /// Stops the pool in the same way as dropping it and returns the number of jobs which have
+    /// panicked, including those which panicked while the pool waited for them to finish
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use npm_expansions::thread_pool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(1);
+    ///
+    /// pool.execute(|| panic!("A job panicked")).unwrap();
+    ///
+    /// assert_eq!(pool.shutdown(), 1);
+    /// ```
+    pub fn shutdown(mut self) -> usize {
+        self.stop();
+        self.panic_count()
+    }
+
+    /// Stops accepting jobs and waits for the queued and running jobs to finish, or for the
+    /// shutdown timeout if one is set. Stopping a pool again does nothing.
+    fn stop(&mut self) {
         drop(self.sender.take());
 
-        for worker in &mut self.workers {
+        let deadline = self
+            .shutdown_timeout
+            .map(|shutdown_timeout| Instant::now() + shutdown_timeout);
+
+        let workers = self
+            .workers
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(deadline) = deadline {
+            while Instant::now() < deadline
+                && workers.iter().any(|worker| {
+                    worker
+                        .thread
+                        .as_ref()
+                        .is_some_and(|thread| !thread.is_finished())
+                })
+            {
+                thread::sleep(SHUTDOWN_POLL_INTERVAL);
+            }
+        }
+
+        for worker in workers.iter_mut() {
             if let Some(thread) = worker.thread.take() {
-                thread
-                    .join()
-                    .expect("Failed to call join on worker thread while dropping");
+                if deadline.is_some() && !thread.is_finished() {
+                    println!(
+                        "Worker {} did not finish before the shutdown timeout",
+                        worker.id
+                    );
+                    continue;
+                }
+
+                if thread.join().is_err() {
+                    println!("Worker {} panicked while shutting down", worker.id);
+                }
             }
         }
     }
 }
 
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
 struct Worker {
-    _id: usize,
+    id: usize,
     thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Result<Worker, Error> {
+    fn new(id: usize, state: Arc<PoolState>) -> Result<Worker, Error> {
         let builder = thread::Builder::new();
 
         let thread = builder.spawn(move || loop {
-            let message = receiver.lock().unwrap().recv();
+            let message = state
+                .receiver
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .recv();
 
             match message {
                 Ok(job) => {
-                    job();
+                    state.queued.fetch_sub(1, Ordering::SeqCst);
+
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        let panics = state.panics.fetch_add(1, Ordering::SeqCst) + 1;
+                        println!(
+                            "Worker {} recovered from a panicking job. Panics so far: {}",
+                            id, panics
+                        );
+                    }
                 }
                 Err(_) => {
                     break;
@@ -102,8 +294,174 @@ impl Worker {
         });
 
         Ok(Worker {
-            _id: id,
+            id,
             thread: Some(thread?),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{atomic::AtomicBool, Barrier};
+
+    #[test]
+    fn drop_waits_for_queued_jobs() {
+        let pool = ThreadPool::new(2);
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..10 {
+            let finished = finished.clone();
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(5));
+                finished.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        }
+
+        drop(pool);
+
+        assert_eq!(finished.load(Ordering::SeqCst), 10)
+    }
+
+    #[test]
+    fn full_when_workers_are_busy() {
+        let pool = ThreadPool::with_queue_size(1, 1);
+        let started = Arc::new(Barrier::new(2));
+        let release = Arc::new(AtomicBool::new(false));
+
+        let (job_started, job_release) = (started.clone(), release.clone());
+        pool.execute(move || {
+            job_started.wait();
+            while !job_release.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(1));
+            }
+        })
+        .unwrap();
+        started.wait();
+
+        assert!(!pool.is_full());
+
+        pool.execute(|| {}).unwrap();
+
+        assert!(pool.is_full());
+
+        release.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn panicking_job_does_not_stop_worker() {
+        let pool = ThreadPool::new(1);
+        let (sender, receiver) = mpsc::channel();
+
+        pool.execute(|| panic!("A job panicked")).unwrap();
+        pool.execute(move || sender.send("finished").unwrap())
+            .unwrap();
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok("finished")
+        );
+        assert_eq!(pool.panic_count(), 1)
+    }
+
+    #[test]
+    fn shutdown_counts_panics_while_draining() {
+        let pool = ThreadPool::new(1);
+        let (release_sender, release_receiver) = mpsc::channel::<()>();
+
+        pool.execute(move || release_receiver.recv().unwrap())
+            .unwrap();
+        pool.execute(|| panic!("A queued job panicked")).unwrap();
+
+        assert_eq!(pool.panic_count(), 0);
+
+        release_sender.send(()).unwrap();
+
+        assert_eq!(pool.shutdown(), 1)
+    }
+
+    #[test]
+    fn recovers_from_poisoned_receiver() {
+        let pool = ThreadPool::new(1);
+        let (release_sender, release_receiver) = mpsc::channel::<()>();
+
+        // The worker only holds the receiver lock while waiting for a job, so keep it busy while
+        // another thread poisons the lock
+        pool.execute(move || release_receiver.recv().unwrap())
+            .unwrap();
+
+        let state = Arc::clone(&pool.state);
+        let _ = thread::spawn(move || {
+            let _guard = state.receiver.lock().unwrap();
+            panic!("Poisoning the receiver");
+        })
+        .join();
+
+        release_sender.send(()).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || sender.send("finished").unwrap())
+            .unwrap();
+
+        assert!(pool.state.receiver.is_poisoned());
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok("finished")
+        )
+    }
+
+    #[test]
+    fn respawns_dead_workers() {
+        let pool = ThreadPool::new(1);
+        let dead_worker = Worker {
+            id: 0,
+            thread: Some(thread::spawn(|| {})),
+        };
+
+        while !dead_worker.thread.as_ref().unwrap().is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // Replace the live worker with a finished one, leaving the live worker to exit on drop
+        let live_worker = std::mem::replace(&mut pool.workers.lock().unwrap()[0], dead_worker);
+
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || sender.send("finished").unwrap())
+            .unwrap();
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok("finished")
+        );
+        assert!(!pool.workers.lock().unwrap()[0]
+            .thread
+            .as_ref()
+            .unwrap()
+            .is_finished());
+
+        drop(pool);
+        live_worker.thread.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn drop_stops_waiting_after_shutdown_timeout() {
+        let pool = ThreadPool::new(1).with_shutdown_timeout(Duration::from_millis(20));
+        let release = Arc::new(AtomicBool::new(false));
+
+        let job_release = release.clone();
+        pool.execute(move || {
+            while !job_release.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(1));
+            }
+        })
+        .unwrap();
+
+        let dropped_at = Instant::now();
+        drop(pool);
+
+        assert!(dropped_at.elapsed() < Duration::from_secs(1));
+
+        release.store(true, Ordering::SeqCst);
+    }
+}